@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use crate::index::{Index, IndexEntry, Stat};
+use crate::lockfile::LockFile;
+use crate::object::ObjectKind;
+use crate::quote::quote_path;
+use crate::repo::Repository;
+use crate::worktree::{self, pathspec_matches};
+
+pub struct Add;
+
+impl Add {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: add [-A | -u] [-N] [-n] [-v] [--] <pathspec>...";
+        let mut all = false;
+        let mut update = false;
+        let mut intent_to_add = false;
+        let mut dry_run = false;
+        let mut verbose = false;
+        let mut pathspecs = Vec::new();
+        let mut options_done = false;
+
+        for arg in args {
+            match arg.as_str() {
+                _ if options_done || !arg.starts_with('-') => pathspecs.push(arg.as_str()),
+                "--" => options_done = true,
+                "-A" | "--all" => all = true,
+                "-u" | "--update" => update = true,
+                "-N" | "--intent-to-add" => intent_to_add = true,
+                "-n" | "--dry-run" => dry_run = true,
+                "-v" | "--verbose" => verbose = true,
+                _ => return Err(usage.to_string()),
+            }
+        }
+        if all && update {
+            return Err("-A and -u are mutually incompatible".to_string());
+        }
+        if pathspecs.is_empty() && !all && !update {
+            eprintln!("Nothing specified, nothing added.");
+            return Ok(());
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let lock = LockFile::acquire(&repo.index_path())?;
+        let mut index = Index::load(&repo.index_path(), repo.hash_kind())?;
+
+        // -A and -u without paths work on the whole tree.
+        let mut specs = Vec::new();
+        for arg in &pathspecs {
+            specs.push(worktree::repo_path(&repo, arg)?);
+        }
+        if specs.is_empty() {
+            specs.push(Vec::new());
+        }
+
+        let mut to_add = BTreeSet::new();
+        for (spec, arg) in specs.iter().zip(pathspecs.iter().chain(std::iter::repeat(&"."))) {
+            let full = repo.work_dir().join(worktree::bytes_path(spec)?);
+            let found = match fs::symlink_metadata(&full) {
+                Ok(metadata) if metadata.is_dir() => Some(worktree::list_files(repo.work_dir(), spec)?),
+                Ok(_) => Some(vec![spec.clone()]),
+                Err(_) => None,
+            };
+            let tracked = index.entries().iter().any(|entry| pathspec_matches(spec, &entry.path));
+            match found {
+                Some(files) if !update => to_add.extend(files),
+                None if !tracked => return Err(format!("pathspec '{}' did not match any files", arg)),
+                _ => {}
+            }
+        }
+
+        // Tracked paths under the pathspecs whose files are gone are staged
+        // as deletions; with -u, the others are re-added.
+        let mut to_remove = Vec::new();
+        for entry in index.entries() {
+            if !specs.iter().any(|spec| pathspec_matches(spec, &entry.path)) {
+                continue;
+            }
+            let full = repo.work_dir().join(worktree::bytes_path(&entry.path)?);
+            match fs::symlink_metadata(&full) {
+                Ok(metadata) if !metadata.is_dir() => {
+                    if update {
+                        to_add.insert(entry.path.clone());
+                    }
+                }
+                _ => to_remove.push(entry.path.clone()),
+            }
+        }
+        to_remove.dedup();
+
+        for path in to_remove {
+            if verbose || dry_run {
+                println!("remove '{}'", quote_path(&path));
+            }
+            index.remove(&path);
+        }
+
+        for path in to_add {
+            let full = repo.work_dir().join(worktree::bytes_path(&path)?);
+            let metadata = fs::symlink_metadata(&full)
+                .map_err(|e| format!("unable to stat '{}': {}", full.display(), e))?;
+            let mode = worktree::file_mode(&metadata);
+
+            let existing = index.get(&path);
+            let unchanged = existing.is_some_and(|entry| {
+                !entry.intent_to_add && entry.stat_matches(&metadata) && !index.is_racy(entry)
+            });
+            if unchanged || (intent_to_add && index.contains(&path)) {
+                continue;
+            }
+
+            let mut entry = if intent_to_add {
+                let mut entry = IndexEntry::new(path, mode, odb.hash(ObjectKind::Blob, b""));
+                entry.intent_to_add = true;
+                entry
+            } else {
+                let id = worktree::blob_id(&odb, &full, &metadata, !dry_run)?;
+                let same = existing.is_some_and(|entry| entry.id == id && entry.mode == mode && !entry.intent_to_add);
+                if (verbose || dry_run) && !same {
+                    println!("add '{}'", quote_path(&path));
+                }
+                IndexEntry::new(path, mode, id)
+            };
+            entry.stat = Stat::from_metadata(&metadata);
+            index.add(entry);
+        }
+
+        if dry_run {
+            return Ok(());
+        }
+        index.write(lock)
+    }
+}
@@ -1,33 +1,210 @@
-use std::fs;
-use flate2::read::ZlibDecoder;
-use std::io::prelude::*;
+use std::io::{self, BufRead, BufWriter, Write};
+
+use crate::object::{HashKind, ObjectId, ObjectKind, Tree};
+use crate::odb::{ObjectDatabase, RawObject};
+use crate::repo::Repository;
+use crate::revision::Revisions;
 
 pub struct CatFile;
 
+const DEFAULT_BATCH_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+
+/// A `--batch` output format: literal text interleaved with `%(atom)`s.
+struct BatchFormat {
+    parts: Vec<FormatPart>,
+}
+
+enum FormatPart {
+    Literal(String),
+    ObjectName,
+    ObjectType,
+    ObjectSize,
+    /// Whatever followed the object name on the input line.
+    Rest,
+}
+
+impl BatchFormat {
+    fn parse(format: &str) -> Result<BatchFormat, String> {
+        let mut parts = Vec::new();
+        let mut rest = format;
+        while let Some(start) = rest.find("%(") {
+            if start > 0 {
+                parts.push(FormatPart::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find(')')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("unterminated format element: {}", &rest[start..]))?;
+            parts.push(match &rest[start + 2..end] {
+                "objectname" => FormatPart::ObjectName,
+                "objecttype" => FormatPart::ObjectType,
+                "objectsize" => FormatPart::ObjectSize,
+                "rest" => FormatPart::Rest,
+                other => return Err(format!("unknown format element: {}", other)),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(FormatPart::Literal(rest.to_string()));
+        }
+        Ok(BatchFormat { parts })
+    }
+
+    fn uses_rest(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, FormatPart::Rest))
+    }
+
+    fn expand(&self, id: &ObjectId, kind: ObjectKind, size: usize, rest: &str) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                FormatPart::Literal(text) => out.push_str(text),
+                FormatPart::ObjectName => out.push_str(&id.to_hex()),
+                FormatPart::ObjectType => out.push_str(kind.as_str()),
+                FormatPart::ObjectSize => out.push_str(&size.to_string()),
+                FormatPart::Rest => out.push_str(rest),
+            }
+        }
+        out
+    }
+}
+
 impl CatFile {
     pub fn run(args: &[String]) -> Result<(), String> {
-        if args.is_empty() {
-            return Err("Usage: cat-file <blob_hash>".to_string());
-        }
-        let blob_hash = &args[1];
-        let content = fs::read(format!(".git/objects/{}/{}", &blob_hash[..2], &blob_hash[2..]))
-            .map_err(|e| e.to_string())?;
-        
-        let mut decompressed_data = ZlibDecoder::new(&content[..]);
-        let mut blob_file_contents_vec = Vec::new();
-        decompressed_data.read_to_end(&mut blob_file_contents_vec)
-            .map_err(|e| e.to_string())?;
-        
-        let readable_blob = String::from_utf8(blob_file_contents_vec)
-            .map_err(|e| e.to_string())?;
-        match Self::extract_content(&readable_blob) {
-            Some(content) => print!("{}", content),
-            None => print!("No content found"),
+        if args.iter().any(|arg| arg.starts_with("--batch") || arg == "--buffer") {
+            return Self::run_batch(args);
         }
-        Ok(())
+        let usage = "Usage: cat-file (-t | -s | -e | -p | <type>) <object>";
+        let [mode, spec] = args else {
+            return Err(usage.to_string());
+        };
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let revisions = Revisions::new(&repo, &odb);
+
+        let id = revisions.resolve(spec)?;
+        if mode == "-e" {
+            // The answer is only given through the exit status.
+            if !odb.exists(&id) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+
+        let mut stdout = io::stdout().lock();
+        match mode.as_str() {
+            "-t" => writeln!(stdout, "{}", odb.read_header(&id)?.0).map_err(|e| e.to_string()),
+            "-s" => writeln!(stdout, "{}", odb.read_header(&id)?.1).map_err(|e| e.to_string()),
+            "-p" => Self::pretty_print(&mut stdout, &odb.read(&id)?, odb.hash_kind()),
+            _ if mode.starts_with('-') => Err(usage.to_string()),
+            _ => {
+                // `cat-file <type> <object>` prints the raw content, peeling
+                // tags and commits down to the requested type.
+                let kind = ObjectKind::parse(mode.as_bytes())?;
+                let id = revisions.peel(&id, kind, spec)?;
+                stdout.write_all(&odb.read(&id)?.data).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Answers one object name per stdin line (or every object, with
+    /// `--batch-all-objects`) using a single repository handle, so packs are
+    /// opened and mapped once for the whole run. `--batch` follows each
+    /// answer with the object's content; `--batch-check` only describes it.
+    /// Output is flushed after every answer unless `--buffer` is given.
+    fn run_batch(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: cat-file (--batch | --batch-check)[=<format>] [--batch-all-objects] [--buffer]";
+        let mut with_contents = None;
+        let mut format = DEFAULT_BATCH_FORMAT;
+        let mut all_objects = false;
+        let mut buffer = false;
+        for arg in args {
+            match arg.as_str() {
+                "--batch" => with_contents = Some(true),
+                "--batch-check" => with_contents = Some(false),
+                "--batch-all-objects" => all_objects = true,
+                "--buffer" => buffer = true,
+                _ => {
+                    if let Some(custom) = arg.strip_prefix("--batch=") {
+                        with_contents = Some(true);
+                        format = custom;
+                    } else if let Some(custom) = arg.strip_prefix("--batch-check=") {
+                        with_contents = Some(false);
+                        format = custom;
+                    } else {
+                        return Err(usage.to_string());
+                    }
+                }
+            }
+        }
+        let with_contents = with_contents.ok_or(usage)?;
+        let format = BatchFormat::parse(format)?;
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let revisions = Revisions::new(&repo, &odb);
+        let mut out = BufWriter::new(io::stdout().lock());
+
+        if all_objects {
+            for id in odb.ids()? {
+                Self::answer(&mut out, &odb, &format, with_contents, &id, "")?;
+            }
+            return out.flush().map_err(|e| e.to_string());
+        }
+
+        for line in io::stdin().lock().lines() {
+            let line = line.map_err(|e| e.to_string())?;
+            // With %(rest) in the format, only the first word names the object.
+            let (name, rest) = match line.split_once([' ', '\t']) {
+                Some((name, rest)) if format.uses_rest() => (name, rest.trim_start()),
+                _ => (line.as_str(), ""),
+            };
+            match revisions.resolve(name) {
+                Ok(id) if odb.exists(&id) => Self::answer(&mut out, &odb, &format, with_contents, &id, rest)?,
+                Err(_) if odb.resolve_prefix(name).is_err() => {
+                    writeln!(out, "{} ambiguous", name).map_err(|e| e.to_string())?
+                }
+                _ => writeln!(out, "{} missing", name).map_err(|e| e.to_string())?,
+            }
+            if !buffer {
+                out.flush().map_err(|e| e.to_string())?;
+            }
+        }
+        out.flush().map_err(|e| e.to_string())
+    }
+
+    fn answer(
+        out: &mut impl Write,
+        odb: &ObjectDatabase,
+        format: &BatchFormat,
+        with_contents: bool,
+        id: &ObjectId,
+        rest: &str,
+    ) -> Result<(), String> {
+        if !with_contents {
+            let (kind, size) = odb.read_header(id)?;
+            return writeln!(out, "{}", format.expand(id, kind, size, rest)).map_err(|e| e.to_string());
+        }
+        let object = odb.read(id)?;
+        writeln!(out, "{}", format.expand(id, object.kind, object.data.len(), rest))
+            .and_then(|_| out.write_all(&object.data))
+            .and_then(|_| out.write_all(b"\n"))
+            .map_err(|e| e.to_string())
     }
 
-    fn extract_content(input: &str) -> Option<&str> {
-        input.find('\0').map(|null_pos| &input[null_pos + 1..])
+    /// Writes `object` the way `-p` shows it: trees as one
+    /// `<mode> <type> <id>\t<name>` line per entry, everything else as its
+    /// raw bytes.
+    pub fn pretty_print(out: &mut impl Write, object: &RawObject, hash: HashKind) -> Result<(), String> {
+        if object.kind != ObjectKind::Tree {
+            return out.write_all(&object.data).map_err(|e| e.to_string());
+        }
+        let tree = Tree::parse(&object.data, hash)?;
+        for entry in &tree.entries {
+            write!(out, "{:06o} {} {}\t", entry.mode, entry.kind(), entry.id).map_err(|e| e.to_string())?;
+            out.write_all(&entry.name).map_err(|e| e.to_string())?;
+            out.write_all(b"\n").map_err(|e| e.to_string())?;
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,269 +1,237 @@
 use std::fs;
-use std::io::{Cursor, Read, BufRead};
 use std::path::{Path, PathBuf};
 use url::Url;
-use git2;
-use flate2::read::ZlibDecoder;
-use reqwest;
-use sha1::{Sha1, Digest};
 
-// pub struct Clone;
+use crate::config::{Config, ConfigEditor};
+use crate::index::{self, Index, Stat};
+use crate::lockfile::LockFile;
+use crate::object::tree::{MODE_EXECUTABLE, MODE_GITLINK, MODE_SYMLINK, MODE_TREE};
+use crate::object::ObjectId;
+use crate::odb::ObjectDatabase;
+use crate::pack::indexer;
+use crate::protocol::http::HttpTransport;
+use crate::protocol::Advertisement;
+use crate::refs::{self, Change, Expected, RefUpdate, Transaction};
+use crate::repo::Repository;
+use crate::worktree;
 
-// impl Clone {
-//     pub fn run(args: &[String]) -> Result<(), String> {
-//         if args.len() != 2 {
-//             return Err("Usage: git clone <repo_url> <target_directory>".to_string());
-//         }
-
-//         let repo_url = &args[0];
-//         let target_dir = Path::new(&args[1]);
-
-//         // println!("Cloning repository: {}", repo_url);
-//         // println!("Target directory: {}", target_dir.display());
-
-//         // Create target directory
-//         fs::create_dir_all(target_dir).map_err(|e| format!("Failed to create target directory: {}", e))?;
-
-//         // Initialize Git repository
-//         Self::init_repository(target_dir)?;
-
-//         // Discover references
-//         let refs = Self::discover_refs(repo_url)?;
-//         println!("Discovered {} refs", refs.len());
-
-//         // Get packfile
-//         let packfile = Self::fetch_packfile(repo_url, &refs)?;
-//         println!("Fetched packfile of size: {} bytes", packfile.len());
-
-//         // Process packfile
-//         Self::process_packfile(&packfile, target_dir)?;
-
-//         // Update refs
-//         Self::update_refs(target_dir, &refs)?;
-
-//         println!("Repository cloned successfully.");
-//         Ok(())
-//     }
-
-//     fn init_repository(target_dir: &Path) -> Result<(), String> {
-//         let git_dir = target_dir.join(".git");
-//         fs::create_dir_all(&git_dir).map_err(|e| format!("Failed to create .git directory: {}", e))?;
-        
-//         for dir in &["objects", "refs", "refs/heads"] {
-//             fs::create_dir_all(git_dir.join(dir))
-//                 .map_err(|e| format!("Failed to create {} directory: {}", dir, e))?;
-//         }
-
-//         fs::write(git_dir.join("HEAD"), "ref: refs/heads/master\n")
-//             .map_err(|e| format!("Failed to create HEAD file: {}", e))?;
-
-//         Ok(())
-//     }
-
-//     fn discover_refs(repo_url: &str) -> Result<Vec<(String, String)>, String> {
-//         let url = format!("{}/info/refs?service=git-upload-pack", repo_url);
-//         let response = reqwest::blocking::get(&url)
-//             .map_err(|e| format!("Failed to fetch refs: {}", e))?
-//             .text()
-//             .map_err(|e| format!("Failed to read refs response: {}", e))?;
-
-//         println!("Refs response:\n{}", response);
-
-//         let mut refs = Vec::new();
-//         for line in response.lines().skip(2) {
-//             if line.len() < 4 {
-//                 continue;
-//             }
-//             let line = &line[4..]; // Skip the 4-digit length prefix
-//             if line.is_empty() || line.starts_with('#') {
-//                 continue;
-//             }
-//             let parts: Vec<&str> = line.splitn(2, ' ').collect();
-//             if parts.len() == 2 {
-//                 refs.push((parts[1].to_string(), parts[0].to_string()));
-//             }
-//         }
-
-//         if refs.is_empty() {
-//             return Err("No valid refs found in the response".to_string());
-//         }
-
-//         println!("Parsed refs: {:?}", refs);
-//         Ok(refs)
-//     }
-
-//     fn fetch_packfile(repo_url: &str, refs: &[(String, String)]) -> Result<Vec<u8>, String> {
-//         let url = format!("{}/git-upload-pack", repo_url);
-//         let want_ref = &refs[0].1; // Use the first ref as the one we want
-    
-//         let body = format!("0032want {}\n00000009done\n", want_ref);
-    
-//         println!("Sending request to: {}", url);
-//         println!("Request body: {:?}", body);
-    
-//         let client = reqwest::blocking::Client::new();
-//         let response = client
-//             .post(&url)
-//             .body(body)
-//             .header("Content-Type", "application/x-git-upload-pack-request")
-//             .send()
-//             .map_err(|e| format!("Failed to fetch packfile: {}", e))?;
-    
-//         if !response.status().is_success() {
-//             return Err(format!("Server returned error status: {}", response.status()));
-//         }
-    
-//         let content = response.bytes().map_err(|e| format!("Failed to read packfile: {}", e))?.to_vec();
-        
-//         if content.is_empty() {
-//             return Err("Received empty packfile".to_string());
-//         }
-    
-//         println!("Received response of size: {} bytes", content.len());
-//         println!("First few bytes: {:?}", &content[..std::cmp::min(content.len(), 20)]);
-    
-//         Ok(content)
-//     }
-
-//     fn process_packfile(packfile: &[u8], target_dir: &Path) -> Result<(), String> {
-//         let mut reader = Cursor::new(packfile);
-        
-//         let mut signature = [0u8; 4];
-//         reader.read_exact(&mut signature).map_err(|e| format!("Failed to read packfile signature: {}", e))?;
-        
-//         println!("Packfile signature: {:?}", signature);
-        
-//         if &signature != b"PACK" {
-//             return Err(format!("Invalid packfile signature: {:?}", signature));
-//         }
-    
-//         let mut version = [0u8; 4];
-//         reader.read_exact(&mut version).map_err(|e| format!("Failed to read packfile version: {}", e))?;
-//         let version = u32::from_be_bytes(version);
-        
-//         println!("Packfile version: {}", version);
-        
-//         if version != 2 {
-//             return Err(format!("Unsupported packfile version: {}", version));
-//         }
-    
-//         let mut object_count = [0u8; 4];
-//         reader.read_exact(&mut object_count).map_err(|e| format!("Failed to read object count: {}", e))?;
-//         let object_count = u32::from_be_bytes(object_count);
-    
-//         println!("Processing packfile with {} objects", object_count);
-    
-//         for i in 0..object_count {
-//             Self::extract_object(&mut reader, target_dir)
-//                 .map_err(|e| format!("Failed to extract object {}/{}: {}", i + 1, object_count, e))?;
-//         }
-    
-//         println!("Finished processing packfile");
-//         Ok(())
-//     }
+pub struct Clone;
 
-//     fn extract_object<R: Read>(reader: &mut R, target_dir: &Path) -> Result<(), String> {
-//         let mut byte = [0u8; 1];
-//         reader.read_exact(&mut byte).map_err(|e| format!("Failed to read object type: {}", e))?;
-//         let obj_type = (byte[0] & 0x70) >> 4;
-//         let mut size = (byte[0] & 0x0F) as u64;
-//         let mut shift = 4;
+impl Clone {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("Usage: git clone <repo_url> [<target_directory>]".to_string());
+        }
 
-//         loop {
-//             reader.read_exact(&mut byte).map_err(|e| format!("Failed to read object size: {}", e))?;
-//             size |= ((byte[0] & 0x7F) as u64) << shift;
-//             shift += 7;
-//             if byte[0] & 0x80 == 0 {
-//                 break;
-//             }
-//         }
+        let url = Url::parse(&args[0])
+            .map_err(|e| format!("Invalid URL: {}", e))?;
 
-//         let mut decoder = ZlibDecoder::new(reader.take(size));
-//         let mut content = Vec::new();
-//         decoder.read_to_end(&mut content).map_err(|e| format!("Failed to decompress object: {}", e))?;
+        let directory = match args.get(1) {
+            Some(directory) => PathBuf::from(directory),
+            None => PathBuf::from(Self::default_directory(&url)?),
+        };
 
-//         let mut header = format!("{} {}\0", Self::object_type_to_string(obj_type), size).into_bytes();
-//         header.extend_from_slice(&content);
+        Self::clone(&url, &directory)
+            .map_err(|e| format!("Failed to clone repository: {}", e))
+    }
 
-//         let hash = Sha1::digest(&header);
-//         let hash_str = format!("{:x}", hash);
-//         let obj_path = target_dir.join(".git/objects").join(&hash_str[..2]).join(&hash_str[2..]);
+    fn default_directory(url: &Url) -> Result<String, String> {
+        let repo_name = url
+            .path_segments()
+            .ok_or("Invalid URL: no path segments")?
+            .rfind(|segment| !segment.is_empty())
+            .ok_or("Invalid URL: empty path")?;
+        Ok(repo_name.strip_suffix(".git").unwrap_or(repo_name).to_string())
+    }
 
-//         fs::create_dir_all(obj_path.parent().unwrap()).map_err(|e| format!("Failed to create object directory: {}", e))?;
-//         fs::write(&obj_path, header).map_err(|e| format!("Failed to write object file: {}", e))?;
+    fn clone(url: &Url, directory: &Path) -> Result<(), String> {
+        if directory.exists() && fs::read_dir(directory).map_err(|e| e.to_string())?.next().is_some() {
+            return Err(format!(
+                "destination path '{}' already exists and is not an empty directory",
+                directory.display()
+            ));
+        }
+        eprintln!("Cloning into '{}'...", directory.display());
+
+        let transport = HttpTransport::new(url);
+        let advertisement = transport.discover_refs()?;
+        let head_branch = Self::head_branch(&advertisement);
+
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create target directory: {}", e))?;
+        let config = Config::load_all(None)?;
+        let repo = Repository::init(
+            directory,
+            head_branch.as_deref().or(config.get("init.defaultbranch")).unwrap_or("main"),
+            advertisement.object_format()?,
+        )?;
+        Self::write_remote_config(&repo, url, head_branch.as_deref())?;
+
+        let mut wants: Vec<ObjectId> = Vec::new();
+        for advertised in &advertisement.refs {
+            let wanted = advertised.name == "HEAD"
+                || advertised.name.starts_with("refs/heads/")
+                || advertised.name.starts_with("refs/tags/");
+            if wanted && !wants.contains(&advertised.id) {
+                wants.push(advertised.id.clone());
+            }
+        }
+        if wants.is_empty() {
+            eprintln!("warning: You appear to have cloned an empty repository.");
+            return Ok(());
+        }
 
-//         println!("Extracted object: {} (type: {}, size: {})", hash_str, Self::object_type_to_string(obj_type), size);
-//         Ok(())
-//     }
+        let pack = transport.fetch_pack(&advertisement, &wants)?;
+        indexer::store_pack(&repo.odb()?, pack)?;
 
-//     fn object_type_to_string(obj_type: u8) -> &'static str {
-//         match obj_type {
-//             1 => "commit",
-//             2 => "tree",
-//             3 => "blob",
-//             4 => "tag",
-//             6 => "ofs-delta",
-//             7 => "ref-delta",
-//             _ => "unknown",
-//         }
-//     }
+        Self::update_refs(&repo, url, &advertisement, head_branch.as_deref())?;
 
-//     fn update_refs(target_dir: &Path, refs: &[(String, String)]) -> Result<(), String> {
-//         let git_dir = target_dir.join(".git");
+        if let Some(head) = advertisement.find("HEAD") {
+            let odb = repo.odb()?;
+            let commit = odb.read_commit(&head.id)?;
+            Self::checkout_tree(&odb, &commit.tree, repo.work_dir())?;
+            Self::write_index(&repo, &odb, &commit.tree)?;
+        }
+        Ok(())
+    }
 
-//         for (name, sha) in refs {
-//             if name.starts_with("refs/") {
-//                 let ref_file = git_dir.join(name);
-//                 fs::create_dir_all(ref_file.parent().unwrap()).map_err(|e| format!("Failed to create ref directory: {}", e))?;
-//                 fs::write(ref_file, format!("{}\n", sha)).map_err(|e| format!("Failed to write ref {}: {}", name, e))?;
-//             }
-//         }
+    /// The branch the remote's HEAD points at: announced through the
+    /// `symref` capability, or else guessed from a branch with the same id.
+    fn head_branch(advertisement: &Advertisement) -> Option<String> {
+        if let Some(target) = advertisement.symref_target("HEAD") {
+            return target.strip_prefix("refs/heads/").map(str::to_string);
+        }
+        let head = advertisement.find("HEAD")?;
+        let mut candidates = advertisement.refs.iter().filter(|r| {
+            r.id == head.id && r.name.starts_with("refs/heads/")
+        });
+        let preferred = candidates
+            .clone()
+            .find(|r| r.name == "refs/heads/main" || r.name == "refs/heads/master");
+        preferred
+            .or_else(|| candidates.next())
+            .and_then(|r| r.name.strip_prefix("refs/heads/"))
+            .map(str::to_string)
+    }
 
-//         // Update HEAD
-//         let head_ref = refs.iter().find(|(name, _)| name == "HEAD" || name.ends_with("/HEAD"));
-//         if let Some((_, sha)) = head_ref {
-//             fs::write(git_dir.join("HEAD"), format!("ref: refs/heads/master\n")).map_err(|e| format!("Failed to write HEAD: {}", e))?;
-//         }
+    fn write_remote_config(repo: &Repository, url: &Url, branch: Option<&str>) -> Result<(), String> {
+        let mut config = ConfigEditor::open(&repo.git_dir().join("config"))?;
+        config.set("remote.origin.url", url.as_str())?;
+        config.set("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*")?;
+        if let Some(branch) = branch {
+            config.set(&format!("branch.{}.remote", branch), "origin")?;
+            config.set(&format!("branch.{}.merge", branch), &format!("refs/heads/{}", branch))?;
+        }
+        config.commit()
+    }
 
-//         Ok(())
-//     }
-// }
+    /// Mirrors the remote branches under `refs/remotes/origin`, copies its
+    /// tags and creates the local branch HEAD already points at, all in one
+    /// transaction.
+    fn update_refs(
+        repo: &Repository,
+        url: &Url,
+        advertisement: &Advertisement,
+        head_branch: Option<&str>,
+    ) -> Result<(), String> {
+        let git_dir = repo.git_dir();
+        let mut transaction = Transaction::new(git_dir);
+        transaction.set_message(&format!("clone: from {}", url));
+        let mut create = |name: String, id: &ObjectId, deref: bool| {
+            transaction.add(RefUpdate { name, change: Change::Set(id.clone()), expected: Expected::Any, deref });
+        };
 
-pub struct Clone;
+        for advertised in &advertisement.refs {
+            if let Some(branch) = advertised.name.strip_prefix("refs/heads/") {
+                create(format!("refs/remotes/origin/{}", branch), &advertised.id, true);
+            } else if advertised.name.starts_with("refs/tags/") {
+                create(advertised.name.clone(), &advertised.id, true);
+            }
+        }
+        let head = advertisement.find("HEAD");
+        if let Some(head) = head {
+            match head_branch {
+                Some(branch) => create(format!("refs/heads/{}", branch), &head.id, true),
+                // A detached remote HEAD leaves ours detached too.
+                None => create("HEAD".to_string(), &head.id, false),
+            }
+        }
+        transaction.commit()?;
 
-impl Clone {
-    pub fn run(args: &[String]) -> Result<(), String> {
-        if args.len() < 1 || args.len() > 2 {
-            return Err("Usage: git clone <repo_url> [<target_directory>]".to_string());
+        match (head, head_branch) {
+            (Some(_), Some(branch)) => {
+                refs::write_symbolic(git_dir, "refs/remotes/origin/HEAD", &format!("refs/remotes/origin/{}", branch), None)
+            }
+            _ => Ok(()),
         }
+    }
 
-        let url = Url::parse(&args[0])
-            .map_err(|e| format!("Invalid URL: {}", e))?;
+    fn checkout_tree(odb: &ObjectDatabase, tree_id: &ObjectId, dir: &Path) -> Result<(), String> {
+        let tree = odb.read_tree(tree_id)?;
+        for entry in &tree.entries {
+            let name = entry.name_lossy();
+            if name.is_empty() || name == "." || name == ".." || name == ".git" || name.contains('/') {
+                return Err(format!("Refusing to check out unsafe path '{}'", name));
+            }
+            let path = dir.join(&name);
+
+            match entry.mode {
+                MODE_TREE => {
+                    fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+                    Self::checkout_tree(odb, &entry.id, &path)?;
+                }
+                MODE_GITLINK => fs::create_dir_all(&path).map_err(|e| e.to_string())?,
+                MODE_SYMLINK => {
+                    let target = odb.read(&entry.id)?.data;
+                    Self::create_symlink(&target, &path)?;
+                }
+                mode => {
+                    let contents = odb.read(&entry.id)?.data;
+                    fs::write(&path, contents).map_err(|e| e.to_string())?;
+                    if mode == MODE_EXECUTABLE {
+                        Self::set_executable(&path)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 
-        let directory = if args.len() == 2 {
-            Some(Path::new(&args[1]))
-        } else {
-            None
-        };
+    /// Stages the checked-out files with their fresh stat data, so that the
+    /// new work tree starts out clean.
+    fn write_index(repo: &Repository, odb: &ObjectDatabase, tree: &ObjectId) -> Result<(), String> {
+        let lock = LockFile::acquire(&repo.index_path())?;
+        let mut entries = index::tree_entries(odb, tree, b"")?;
+        for entry in entries.iter_mut().filter(|entry| entry.mode != MODE_GITLINK) {
+            let path = repo.work_dir().join(worktree::bytes_path(&entry.path)?);
+            if let Ok(metadata) = fs::symlink_metadata(&path) {
+                entry.stat = Stat::from_metadata(&metadata);
+            }
+        }
+        let mut index = Index::new(repo.hash_kind());
+        index.replace_entries(entries);
+        index.write(lock)
+    }
 
-        Self::clone(&url, directory)
-            .map_err(|e| format!("Failed to clone repository: {}", e))
+    #[cfg(unix)]
+    fn create_symlink(target: &[u8], path: &Path) -> Result<(), String> {
+        use std::os::unix::ffi::OsStrExt;
+        std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(target), path).map_err(|e| e.to_string())
     }
 
-    fn clone(url: &Url, directory: Option<&Path>) -> Result<(), git2::Error> {
-        let repo_name = url
-            .path_segments()
-            .ok_or_else(|| git2::Error::from_str("Invalid URL: no path segments"))?
-            .last()
-            .ok_or_else(|| git2::Error::from_str("Invalid URL: empty path"))?;
-        
-        let repo_name = repo_name.strip_suffix(".git").unwrap_or(repo_name);
-        let directory_default = PathBuf::from(repo_name);
-        let directory = directory.unwrap_or(&directory_default);
+    #[cfg(not(unix))]
+    fn create_symlink(target: &[u8], path: &Path) -> Result<(), String> {
+        fs::write(path, target).map_err(|e| e.to_string())
+    }
 
-        git2::build::RepoBuilder::new()
-            .clone(url.as_str(), directory)?;
+    #[cfg(unix)]
+    fn set_executable(path: &Path) -> Result<(), String> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())
+    }
 
+    #[cfg(not(unix))]
+    fn set_executable(_path: &Path) -> Result<(), String> {
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,303 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use crate::commands::commit_tree::CommitTree;
+use crate::commands::write_tree::WriteTree;
+use crate::config::Config;
+use crate::ident::{self, Role};
+use crate::index::{Index, IndexEntry, Stat};
+use crate::lockfile::LockFile;
+use crate::object::{self, Object};
+use crate::odb::{ObjectDatabase, DEFAULT_ABBREV};
+use crate::refs::{self, Change, Expected, RefUpdate, Transaction};
+use crate::repo::Repository;
+use crate::worktree;
+
+pub struct Commit;
+
+const EDIT_INSTRUCTIONS: &str = "\n# Please enter the commit message for your changes. Lines starting\n\
+                                 # with '#' will be ignored, and an empty message aborts the commit.\n#\n";
+
+impl Commit {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: commit [-a] [--amend] [--allow-empty] [--allow-empty-message] [--no-edit] [-q] \
+                     [(-m <message>)...] [(-F <file>)...]";
+        let mut message: Option<Vec<u8>> = None;
+        let mut all = false;
+        let mut amend = false;
+        let mut allow_empty = false;
+        let mut allow_empty_message = false;
+        let mut edit = None;
+        let mut quiet = false;
+
+        let args = Self::split_short_options(args);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-m" | "--message" => {
+                    let text = args.next().ok_or(usage)?;
+                    CommitTree::add_paragraph(message.get_or_insert_with(Vec::new), text.as_bytes());
+                }
+                "-F" | "--file" => {
+                    let text = CommitTree::read_message_file(args.next().ok_or(usage)?)?;
+                    CommitTree::add_paragraph(message.get_or_insert_with(Vec::new), &text);
+                }
+                "-a" | "--all" => all = true,
+                "--amend" => amend = true,
+                "--allow-empty" => allow_empty = true,
+                "--allow-empty-message" => allow_empty_message = true,
+                "-e" | "--edit" => edit = Some(true),
+                "--no-edit" => edit = Some(false),
+                "-q" | "--quiet" => quiet = true,
+                _ if arg.starts_with("--message=") => {
+                    let text = &arg["--message=".len()..];
+                    CommitTree::add_paragraph(message.get_or_insert_with(Vec::new), text.as_bytes());
+                }
+                _ => return Err(usage.to_string()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let git_dir = repo.git_dir();
+        let config = repo.config()?;
+
+        // The index stays locked until the commit is recorded, so that -a
+        // can write back what it staged.
+        let lock = LockFile::acquire(&repo.index_path())?;
+        let mut index = Index::load(&repo.index_path(), repo.hash_kind())?;
+        if all {
+            Self::stage_tracked(&repo, &odb, &mut index)?;
+        }
+        let tree = WriteTree::write_tree(&odb, &index, b"", false)?;
+
+        let branch = refs::follow_symbolic(git_dir, "HEAD")?;
+        let head = refs::resolve(git_dir, "HEAD")?;
+        let previous = match (&head, amend) {
+            (Some(head), true) => Some(odb.read_commit(head)?),
+            (None, true) => return Err("You have nothing to amend.".to_string()),
+            (_, false) => None,
+        };
+
+        // Only an amend may leave the tree as it was.
+        if !amend && !allow_empty {
+            let unchanged = match &head {
+                Some(head) => odb.read_commit(head)?.tree == tree,
+                None => index.entries().is_empty(),
+            };
+            if unchanged {
+                return Err("nothing to commit".to_string());
+            }
+        }
+
+        // Without -m or -F an editor is opened, starting from the amended
+        // commit's message if there is one.
+        let initial = message.clone().or_else(|| previous.as_ref().map(|commit| commit.message.clone()));
+        let edit = edit.unwrap_or(message.is_none());
+        let message = match (edit, initial) {
+            (true, initial) => Self::edit_message(&repo, &config, initial.as_deref().unwrap_or_default())?,
+            (false, Some(initial)) => {
+                fs::write(git_dir.join("COMMIT_EDITMSG"), &initial)
+                    .map_err(|e| format!("could not write COMMIT_EDITMSG: {}", e))?;
+                Self::cleanup(&initial, false)
+            }
+            (false, None) => Vec::new(),
+        };
+        if message.is_empty() && !allow_empty_message {
+            return Err("Aborting commit due to empty commit message.".to_string());
+        }
+
+        let (parents, author) = match previous {
+            Some(previous) => (previous.parents, previous.author),
+            None => (head.iter().cloned().collect(), ident::identity(&config, Role::Author)?),
+        };
+        let commit = object::Commit::new(tree, parents, author, ident::identity(&config, Role::Committer)?, message);
+        let subject = commit.summary();
+        let root = commit.parents.is_empty();
+        let id = odb.write_object(&Object::Commit(commit))?;
+
+        let kind = match (amend, root) {
+            (true, _) => "commit (amend)",
+            (false, true) => "commit (initial)",
+            (false, false) => "commit",
+        };
+        let mut transaction = Transaction::new(git_dir);
+        transaction.set_message(&format!("{}: {}", kind, subject));
+        transaction.add(RefUpdate {
+            name: "HEAD".to_string(),
+            change: Change::Set(id.clone()),
+            expected: head.map_or(Expected::Missing, Expected::Value),
+            deref: true,
+        });
+        transaction.commit()?;
+
+        if all {
+            index.write(lock)?;
+        } else {
+            drop(lock);
+        }
+
+        if !quiet {
+            let place = match branch.strip_prefix("refs/heads/") {
+                Some(name) => name.to_string(),
+                None => "detached HEAD".to_string(),
+            };
+            let root = if root { " (root-commit)" } else { "" };
+            println!("[{}{} {}] {}", place, root, odb.abbreviate(&id, DEFAULT_ABBREV)?, subject);
+        }
+        Ok(())
+    }
+
+    /// Splits clustered short options the way git's option parser does:
+    /// `-qa` is `-q -a`, and `-m` or `-F` takes the rest of the cluster as
+    /// its value when there is any, so `-qamfix` is `-q -a -m fix`. The
+    /// value of `--message` or `--file` is passed through untouched.
+    fn split_short_options(args: &[String]) -> Vec<String> {
+        let mut out = Vec::with_capacity(args.len());
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let cluster = match arg.strip_prefix('-') {
+                Some(cluster) if !cluster.is_empty() && !cluster.starts_with('-') => cluster,
+                _ => {
+                    out.push(arg.clone());
+                    if arg == "--message" || arg == "--file" {
+                        out.extend(args.next().cloned());
+                    }
+                    continue;
+                }
+            };
+            for (i, c) in cluster.char_indices() {
+                out.push(format!("-{}", c));
+                if c == 'm' || c == 'F' {
+                    let value = &cluster[i + 1..];
+                    if !value.is_empty() {
+                        out.push(value.to_string());
+                    } else if let Some(next) = args.next() {
+                        out.push(next.clone());
+                    }
+                    break;
+                }
+            }
+        }
+        out
+    }
+
+    /// Stages every tracked file as it is in the work tree, the way `-a`
+    /// does: modified files are re-added and deleted ones removed. New
+    /// files stay untracked.
+    fn stage_tracked(repo: &Repository, odb: &ObjectDatabase, index: &mut Index) -> Result<(), String> {
+        let mut updated = Vec::new();
+        let mut removed = Vec::new();
+        for entry in index.entries().iter().filter(|entry| entry.stage == 0) {
+            let full = repo.work_dir().join(worktree::bytes_path(&entry.path)?);
+            match fs::symlink_metadata(&full) {
+                Ok(metadata) if !metadata.is_dir() => {
+                    if entry.intent_to_add || worktree::is_modified(odb, index, entry, &full, &metadata)? {
+                        let id = worktree::blob_id(odb, &full, &metadata, true)?;
+                        let mut updated_entry = IndexEntry::new(entry.path.clone(), worktree::file_mode(&metadata), id);
+                        updated_entry.stat = Stat::from_metadata(&metadata);
+                        updated.push(updated_entry);
+                    }
+                }
+                _ => removed.push(entry.path.clone()),
+            }
+        }
+        for path in removed {
+            index.remove(&path);
+        }
+        for entry in updated {
+            index.add(entry);
+        }
+        Ok(())
+    }
+
+    /// Opens the editor on `COMMIT_EDITMSG`, seeded with `initial`, and
+    /// returns what was saved with the comment lines stripped.
+    fn edit_message(repo: &Repository, config: &Config, initial: &[u8]) -> Result<Vec<u8>, String> {
+        let path = repo.git_dir().join("COMMIT_EDITMSG");
+        let mut template = initial.to_vec();
+        template.extend_from_slice(EDIT_INSTRUCTIONS.as_bytes());
+        fs::write(&path, &template).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+
+        let editor = env::var("GIT_EDITOR")
+            .ok()
+            .or_else(|| config.get("core.editor").map(str::to_string))
+            .or_else(|| env::var("VISUAL").ok())
+            .or_else(|| env::var("EDITOR").ok())
+            .unwrap_or_else(|| "vi".to_string());
+        // The editor setting is a shell command, which may carry arguments.
+        if editor != ":" {
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(format!("{} \"$@\"", editor))
+                .arg(&editor)
+                .arg(&path)
+                .status()
+                .map_err(|e| format!("unable to start editor '{}': {}", editor, e))?;
+            if !status.success() {
+                return Err(format!(
+                    "there was a problem with the editor '{}'.\nPlease supply the message using either -m or -F option.",
+                    editor
+                ));
+            }
+        }
+
+        let text = fs::read(&path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        Ok(Self::cleanup(&text, true))
+    }
+
+    /// Tidies a message as git's default cleanup does: trailing whitespace
+    /// goes, runs of blank lines become one, blank lines at either end are
+    /// dropped and the message ends in a newline. Lines starting with `#`
+    /// are removed too when `strip_comments` is set.
+    fn cleanup(text: &[u8], strip_comments: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pending_blank = false;
+        for line in text.split(|&b| b == b'\n') {
+            if strip_comments && line.starts_with(b"#") {
+                continue;
+            }
+            let end = line.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(0, |i| i + 1);
+            let line = &line[..end];
+            if line.is_empty() {
+                pending_blank = !out.is_empty();
+                continue;
+            }
+            if pending_blank {
+                out.push(b'\n');
+                pending_blank = false;
+            }
+            out.extend_from_slice(line);
+            out.push(b'\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(args: &[&str]) -> Vec<String> {
+        Commit::split_short_options(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn splits_clustered_short_options() {
+        assert_eq!(split(&["-qa"]), ["-q", "-a"]);
+        assert_eq!(split(&["-qam", "msg"]), ["-q", "-a", "-m", "msg"]);
+        assert_eq!(split(&["-amfix", "-e"]), ["-a", "-m", "fix", "-e"]);
+        assert_eq!(split(&["-qF", "-"]), ["-q", "-F", "-"]);
+        // A value is taken as it is, even when it looks like options.
+        assert_eq!(split(&["-m", "-qa"]), ["-m", "-qa"]);
+        assert_eq!(split(&["--amend", "--message=-q", "-"]), ["--amend", "--message=-q", "-"]);
+    }
+
+    #[test]
+    fn keeps_long_option_values_whole() {
+        assert_eq!(split(&["--message", "-qa"]), ["--message", "-qa"]);
+        assert_eq!(split(&["--file", "-qa", "-qa"]), ["--file", "-qa", "-q", "-a"]);
+        assert_eq!(split(&["-q", "--message"]), ["-q", "--message"]);
+    }
+}
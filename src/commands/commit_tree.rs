@@ -1,73 +1,97 @@
-use std;
-use sha1::{Digest, Sha1};
 use std::fs;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use std::io::Write;
+use std::io::{self, Read};
+
+use crate::ident::{self, Role};
+use crate::object::{Commit, Object, ObjectKind};
+use crate::repo::Repository;
+use crate::revision::Revisions;
 
 pub struct CommitTree;
 
-impl CommitTree{
-    pub fn run(args: &[String]) -> Result<(), String>{
+impl CommitTree {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: commit-tree <tree> [(-p <parent>)...] [(-m <message>)...] [(-F <file>)...]";
+        let mut tree = None;
+        let mut parent_specs = Vec::new();
+        let mut message: Option<Vec<u8>> = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-p" => parent_specs.push(args.next().ok_or(usage)?),
+                "-m" => {
+                    let text = args.next().ok_or(usage)?;
+                    Self::add_paragraph(message.get_or_insert_with(Vec::new), text.as_bytes());
+                }
+                "-F" => {
+                    let file = args.next().ok_or(usage)?;
+                    let text = Self::read_message_file(file)?;
+                    Self::add_paragraph(message.get_or_insert_with(Vec::new), &text);
+                }
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ if tree.is_none() => tree = Some(arg),
+                _ => return Err(usage.to_string()),
+            }
+        }
+        let tree = tree.ok_or(usage)?;
 
-        let message = format!("{}\n", args[4]);
-        let parent_commit_sha = &args[2];
-        let tree_sha = &args[0];
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let revisions = Revisions::new(&repo, &odb);
+        let tree = revisions.resolve_as(tree, ObjectKind::Tree)?;
+        let mut parents = Vec::new();
+        for spec in parent_specs {
+            let parent = revisions.resolve_as(spec, ObjectKind::Commit)?;
+            if parents.contains(&parent) {
+                eprintln!("error: duplicate parent {} ignored", parent);
+                continue;
+            }
+            parents.push(parent);
+        }
 
-        let now = std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).expect("Time went backwards");
-        let timestamp = now.as_secs();
-        
-        // Hardcoded values for demonstration (replace these with actual values in a real implementation)
-        let author_name = "John Doe";
-        let author_email = "johndoe@example.com";
-        let committer_name = "John Doe";
-        let committer_email = "johndoe@example.com";
-        let timezone = "+0000";
+        // Without -m or -F, the message is read from standard input as is.
+        let message = match message {
+            Some(message) => message,
+            None => {
+                let mut message = Vec::new();
+                io::stdin().read_to_end(&mut message).map_err(|e| e.to_string())?;
+                message
+            }
+        };
 
-        // Construct the content of the commit object
-        let content = format!(
-            "tree {}\n\
-            parent {}\n\
-            author {} <{}> {} {}\n\
-            committer {} <{}> {} {}\n\
-            \n\
-            {}",
-            tree_sha,
-            parent_commit_sha,
-            author_name, author_email, timestamp, timezone,
-            committer_name, committer_email, timestamp, timezone,
+        let config = repo.config()?;
+        let commit = Commit::new(
+            tree,
+            parents,
+            ident::identity(&config, Role::Author)?,
+            ident::identity(&config, Role::Committer)?,
             message,
         );
 
+        let commit_hash = odb.write_object(&Object::Commit(commit))?;
+        println!("{}", commit_hash);
+        Ok(())
+    }
 
-        let size = content.len();
-
-        let commit_obj_str = format!("commit {}\0{}", size, content);
-        let mut hasher = Sha1::new();
-        hasher.update(commit_obj_str.clone());
-        let hash_result = hasher.finalize();
-
-        let commit_hash = format!("{:x}", hash_result);
-        print!("{}", &commit_hash);
-
-        let dir_name = &commit_hash[..2];
-        let file_name = &commit_hash[2..];
-
-        let dir_path = format!(".git/objects/{}", dir_name);
-        let file_path = format!("{}/{}", dir_path, file_name);
-
-        fs::create_dir_all(&dir_path).unwrap();
-
-
-        let commit_object_file = fs::File::create(file_path).unwrap();
+    /// Appends one `-m` or `-F` paragraph, separated from the previous one
+    /// by a blank line and ending in a newline.
+    pub(crate) fn add_paragraph(message: &mut Vec<u8>, text: &[u8]) {
+        if !message.is_empty() {
+            message.push(b'\n');
+        }
+        message.extend_from_slice(text);
+        if !message.is_empty() && !message.ends_with(b"\n") {
+            message.push(b'\n');
+        }
+    }
 
-        // STEP 4 - COMPRESS THE CONTENTS OF THE ORIGINAL FILE USING ZLIB AND WRITE IT TO THE .git/objects/[hash[..2]]/[hash[2..]] file
-        // let mut encoder = ZlibEncoder::new(commit_object_file, Compression::default());
-        // encoder.write_all(commit_obj_str.as_bytes()).unwrap();
-        // encoder.finish().unwrap();
-        let mut encoder = ZlibEncoder::new(commit_object_file, Compression::default());
-        encoder.write_all(commit_obj_str.as_bytes()).map_err(|e| e.to_string())?;
-        encoder.finish().map_err(|e| e.to_string())?;
-        Ok(())
+    /// Reads a message file, where `-` means standard input.
+    pub(crate) fn read_message_file(file: &str) -> Result<Vec<u8>, String> {
+        if file == "-" {
+            let mut text = Vec::new();
+            io::stdin().read_to_end(&mut text).map_err(|e| e.to_string())?;
+            return Ok(text);
+        }
+        fs::read(file).map_err(|e| format!("could not read log file '{}': {}", file, e))
     }
-}
\ No newline at end of file
+}
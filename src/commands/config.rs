@@ -0,0 +1,306 @@
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::config::{self, ConfigEditor, ConfigEntry, IncludeContext, Scope};
+use crate::repo::Repository;
+
+pub struct Config;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Get,
+    GetAll,
+    List,
+    Set,
+    Add,
+    Unset,
+    UnsetAll,
+}
+
+/// The file `--global`, `--system`, `--local` or `--file` picks.
+#[derive(Clone)]
+enum Location {
+    Global,
+    System,
+    Local,
+    File(PathBuf),
+}
+
+/// How `--type` reads and writes values.
+#[derive(Clone, Copy)]
+enum Type {
+    Bool,
+    Int,
+    BoolOrInt,
+    Path,
+    Color,
+}
+
+struct Output {
+    kind: Option<Type>,
+    show_origin: bool,
+    show_scope: bool,
+    nul_terminated: bool,
+}
+
+impl Config {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: config [--global | --system | --local | --file <file>] [--[no-]includes] \
+                     [--type=<type>] [--show-origin] [--show-scope] [-z]\n       \
+                     [--get | --get-all | --list | --set | --add | --unset | --unset-all] [<name> [<value>]]";
+        let mut action = None;
+        let mut location = None;
+        let mut includes = None;
+        let mut output = Output { kind: None, show_origin: false, show_scope: false, nul_terminated: false };
+        let mut positional = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let chosen = match arg.as_str() {
+                "--get" => Some(Action::Get),
+                "--get-all" => Some(Action::GetAll),
+                "-l" | "--list" => Some(Action::List),
+                "--set" => Some(Action::Set),
+                "--add" => Some(Action::Add),
+                "--unset" => Some(Action::Unset),
+                "--unset-all" => Some(Action::UnsetAll),
+                _ => None,
+            };
+            if let Some(chosen) = chosen {
+                if action.is_some_and(|action| action != chosen) {
+                    return Err("only one action at a time".to_string());
+                }
+                action = Some(chosen);
+                continue;
+            }
+
+            match arg.as_str() {
+                "--global" => location = Some(Location::Global),
+                "--system" => location = Some(Location::System),
+                "--local" => location = Some(Location::Local),
+                "-f" | "--file" => location = Some(Location::File(PathBuf::from(args.next().ok_or(usage)?))),
+                "--includes" => includes = Some(true),
+                "--no-includes" => includes = Some(false),
+                "--type" => output.kind = Some(Type::from_name(args.next().ok_or(usage)?)?),
+                "--bool" => output.kind = Some(Type::Bool),
+                "--int" => output.kind = Some(Type::Int),
+                "--bool-or-int" => output.kind = Some(Type::BoolOrInt),
+                "--path" => output.kind = Some(Type::Path),
+                "--show-origin" => output.show_origin = true,
+                "--show-scope" => output.show_scope = true,
+                "-z" | "--null" => output.nul_terminated = true,
+                _ if arg.starts_with("--file=") => {
+                    location = Some(Location::File(PathBuf::from(&arg["--file=".len()..])));
+                }
+                _ if arg.starts_with("--type=") => output.kind = Some(Type::from_name(&arg["--type=".len()..])?),
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => positional.push(arg.as_str()),
+            }
+        }
+
+        let action = match (action, positional.len()) {
+            (Some(action), _) => action,
+            (None, 1) => Action::Get,
+            (None, 2) => Action::Set,
+            (None, _) => return Err(usage.to_string()),
+        };
+        let expected = match action {
+            Action::List => 0,
+            Action::Get | Action::GetAll | Action::Unset | Action::UnsetAll => 1,
+            Action::Set | Action::Add => 2,
+        };
+        if positional.len() != expected {
+            return Err(usage.to_string());
+        }
+
+        let repo = Repository::discover().ok();
+        match action {
+            Action::Get | Action::GetAll | Action::List => {
+                let config = Self::read(repo.as_ref(), location.as_ref(), includes)?;
+                let entries = match action {
+                    Action::List => config.entries().iter().collect(),
+                    _ => {
+                        config::key_parts(positional[0])?;
+                        let mut entries = config.matching(positional[0]);
+                        if action == Action::Get {
+                            entries.drain(..entries.len().saturating_sub(1));
+                        }
+                        entries
+                    }
+                };
+                if entries.is_empty() && action != Action::List {
+                    process::exit(1);
+                }
+                output.print(&entries, action == Action::List)
+            }
+            Action::Set | Action::Add | Action::Unset | Action::UnsetAll => {
+                let (_, path) = Self::file(repo.as_ref(), location.unwrap_or(Location::Local))?;
+                Self::edit(&path, action, positional[0], positional.get(1).copied(), output.kind)
+            }
+        }
+    }
+
+    /// The config to look values up in: every scope, or just the chosen
+    /// file, where includes are only followed with `--includes`.
+    fn read(
+        repo: Option<&Repository>,
+        location: Option<&Location>,
+        includes: Option<bool>,
+    ) -> Result<config::Config, String> {
+        let git_dir = repo.map(|repo| repo.git_dir().to_path_buf());
+        let Some(location) = location else {
+            return config::Config::load_all(git_dir.as_deref());
+        };
+        let (scope, path) = Self::file(repo, location.clone())?;
+        let context = IncludeContext { git_dir };
+        let mut config = config::Config::default();
+        config.add_file(&path, scope, includes.unwrap_or(false).then_some(&context))?;
+        Ok(config)
+    }
+
+    fn file(repo: Option<&Repository>, location: Location) -> Result<(Scope, PathBuf), String> {
+        match location {
+            Location::Global => Ok((Scope::Global, config::Config::global_path()?)),
+            Location::System => Ok((
+                Scope::System,
+                config::Config::system_path().unwrap_or_else(|| PathBuf::from("/etc/gitconfig")),
+            )),
+            Location::Local => match repo {
+                Some(repo) => Ok((Scope::Local, repo.git_dir().join("config"))),
+                None => Err("--local can only be used inside a git repository".to_string()),
+            },
+            Location::File(path) => Ok((Scope::Command, path)),
+        }
+    }
+
+    /// Applies a write action to one file. Like git, an ambiguous change
+    /// to a multi-valued key and unsetting a missing key exit with 5.
+    fn edit(
+        path: &Path,
+        action: Action,
+        name: &str,
+        value: Option<&str>,
+        kind: Option<Type>,
+    ) -> Result<(), String> {
+        let value = match (value, kind) {
+            (Some(value), Some(kind)) => Some(kind.normalize(name, value)?),
+            (value, _) => value.map(str::to_string),
+        };
+        let mut editor = ConfigEditor::open(path)?;
+        let count = editor.count(name)?;
+
+        let refusal = match action {
+            Action::Set if count > 1 => Some(format!(
+                "warning: {} has multiple values\nerror: cannot overwrite multiple values with a single value\n       \
+                 Use a regexp, --add or --replace-all to change {}.",
+                name, name
+            )),
+            Action::Unset if count > 1 => Some(format!("warning: {} has multiple values", name)),
+            Action::Unset | Action::UnsetAll if count == 0 => Some(String::new()),
+            _ => None,
+        };
+        if let Some(message) = refusal {
+            // Exiting skips destructors, so release the lock first.
+            drop(editor);
+            if !message.is_empty() {
+                eprintln!("{}", message);
+            }
+            process::exit(5);
+        }
+
+        match (action, value) {
+            (Action::Set, Some(value)) => editor.set(name, &value)?,
+            (Action::Add, Some(value)) => editor.add(name, &value)?,
+            _ => {
+                editor.unset(name)?;
+            }
+        }
+        editor.commit()
+    }
+}
+
+impl Type {
+    fn from_name(name: &str) -> Result<Type, String> {
+        match name {
+            "bool" => Ok(Type::Bool),
+            "int" => Ok(Type::Int),
+            "bool-or-int" => Ok(Type::BoolOrInt),
+            "path" => Ok(Type::Path),
+            "color" => Ok(Type::Color),
+            _ => Err(format!("unrecognized --type argument, {}", name)),
+        }
+    }
+
+    /// A value as `--get` shows it: booleans and integers in canonical
+    /// form, paths expanded and colors as escape sequences.
+    fn format(self, name: &str, value: Option<&str>) -> Result<String, String> {
+        let text = value.unwrap_or("");
+        let bad_bool = || format!("bad boolean config value '{}' for '{}'", text, name);
+        let bad_int = || format!("bad numeric config value '{}' for '{}': invalid unit", text, name);
+        match self {
+            Type::Bool => config::parse_bool(value).map(|b| b.to_string()).ok_or_else(bad_bool),
+            Type::Int => config::parse_int(text).map(|n| n.to_string()).ok_or_else(bad_int),
+            Type::BoolOrInt => match value.and_then(config::parse_int) {
+                Some(n) => Ok(n.to_string()),
+                None => config::parse_bool(value).map(|b| b.to_string()).ok_or_else(bad_bool),
+            },
+            Type::Path => config::expand_path(text).map(|path| path.to_string_lossy().into_owned()),
+            Type::Color => config::parse_color(text).ok_or_else(|| format!("invalid color value: {}", text)),
+        }
+    }
+
+    /// A value as it is stored: booleans and integers canonicalised, while
+    /// paths and colors are kept as written once they check out.
+    fn normalize(self, name: &str, value: &str) -> Result<String, String> {
+        let formatted = self.format(name, Some(value))?;
+        match self {
+            Type::Bool | Type::Int | Type::BoolOrInt => Ok(formatted),
+            Type::Path | Type::Color => Ok(value.to_string()),
+        }
+    }
+}
+
+impl Output {
+    /// Prints values for `--get` and `--get-all`, or `name=value` lines for
+    /// `--list`, where a bare key is shown without `=`. With `-z` each
+    /// record ends in NUL and a name is followed by a newline instead.
+    fn print(&self, entries: &[&ConfigEntry], list: bool) -> Result<(), String> {
+        let (field_end, record_end) = match self.nul_terminated {
+            true => ("\0", "\0"),
+            false => ("\t", "\n"),
+        };
+        let mut out = BufWriter::new(io::stdout().lock());
+        for entry in entries {
+            let mut line = String::new();
+            if self.show_scope {
+                let scope = entry.origin.as_ref().map_or(Scope::Command, |origin| origin.scope);
+                line.push_str(scope.name());
+                line.push_str(field_end);
+            }
+            if self.show_origin {
+                if let Some(origin) = &entry.origin {
+                    line.push_str(&origin.describe());
+                }
+                line.push_str(field_end);
+            }
+
+            let value = match self.kind {
+                Some(kind) if !list => kind.format(&entry.name(), entry.value.as_deref())?,
+                _ => entry.value.clone().unwrap_or_default(),
+            };
+            if list {
+                line.push_str(&entry.name());
+                if entry.value.is_some() {
+                    line.push_str(if self.nul_terminated { "\n" } else { "=" });
+                    line.push_str(&value);
+                }
+            } else {
+                line.push_str(&value);
+            }
+            line.push_str(record_end);
+            out.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+        }
+        out.flush().map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::fsck::{self, Severity};
+use crate::index::Index;
+use crate::object::tree::MODE_GITLINK;
+use crate::object::{Object, ObjectId, ObjectKind};
+use crate::odb::{ObjectDatabase, RawObject};
+use crate::pack::indexer;
+use crate::reflog;
+use crate::refs;
+use crate::repo::Repository;
+
+pub struct Fsck;
+
+/// What fsck learned about the object store: every object's type and the
+/// objects each one points at (with the type it expects to find).
+#[derive(Default)]
+struct Graph {
+    kinds: BTreeMap<ObjectId, ObjectKind>,
+    links: HashMap<ObjectId, Vec<(ObjectId, ObjectKind)>>,
+    errors: usize,
+}
+
+impl Fsck {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let mut show_unreachable = false;
+        let mut show_dangling = true;
+        let mut strict = false;
+        for arg in args {
+            match arg.as_str() {
+                "--unreachable" => show_unreachable = true,
+                "--no-dangling" => show_dangling = false,
+                "--dangling" => show_dangling = true,
+                "--strict" => strict = true,
+                _ => return Err("Usage: fsck [--unreachable] [--[no-]dangling] [--strict]".to_string()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let mut graph = Graph::default();
+
+        Self::check_packs(&odb, &mut graph, strict);
+        Self::check_loose(&odb, &mut graph, strict)?;
+
+        let mut tips = Vec::new();
+        let mut named_tips: Vec<(String, ObjectId)> = refs::list_refs(repo.git_dir())?;
+        if let Some(head) = refs::resolve(repo.git_dir(), "HEAD")? {
+            named_tips.push(("HEAD".to_string(), head));
+        }
+        for (name, id) in named_tips {
+            if graph.kinds.contains_key(&id) {
+                tips.push(id);
+            } else {
+                eprintln!("error: {}: invalid sha1 pointer {}", name, id);
+                graph.errors += 1;
+            }
+        }
+        // Commits a ref used to point at, blobs only staged so far and the
+        // trees cached for the index are not dangling either.
+        let null = odb.hash_kind().null_id();
+        for name in reflog::list(repo.git_dir())? {
+            for entry in reflog::read(repo.git_dir(), &name)? {
+                for id in [entry.old, entry.new] {
+                    if id == null {
+                        continue;
+                    }
+                    if graph.kinds.contains_key(&id) {
+                        tips.push(id);
+                    } else {
+                        eprintln!("error: {}: invalid reflog entry {}", name, id);
+                        graph.errors += 1;
+                    }
+                }
+            }
+        }
+        let index = Index::load(&repo.index_path(), odb.hash_kind())?;
+        for entry in index.entries() {
+            if entry.mode == MODE_GITLINK || entry.intent_to_add {
+                continue;
+            }
+            if graph.kinds.contains_key(&entry.id) {
+                tips.push(entry.id.clone());
+            } else {
+                eprintln!("error: {}: invalid sha1 pointer in the index", entry.id);
+                graph.errors += 1;
+            }
+        }
+        for id in index.cache_tree() {
+            if graph.kinds.contains_key(&id) {
+                tips.push(id);
+            } else {
+                eprintln!("error: {}: invalid sha1 pointer in cache-tree", id);
+                graph.errors += 1;
+            }
+        }
+
+        let mut referenced: HashSet<&ObjectId> = HashSet::new();
+        let mut missing: BTreeMap<&ObjectId, ObjectKind> = BTreeMap::new();
+        for targets in graph.links.values() {
+            for (target, kind) in targets {
+                referenced.insert(target);
+                if !graph.kinds.contains_key(target) {
+                    missing.insert(target, *kind);
+                }
+            }
+        }
+        for (id, kind) in &missing {
+            println!("missing {} {}", kind, id);
+        }
+
+        let mut reachable: HashSet<&ObjectId> = HashSet::new();
+        let mut stack: Vec<&ObjectId> = tips.iter().collect();
+        while let Some(id) = stack.pop() {
+            if !reachable.insert(id) {
+                continue;
+            }
+            if let Some(targets) = graph.links.get(id) {
+                stack.extend(targets.iter().map(|(target, _)| target));
+            }
+        }
+
+        for (id, kind) in &graph.kinds {
+            if reachable.contains(id) {
+                continue;
+            }
+            if show_unreachable {
+                println!("unreachable {} {}", kind, id);
+            } else if show_dangling && !referenced.contains(id) {
+                println!("dangling {} {}", kind, id);
+            }
+        }
+
+        if graph.errors > 0 || !missing.is_empty() {
+            return Err(format!("fsck found {} errors and {} missing objects", graph.errors, missing.len()));
+        }
+        Ok(())
+    }
+
+    fn check_packs(odb: &ObjectDatabase, graph: &mut Graph, strict: bool) {
+        for pack in odb.packs() {
+            let index = pack.index();
+            let checked = pack.data().and_then(|data| indexer::verify_pack_header(data, odb.hash_kind()).map(|_| ()));
+            if let Err(e) = checked {
+                eprintln!("error: {}: {}", pack.path().display(), e);
+                graph.errors += 1;
+                continue;
+            }
+            if !index.verify_checksum() {
+                eprintln!("error: {}: index checksum mismatch", pack.path().display());
+                graph.errors += 1;
+            }
+
+            for i in 0..index.len() {
+                let id = index.id_at(i);
+                match pack.read_at(index.offset_at(i)) {
+                    Ok(object) => Self::check_object(odb, &id, object, graph, strict),
+                    Err(e) => {
+                        eprintln!("error: {}: cannot read {}: {}", pack.path().display(), id, e);
+                        graph.errors += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_loose(odb: &ObjectDatabase, graph: &mut Graph, strict: bool) -> Result<(), String> {
+        for id in odb.loose().ids()? {
+            match odb.loose().read(&id) {
+                Ok(Some(object)) => Self::check_object(odb, &id, object, graph, strict),
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    graph.errors += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_object(odb: &ObjectDatabase, id: &ObjectId, object: RawObject, graph: &mut Graph, strict: bool) {
+        if graph.kinds.contains_key(id) {
+            return;
+        }
+        let actual = odb.hash(object.kind, &object.data);
+        if &actual != id {
+            eprintln!("error: hash mismatch for {} (content hashes to {})", id, actual);
+            graph.errors += 1;
+            return;
+        }
+        graph.kinds.insert(id.clone(), object.kind);
+
+        for problem in fsck::check_object(object.kind, &object.data, odb.hash_kind()) {
+            let is_error = problem.severity == Severity::Error || strict;
+            let label = if is_error { "error" } else { "warning" };
+            eprintln!("{} in {} {}: {}", label, object.kind, id, problem.message);
+            if is_error {
+                graph.errors += 1;
+            }
+        }
+
+        let links = match object.parse(odb.hash_kind()) {
+            Ok(Object::Commit(commit)) => {
+                let mut links = vec![(commit.tree, ObjectKind::Tree)];
+                links.extend(commit.parents.into_iter().map(|parent| (parent, ObjectKind::Commit)));
+                links
+            }
+            Ok(Object::Tree(tree)) => tree.entries
+                .into_iter()
+                .filter(|entry| entry.mode != MODE_GITLINK)
+                .map(|entry| {
+                    let kind = entry.kind();
+                    (entry.id, kind)
+                })
+                .collect(),
+            Ok(Object::Tag(tag)) => vec![(tag.object, tag.kind)],
+            Ok(Object::Blob(_)) | Err(_) => Vec::new(),
+        };
+        graph.links.insert(id.clone(), links);
+    }
+}
@@ -1,45 +1,107 @@
 use std::fs;
-use sha1::{Digest, Sha1};
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use std::io::Write;
+use std::io::{self, BufRead, Read};
+
+use crate::object::{HashKind, Object, ObjectId, ObjectKind};
+use crate::odb::ObjectDatabase;
+use crate::repo::Repository;
 
 pub struct HashObject;
 
 impl HashObject {
     pub fn run(args: &[String]) -> Result<(), String> {
-        if args.is_empty() {
-            return Err("Usage: hash-object <filename>".to_string());
-        }
-        let filename = &args[1];
-        let sha_input = match fs::read_to_string(filename) {
-            Ok(x) => {
-                let size = fs::metadata(filename).map_err(|e| e.to_string())?.len();
-                format!("blob {}\0{}", size, x)
-            },
-            Err(_) => return Err("Invalid file content".to_string()),
-        };
+        let usage = "Usage: hash-object [-t <type>] [-w] [--literally] [--stdin] [--stdin-paths] [<file>...]";
+        let mut kind = ObjectKind::Blob;
+        let mut write = false;
+        let mut literally = false;
+        let mut from_stdin = false;
+        let mut stdin_paths = false;
+        let mut files = Vec::new();
 
-        let mut hasher = Sha1::new();
-        hasher.update(sha_input.clone());
-        let hash_result = hasher.finalize();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-w" => write = true,
+                "-t" => kind = ObjectKind::parse(iter.next().ok_or(usage)?.as_bytes())?,
+                "--literally" => literally = true,
+                "--stdin" => from_stdin = true,
+                "--stdin-paths" => stdin_paths = true,
+                "--" => files.extend(iter.by_ref()),
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => files.push(arg),
+            }
+        }
+        if stdin_paths && (from_stdin || !files.is_empty()) {
+            return Err("--stdin-paths cannot be combined with --stdin or file arguments".to_string());
+        }
 
-        let hash = format!("{:x}", hash_result);
-        print!("{}", &hash);
+        // Writing needs a repository; hashing alone works anywhere, using
+        // the surrounding repository's hash function when there is one.
+        let repo = Repository::discover();
+        let odb = match (&repo, write) {
+            (Ok(repo), _) => Some(repo.odb()?),
+            (Err(e), true) => return Err(e.clone()),
+            (Err(_), false) => None,
+        };
+        let hasher = ObjectHasher { odb: odb.as_ref(), kind, write, literally };
+
+        if from_stdin {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data).map_err(|e| e.to_string())?;
+            println!("{}", hasher.hash_bytes(&data)?);
+        }
+        for file in files {
+            println!("{}", hasher.hash_file(file)?);
+        }
+        if stdin_paths {
+            for line in io::stdin().lock().lines() {
+                let path = line.map_err(|e| e.to_string())?;
+                println!("{}", hasher.hash_file(&path)?);
+            }
+        }
+        Ok(())
+    }
+}
 
-        let dir_name = &hash[..2];
-        let file_name = &hash[2..];
+/// Hashes (and with `-w`, stores) one object at a time with shared settings.
+struct ObjectHasher<'a> {
+    odb: Option<&'a ObjectDatabase>,
+    kind: ObjectKind,
+    write: bool,
+    literally: bool,
+}
 
-        let dir_path = format!(".git/objects/{}", dir_name);
-        let file_path = format!("{}/{}", dir_path, file_name);
-        fs::create_dir_all(&dir_path).map_err(|e| e.to_string())?;
+impl ObjectHasher<'_> {
+    fn hash_kind(&self) -> HashKind {
+        self.odb.map_or(HashKind::default(), |odb| odb.hash_kind())
+    }
 
-        let blob_object_file = fs::File::create(file_path).map_err(|e| e.to_string())?;
+    /// Blobs are streamed from disk so large files are never held in memory;
+    /// other types are read whole, since they are checked by parsing.
+    fn hash_file(&self, path: &str) -> Result<ObjectId, String> {
+        if self.kind != ObjectKind::Blob {
+            let data = fs::read(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+            return self.hash_bytes(&data);
+        }
 
-        let mut encoder = ZlibEncoder::new(blob_object_file, Compression::default());
-        encoder.write_all(sha_input.as_bytes()).map_err(|e| e.to_string())?;
-        encoder.finish().map_err(|e| e.to_string())?;
+        let mut file = fs::File::open(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+        let size = file.metadata().map_err(|e| e.to_string())?.len();
+        let result = match self.odb {
+            Some(odb) if self.write => odb.write_stream(self.kind, size, &mut file),
+            _ => self.hash_kind().hash_stream(self.kind, size, &mut file, &mut io::sink()),
+        };
+        result.map_err(|e| format!("Could not hash {}: {}", path, e))
+    }
 
-        Ok(())
+    /// Unless `--literally` is given, anything but a blob must parse as the
+    /// requested type, so that malformed trees or commits are not stored.
+    fn hash_bytes(&self, data: &[u8]) -> Result<ObjectId, String> {
+        if !self.literally && self.kind != ObjectKind::Blob {
+            Object::parse(self.kind, data, self.hash_kind())
+                .map_err(|e| format!("Corrupt {}: {}", self.kind, e))?;
+        }
+        match self.odb {
+            Some(odb) if self.write => odb.write(self.kind, data),
+            _ => Ok(self.hash_kind().hash_object(self.kind, data)),
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,96 @@
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::object::HashKind;
+use crate::pack::index;
+use crate::pack::indexer;
+use crate::repo::Repository;
+
+pub struct IndexPack;
+
+impl IndexPack {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: index-pack [--stdin] [--fix-thin] [--object-format=<hash>] [-o <index-file>] [<pack-file>]";
+        let mut from_stdin = false;
+        let mut object_format: Option<HashKind> = None;
+        let mut fix_thin = false;
+        let mut index_path: Option<PathBuf> = None;
+        let mut pack_path: Option<PathBuf> = None;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--stdin" => from_stdin = true,
+                "--fix-thin" => fix_thin = true,
+                "-o" => index_path = Some(PathBuf::from(iter.next().ok_or(usage)?)),
+                _ if arg.starts_with("--object-format=") => {
+                    object_format = Some(HashKind::from_name(&arg["--object-format=".len()..])?);
+                }
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ if pack_path.is_none() => pack_path = Some(PathBuf::from(arg)),
+                _ => return Err(usage.to_string()),
+            }
+        }
+        if fix_thin && !from_stdin {
+            return Err("--fix-thin cannot be used without --stdin".to_string());
+        }
+
+        let pack = if from_stdin {
+            let mut pack = Vec::new();
+            std::io::stdin().read_to_end(&mut pack).map_err(|e| e.to_string())?;
+            pack
+        } else {
+            let path = pack_path.as_ref().ok_or(usage)?;
+            fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?
+        };
+
+        // Thin packs can only be completed from objects the repository has.
+        let repo = if fix_thin || (from_stdin && pack_path.is_none()) {
+            Some(Repository::discover()?)
+        } else {
+            None
+        };
+        // Neither the pack nor the index records its hash function, so it
+        // comes from the repository unless given explicitly.
+        let hash = match (&repo, object_format) {
+            (Some(repo), Some(hash)) if hash != repo.hash_kind() => {
+                return Err("--object-format does not match the repository's object format".to_string());
+            }
+            (Some(repo), _) => repo.hash_kind(),
+            (None, Some(hash)) => hash,
+            (None, None) => Repository::discover().map(|repo| repo.hash_kind()).unwrap_or_default(),
+        };
+        let (pack, indexed) = match &repo {
+            Some(repo) if fix_thin => indexer::index_with_bases(&repo.odb()?, pack)?,
+            _ => {
+                let indexed = indexer::index_pack(&pack, hash, &|_| None)?;
+                (pack, indexed)
+            }
+        };
+        let checksum = hex::encode(&indexed.checksum);
+
+        match (pack_path, repo) {
+            // A pack from stdin without a file name goes into the
+            // repository's own pack directory.
+            (None, Some(repo)) => {
+                indexer::write_pack(&repo.odb()?, &pack, &indexed)?;
+            }
+            (Some(pack_path), _) => {
+                if from_stdin {
+                    indexer::write_file(&pack_path, &pack)?;
+                }
+                let index_path = index_path.unwrap_or_else(|| pack_path.with_extension("idx"));
+                indexer::write_file(&index_path, &index::write_index(&indexed.entries, &indexed.checksum, indexed.hash))?;
+            }
+            (None, None) => return Err(usage.to_string()),
+        }
+
+        if from_stdin {
+            println!("pack\t{}", checksum);
+        } else {
+            println!("{}", checksum);
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,34 @@
-use std::fs;
+use std::env;
+
+use crate::config::Config;
+use crate::object::HashKind;
+use crate::repo::Repository;
 
 pub struct Init;
 
 impl Init {
-    pub fn run(_args: &[String]) -> Result<(), String> {
-        fs::create_dir(".git").map_err(|e| e.to_string())?;
-        fs::create_dir(".git/objects").map_err(|e| e.to_string())?;
-        fs::create_dir(".git/refs").map_err(|e| e.to_string())?;
-        fs::write(".git/HEAD", "ref: refs/heads/main\n").map_err(|e| e.to_string())?;
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: init [--object-format=<sha1|sha256>]";
+        let mut hash = match env::var("GIT_DEFAULT_HASH") {
+            Ok(name) => HashKind::from_name(&name)?,
+            Err(_) => HashKind::Sha1,
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if let Some(name) = arg.strip_prefix("--object-format=") {
+                hash = HashKind::from_name(name)?;
+            } else if arg == "--object-format" {
+                hash = HashKind::from_name(iter.next().ok_or(usage)?)?;
+            } else {
+                return Err(usage.to_string());
+            }
+        }
+
+        let config = Config::load_all(None)?;
+        let branch = config.get("init.defaultbranch").unwrap_or("main");
+        let cwd = env::current_dir().map_err(|e| e.to_string())?;
+        Repository::init(&cwd, branch, hash)?;
         println!("Initialized git directory");
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,473 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::date::{self, DateFormat};
+use crate::object::{Commit, ObjectId, ObjectKind, Signature};
+use crate::odb::{ObjectDatabase, DEFAULT_ABBREV};
+use crate::pattern::Pattern;
+use crate::refs;
+use crate::repo::Repository;
+use crate::revision::{RevisionArg, Revisions};
+use crate::revwalk::RevWalk;
+use crate::worktree;
+
+pub struct Log;
+
+/// How each commit is shown, as `--pretty` and `--format` pick it.
+enum Pretty {
+    Oneline,
+    Short,
+    Medium,
+    Full,
+    /// A `--format` template. With `tformat:` every commit's output ends in
+    /// a newline; with `format:` newlines only go between commits.
+    Format { template: String, terminated: bool },
+}
+
+struct Output {
+    pretty: Pretty,
+    abbrev_commit: bool,
+    date: DateFormat,
+}
+
+/// Which commits are shown of those the walk reaches.
+struct Filter {
+    authors: Vec<Pattern>,
+    greps: Vec<Pattern>,
+}
+
+impl Log {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let mut output = Output { pretty: Pretty::Medium, abbrev_commit: false, date: DateFormat::Default };
+        let mut max_count: Option<usize> = None;
+        let mut reverse = false;
+        let mut topo_order = false;
+        let mut first_parent = false;
+        let mut ignore_case = false;
+        let mut authors = Vec::new();
+        let mut greps = Vec::new();
+        let mut since = None;
+        let mut until = None;
+        let mut specs = Vec::new();
+        let mut paths = Vec::new();
+        let mut options_done = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                _ if options_done => paths.push(arg.as_str()),
+                "--" => options_done = true,
+                "--oneline" => {
+                    output.pretty = Pretty::Oneline;
+                    output.abbrev_commit = true;
+                }
+                "--abbrev-commit" => output.abbrev_commit = true,
+                "--no-abbrev-commit" => output.abbrev_commit = false,
+                "--pretty" => output.pretty = Pretty::Medium,
+                "--reverse" => reverse = true,
+                "--topo-order" => topo_order = true,
+                "--date-order" => topo_order = false,
+                "--first-parent" => first_parent = true,
+                "-i" | "--regexp-ignore-case" => ignore_case = true,
+                "-n" | "--max-count" => {
+                    let count = args.next().ok_or("option 'max-count' requires a value")?;
+                    max_count = Some(Self::count(count)?);
+                }
+                _ if arg.starts_with("--max-count=") => max_count = Some(Self::count(&arg["--max-count=".len()..])?),
+                _ if arg.starts_with("-n") => max_count = Some(Self::count(&arg[2..])?),
+                _ if arg.len() > 1 && arg.starts_with('-') && arg[1..].bytes().all(|b| b.is_ascii_digit()) => {
+                    max_count = Some(Self::count(&arg[1..])?);
+                }
+                _ if arg.starts_with("--pretty=") || arg.starts_with("--format=") => {
+                    output.pretty = Pretty::parse(arg.split_once('=').map_or("", |(_, value)| value))?;
+                }
+                _ if arg.starts_with("--date=") => output.date = DateFormat::from_name(&arg["--date=".len()..])?,
+                _ if arg.starts_with("--author=") => authors.push(&arg["--author=".len()..]),
+                _ if arg.starts_with("--grep=") => greps.push(&arg["--grep=".len()..]),
+                _ if arg.starts_with("--since=") || arg.starts_with("--after=") => {
+                    since = arg.split_once('=').map(|(_, date)| date::approxidate(date)).transpose()?;
+                }
+                _ if arg.starts_with("--until=") || arg.starts_with("--before=") => {
+                    until = arg.split_once('=').map(|(_, date)| date::approxidate(date)).transpose()?;
+                }
+                _ if arg.starts_with('-') => return Err(format!("unrecognized argument: {}", arg)),
+                _ => specs.push(arg.as_str()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let revisions = Revisions::new(&repo, &odb);
+        let mut walk = RevWalk::new(&odb);
+        if first_parent {
+            walk.first_parent();
+        }
+        if topo_order {
+            walk.topo_order();
+        }
+        if let Some(since) = since {
+            walk.set_since(since);
+        }
+        if let Some(until) = until {
+            walk.set_until(until);
+        }
+
+        // Without `--`, the first argument that is not a revision starts
+        // the paths, as long as each of them names something in the work
+        // tree.
+        let mut revision_args = Vec::new();
+        for (i, spec) in specs.iter().enumerate() {
+            match revisions.parse_arg(spec) {
+                Ok(arg) => revision_args.push((spec, arg)),
+                Err(e) => {
+                    for path in &specs[i..] {
+                        let full = repo.work_dir().join(worktree::bytes_path(&worktree::repo_path(&repo, path)?)?);
+                        if full.symlink_metadata().is_err() {
+                            return Err(match path == spec && !e.starts_with("ambiguous argument") {
+                                true => e,
+                                false => format!(
+                                    "ambiguous argument '{}': unknown revision or path not in the working tree.\n\
+                                     Use '--' to separate paths from revisions, like this:\n\
+                                     'git <command> [<revision>...] -- [<file>...]'",
+                                    path
+                                ),
+                            });
+                        }
+                    }
+                    paths.splice(0..0, specs[i..].iter().copied());
+                    break;
+                }
+            }
+        }
+        let mut repo_paths = Vec::new();
+        for path in paths {
+            repo_paths.push(worktree::repo_path(&repo, path)?);
+        }
+        if !repo_paths.is_empty() {
+            walk.set_paths(repo_paths);
+        }
+
+        if revision_args.is_empty() {
+            let head = refs::resolve(repo.git_dir(), "HEAD")?.ok_or_else(|| {
+                let branch = refs::follow_symbolic(repo.git_dir(), "HEAD").unwrap_or_else(|_| "HEAD".to_string());
+                format!("your current branch '{}' does not have any commits yet", refs::shorten_ref(&branch))
+            })?;
+            walk.push(&revisions.peel(&head, ObjectKind::Commit, "HEAD")?)?;
+        }
+        for (spec, arg) in revision_args {
+            let commit = |id: &ObjectId| revisions.peel(id, ObjectKind::Commit, spec);
+            match arg {
+                RevisionArg::Include(resolved) => walk.push(&commit(&resolved.id)?)?,
+                RevisionArg::Exclude(resolved) => walk.hide(&commit(&resolved.id)?)?,
+                RevisionArg::Range { from, to } => {
+                    walk.hide(&commit(&from.id)?)?;
+                    walk.push(&commit(&to.id)?)?;
+                }
+                RevisionArg::Symmetric { left, right, bases } => {
+                    walk.push(&commit(&left.id)?)?;
+                    walk.push(&commit(&right.id)?)?;
+                    for base in bases {
+                        walk.hide(&base)?;
+                    }
+                }
+            }
+        }
+
+        let filter = Filter {
+            authors: authors.iter().map(|p| Pattern::new(p, ignore_case)).collect::<Result<_, _>>()?,
+            greps: greps.iter().map(|p| Pattern::new(p, ignore_case)).collect::<Result<_, _>>()?,
+        };
+        let mut commits = Vec::new();
+        for item in walk {
+            if max_count.is_some_and(|max| commits.len() >= max) {
+                break;
+            }
+            let (id, commit) = item?;
+            if filter.matches(&commit) {
+                commits.push((id, commit));
+            }
+        }
+        if reverse {
+            commits.reverse();
+        }
+
+        let mut out = BufWriter::new(io::stdout().lock());
+        for (i, (id, commit)) in commits.iter().enumerate() {
+            let text = output.render(&odb, id, commit, i == 0)?;
+            out.write_all(&text).map_err(|e| e.to_string())?;
+        }
+        out.flush().map_err(|e| e.to_string())
+    }
+
+    fn count(text: &str) -> Result<usize, String> {
+        text.parse().map_err(|_| format!("'{}': not an integer", text))
+    }
+}
+
+impl Pretty {
+    /// A `--pretty` or `--format` value: a format name, `format:` or
+    /// `tformat:` and a template, or a bare template containing `%`.
+    fn parse(value: &str) -> Result<Pretty, String> {
+        match value {
+            "oneline" => Ok(Pretty::Oneline),
+            "short" => Ok(Pretty::Short),
+            "" | "medium" => Ok(Pretty::Medium),
+            "full" => Ok(Pretty::Full),
+            _ => {
+                if let Some(template) = value.strip_prefix("format:") {
+                    return Ok(Pretty::Format { template: template.to_string(), terminated: false });
+                }
+                match value.strip_prefix("tformat:") {
+                    Some(template) => Ok(Pretty::Format { template: template.to_string(), terminated: true }),
+                    None if value.contains('%') => Ok(Pretty::Format { template: value.to_string(), terminated: true }),
+                    None => Err(format!("invalid --pretty format: {}", value)),
+                }
+            }
+        }
+    }
+}
+
+impl Filter {
+    /// Each kind of filter given must match: `--author` against the
+    /// author's `Name <email>`, `--grep` against some line of the message.
+    fn matches(&self, commit: &Commit) -> bool {
+        let author = format!("{} <{}>", commit.author.name, commit.author.email);
+        let message = String::from_utf8_lossy(&commit.message);
+        (self.authors.is_empty() || self.authors.iter().any(|p| p.is_match(&author)))
+            && (self.greps.is_empty() || self.greps.iter().any(|p| message.lines().any(|line| p.is_match(line))))
+    }
+}
+
+impl Output {
+    /// One commit's output, including what separates it from the one
+    /// before.
+    fn render(&self, odb: &ObjectDatabase, id: &ObjectId, commit: &Commit, first: bool) -> Result<Vec<u8>, String> {
+        let hash = match self.abbrev_commit {
+            true => odb.abbreviate(id, DEFAULT_ABBREV)?,
+            false => id.to_hex(),
+        };
+        let (subject, _) = split_message(&commit.message);
+        let mut out = Vec::new();
+        match &self.pretty {
+            Pretty::Oneline => out.extend_from_slice(format!("{} {}\n", hash, subject).as_bytes()),
+            Pretty::Format { template, terminated } => {
+                if !first && !terminated {
+                    out.push(b'\n');
+                }
+                out.extend_from_slice(&self.expand(odb, template, id, commit)?);
+                if *terminated {
+                    out.push(b'\n');
+                }
+            }
+            Pretty::Short | Pretty::Medium | Pretty::Full => {
+                let mut text = String::new();
+                if !first {
+                    text.push('\n');
+                }
+                text.push_str(&format!("commit {}\n", hash));
+                if commit.parents.len() > 1 {
+                    let parents: Vec<String> = commit
+                        .parents
+                        .iter()
+                        .map(|parent| odb.abbreviate(parent, DEFAULT_ABBREV))
+                        .collect::<Result<_, _>>()?;
+                    text.push_str(&format!("Merge: {}\n", parents.join(" ")));
+                }
+                text.push_str(&format!("Author: {}\n", identity(&commit.author)));
+                match self.pretty {
+                    Pretty::Medium => text.push_str(&format!("Date:   {}\n", self.date(&commit.author))),
+                    Pretty::Full => text.push_str(&format!("Commit: {}\n", identity(&commit.committer))),
+                    _ => {}
+                }
+                text.push('\n');
+                // The short format shows the subject paragraph as written.
+                let message = String::from_utf8_lossy(&commit.message);
+                let lines = message.trim_end_matches('\n').lines().skip_while(|line| line.trim().is_empty());
+                let shown: Vec<&str> = match self.pretty {
+                    Pretty::Short => lines.take_while(|line| !line.trim().is_empty()).collect(),
+                    _ => lines.collect(),
+                };
+                for line in shown {
+                    text.push_str(&format!("    {}\n", line));
+                }
+                out.extend_from_slice(text.as_bytes());
+            }
+        }
+        Ok(out)
+    }
+
+    /// Fills in a `--format` template. Unknown placeholders are kept as
+    /// written, as git does. The result is bytes, since `%xNN` and `%B`
+    /// need not be UTF-8.
+    fn expand(&self, odb: &ObjectDatabase, template: &str, id: &ObjectId, commit: &Commit) -> Result<Vec<u8>, String> {
+        let (subject, body) = split_message(&commit.message);
+        let abbrev_all = |ids: &[ObjectId]| -> Result<String, String> {
+            let ids: Vec<String> =
+                ids.iter().map(|id| odb.abbreviate(id, DEFAULT_ABBREV)).collect::<Result<_, _>>()?;
+            Ok(ids.join(" "))
+        };
+        let mut out = Vec::new();
+        let mut rest = template;
+        while let Some(percent) = rest.find('%') {
+            out.extend_from_slice(&rest.as_bytes()[..percent]);
+            rest = &rest[percent + 1..];
+            let (expansion, len) = match rest.as_bytes() {
+                [b'H', ..] => (id.to_hex(), 1),
+                [b'h', ..] => (odb.abbreviate(id, DEFAULT_ABBREV)?, 1),
+                [b'T', ..] => (commit.tree.to_hex(), 1),
+                [b't', ..] => (odb.abbreviate(&commit.tree, DEFAULT_ABBREV)?, 1),
+                [b'P', ..] => (commit.parents.iter().map(ObjectId::to_hex).collect::<Vec<_>>().join(" "), 1),
+                [b'p', ..] => (abbrev_all(&commit.parents)?, 1),
+                [b'a' | b'c', field, ..] => {
+                    let who = if rest.starts_with('a') { &commit.author } else { &commit.committer };
+                    match field {
+                        b'n' => (who.name.clone(), 2),
+                        b'e' => (who.email.clone(), 2),
+                        b'd' => (self.date(who), 2),
+                        b't' => (who.time.to_string(), 2),
+                        b'i' => (date::format_date(who.time, who.offset, DateFormat::Iso), 2),
+                        _ => ("%".to_string(), 0),
+                    }
+                }
+                [b's', ..] => (subject.clone(), 1),
+                [b'b', ..] => (body.clone(), 1),
+                [b'B', ..] => {
+                    out.extend_from_slice(&commit.message);
+                    (String::new(), 1)
+                }
+                [b'n', ..] => ("\n".to_string(), 1),
+                [b'x', high, low, ..] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                    out.push(u8::from_str_radix(&rest[1..3], 16).map_err(|e| e.to_string())?);
+                    (String::new(), 3)
+                }
+                [b'%', ..] => ("%".to_string(), 1),
+                _ => ("%".to_string(), 0),
+            };
+            out.extend_from_slice(expansion.as_bytes());
+            rest = &rest[len..];
+        }
+        out.extend_from_slice(rest.as_bytes());
+        Ok(out)
+    }
+
+    fn date(&self, who: &Signature) -> String {
+        date::format_date(who.time, who.offset, self.date)
+    }
+}
+
+fn identity(who: &Signature) -> String {
+    format!("{} <{}>", who.name, who.email)
+}
+
+/// The subject, the message's first paragraph joined onto one line, and the
+/// body, everything after the blank lines that follow it.
+fn split_message(message: &[u8]) -> (String, String) {
+    let message = String::from_utf8_lossy(message);
+    let mut lines = message.lines().skip_while(|line| line.trim().is_empty()).peekable();
+    let mut subject = Vec::new();
+    while let Some(line) = lines.next_if(|line| !line.trim().is_empty()) {
+        subject.push(line.trim());
+    }
+    let body: Vec<&str> = lines.skip_while(|line| line.trim().is_empty()).collect();
+    let body = match body.is_empty() {
+        true => String::new(),
+        false => format!("{}\n", body.join("\n")),
+    };
+    (subject.join(" "), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+    use crate::test_support::TempRepo;
+
+    fn output(pretty: &str) -> Output {
+        Output { pretty: Pretty::parse(pretty).unwrap(), abbrev_commit: false, date: DateFormat::Default }
+    }
+
+    /// A root commit and a child whose message has a subject paragraph
+    /// over two lines and a body.
+    fn history(t: &TempRepo) -> (ObjectId, ObjectId) {
+        let tree = t.tree(&[("README", b"hello\n")]);
+        let root = t.commit(&tree, &[], 100, "root\n");
+        let child = t.commit(&tree, &[&root], 200, "first line\nsecond line\n\nbody one\n\nbody two\n");
+        (root, child)
+    }
+
+    fn expand(t: &TempRepo, template: &str, id: &ObjectId) -> Vec<u8> {
+        let commit = t.odb.read_commit(id).unwrap();
+        output("medium").expand(&t.odb, template, id, &commit).unwrap()
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        let t = TempRepo::new();
+        let (root, child) = history(&t);
+        let commit = t.odb.read_commit(&child).unwrap();
+        let short = |id: &ObjectId| id.to_hex()[..7].to_string();
+
+        let cases = [
+            ("%H", child.to_hex()),
+            ("%h", short(&child)),
+            ("%T", commit.tree.to_hex()),
+            ("%t", short(&commit.tree)),
+            ("%P", root.to_hex()),
+            ("%p", short(&root)),
+            ("%an <%ae> %at", "A U Thor <author@example.com> 200".to_string()),
+            ("%cn %ct", "A U Thor 200".to_string()),
+            ("%ad", "Thu Jan 1 00:03:20 1970 +0000".to_string()),
+            ("%ai", "1970-01-01 00:03:20 +0000".to_string()),
+            ("%s", "first line second line".to_string()),
+            ("%b", "body one\n\nbody two\n".to_string()),
+            ("%B", "first line\nsecond line\n\nbody one\n\nbody two\n".to_string()),
+            ("a%nb %% %x41", "a\nb % A".to_string()),
+            // Unknown placeholders, and a `%` at the end, are kept.
+            ("%q %aX %x4 %", "%q %aX %x4 %".to_string()),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(String::from_utf8(expand(&t, template, &child)).unwrap(), expected, "{}", template);
+        }
+        assert_eq!(expand(&t, "[%P]", &root), b"[]");
+    }
+
+    #[test]
+    fn hex_escapes_and_raw_messages_are_bytes() {
+        let t = TempRepo::new();
+        let (_, child) = history(&t);
+        assert_eq!(expand(&t, "%xe9%x00%xFF", &child), [0xe9, 0x00, 0xff]);
+
+        let latin1 = t.odb.read_commit(&child).unwrap();
+        let message = b"caf\xe9\n".to_vec();
+        let commit = Commit::new(latin1.tree, Vec::new(), latin1.author, latin1.committer, message.clone());
+        let id = t.odb.write_object(&Object::Commit(commit)).unwrap();
+        assert_eq!(expand(&t, "%B", &id), message);
+    }
+
+    #[test]
+    fn format_and_tformat_separate_commits() {
+        let t = TempRepo::new();
+        let (root, child) = history(&t);
+        let render = |pretty: &str| {
+            let output = output(pretty);
+            let mut out = Vec::new();
+            for (i, id) in [&child, &root].into_iter().enumerate() {
+                let commit = t.odb.read_commit(id).unwrap();
+                out.extend(output.render(&t.odb, id, &commit, i == 0).unwrap());
+            }
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render("format:%s"), "first line second line\nroot");
+        assert_eq!(render("tformat:%s"), "first line second line\nroot\n");
+        assert_eq!(render("%s"), "first line second line\nroot\n");
+        assert_eq!(render("oneline"), format!("{} first line second line\n{} root\n", child, root));
+        assert_eq!(
+            render("short"),
+            format!(
+                "commit {}\nAuthor: A U Thor <author@example.com>\n\n    first line\n    second line\n\n\
+                 commit {}\nAuthor: A U Thor <author@example.com>\n\n    root\n",
+                child, root
+            )
+        );
+        assert!(Pretty::parse("nonsense").is_err());
+    }
+}
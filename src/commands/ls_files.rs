@@ -0,0 +1,128 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+use crate::index::Index;
+use crate::quote::quote_path;
+use crate::repo::Repository;
+use crate::worktree::{self, pathspec_matches};
+
+pub struct LsFiles;
+
+struct Output {
+    /// Where paths are shown relative to: the current directory, or the
+    /// top of the work tree with `--full-name`.
+    prefix: Vec<u8>,
+    nul_terminated: bool,
+}
+
+impl LsFiles {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: ls-files [-c] [-s] [-u] [-d] [-m] [-o] [-z] [--full-name] [--] [<pathspec>...]";
+        let mut cached = false;
+        let mut stage = false;
+        let mut unmerged = false;
+        let mut deleted = false;
+        let mut modified = false;
+        let mut others = false;
+        let mut nul_terminated = false;
+        let mut full_name = false;
+        let mut pathspecs = Vec::new();
+        let mut options_done = false;
+
+        for arg in args {
+            match arg.as_str() {
+                _ if options_done || !arg.starts_with('-') => pathspecs.push(arg.as_str()),
+                "--" => options_done = true,
+                "-c" | "--cached" => cached = true,
+                "-s" | "--stage" => stage = true,
+                "-u" | "--unmerged" => unmerged = true,
+                "-d" | "--deleted" => deleted = true,
+                "-m" | "--modified" => modified = true,
+                "-o" | "--others" => others = true,
+                "-z" => nul_terminated = true,
+                "--full-name" => full_name = true,
+                _ => return Err(usage.to_string()),
+            }
+        }
+        if !(stage || unmerged || deleted || modified || others) {
+            cached = true;
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let index = Index::load(&repo.index_path(), repo.hash_kind())?;
+
+        let cwd = worktree::cwd_prefix(&repo)?;
+        let mut specs = Vec::new();
+        for arg in &pathspecs {
+            specs.push(worktree::repo_path(&repo, arg)?);
+        }
+        if specs.is_empty() {
+            specs.push(cwd.strip_suffix(b"/").unwrap_or(&cwd).to_vec());
+        }
+        let selected = |path: &[u8]| specs.iter().any(|spec| pathspec_matches(spec, path));
+
+        let output = Output { prefix: if full_name { Vec::new() } else { cwd.clone() }, nul_terminated };
+        let mut out = BufWriter::new(io::stdout().lock());
+
+        if others {
+            for spec in &specs {
+                let full = repo.work_dir().join(worktree::bytes_path(spec)?);
+                let files = match fs::symlink_metadata(&full) {
+                    Ok(metadata) if metadata.is_dir() => worktree::list_files(repo.work_dir(), spec)?,
+                    Ok(_) => vec![spec.clone()],
+                    Err(_) => Vec::new(),
+                };
+                for path in files.iter().filter(|path| !index.contains(path)) {
+                    output.path_line(b"", path, &mut out)?;
+                }
+            }
+        }
+
+        if cached || stage || unmerged {
+            for entry in index.entries().iter().filter(|entry| selected(&entry.path)) {
+                if unmerged && entry.stage == 0 {
+                    continue;
+                }
+                if stage || unmerged {
+                    let line = format!("{:06o} {} {}\t", entry.mode, entry.id, entry.stage);
+                    output.path_line(line.as_bytes(), &entry.path, &mut out)?;
+                } else {
+                    output.path_line(b"", &entry.path, &mut out)?;
+                }
+            }
+        }
+
+        if deleted || modified {
+            for entry in index.entries().iter().filter(|entry| selected(&entry.path)) {
+                let full = repo.work_dir().join(worktree::bytes_path(&entry.path)?);
+                let metadata = fs::symlink_metadata(&full).ok().filter(|metadata| !metadata.is_dir());
+                if deleted && metadata.is_none() {
+                    output.path_line(b"", &entry.path, &mut out)?;
+                }
+                let changed = match &metadata {
+                    Some(metadata) => worktree::is_modified(&odb, &index, entry, &full, metadata)?,
+                    None => true,
+                };
+                if modified && changed {
+                    output.path_line(b"", &entry.path, &mut out)?;
+                }
+            }
+        }
+
+        out.flush().map_err(|e| e.to_string())
+    }
+}
+
+impl Output {
+    fn path_line(&self, lead: &[u8], path: &[u8], out: &mut impl Write) -> Result<(), String> {
+        let path = worktree::relative_to(path, &self.prefix);
+        let mut line = lead.to_vec();
+        match self.nul_terminated {
+            true => line.extend_from_slice(&path),
+            false => line.extend_from_slice(quote_path(&path).as_bytes()),
+        }
+        line.push(if self.nul_terminated { 0 } else { b'\n' });
+        out.write_all(&line).map_err(|e| e.to_string())
+    }
+}
@@ -1,57 +1,235 @@
-use std::fs;
-use flate2::read::ZlibDecoder;
-use std::io::prelude::*;
+use std::io::{self, BufWriter, Write};
+
+use crate::object::{ObjectId, ObjectKind, TreeEntry};
+use crate::odb::{ObjectDatabase, DEFAULT_ABBREV};
+use crate::quote::quote_path;
+use crate::repo::Repository;
+use crate::revision::Revisions;
+use crate::worktree;
 
 pub struct LsTree;
 
+const DEFAULT_FORMAT: &str = "%(objectmode) %(objecttype) %(objectname)%x09%(path)";
+const LONG_FORMAT: &str = "%(objectmode) %(objecttype) %(objectname) %(objectsize:padded)%x09%(path)";
+
+/// How a path relates to the pathspecs given on the command line.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Match {
+    /// The path is a pathspec or lies below one.
+    Inside,
+    /// The path is a directory on the way to a pathspec.
+    Leading,
+    None,
+}
+
+struct Listing<'a> {
+    odb: &'a ObjectDatabase,
+    pathspecs: Vec<Vec<u8>>,
+    recursive: bool,
+    show_trees: bool,
+    only_trees: bool,
+    nul_terminated: bool,
+    abbrev: Option<usize>,
+    format: String,
+    /// The current directory, which printed paths are relative to; empty
+    /// to print them from the top of the tree.
+    prefix: Vec<u8>,
+}
+
 impl LsTree {
     pub fn run(args: &[String]) -> Result<(), String> {
-        if args.is_empty() {
-            return Err("Usage: ls-tree <tree_sha>".to_string());
+        let usage = "Usage: ls-tree [-d] [-r] [-t] [-l] [-z] [--name-only] [--object-only] \
+                     [--abbrev[=<n>]] [--full-name] [--full-tree] [--format=<format>] <tree-ish> [<path>...]";
+        let mut recursive = false;
+        let mut show_trees = false;
+        let mut only_trees = false;
+        let mut long = false;
+        let mut nul_terminated = false;
+        let mut full_name = false;
+        let mut full_tree = false;
+        let mut abbrev = None;
+        let mut format: Option<String> = None;
+        let mut positional = Vec::new();
+
+        for arg in args {
+            match arg.as_str() {
+                "-r" => recursive = true,
+                "-t" => show_trees = true,
+                "-d" => only_trees = true,
+                "-l" | "--long" => long = true,
+                "-z" => nul_terminated = true,
+                "--name-only" | "--name-status" => format = Some("%(path)".to_string()),
+                "--object-only" => format = Some("%(objectname)".to_string()),
+                "--full-name" => full_name = true,
+                "--full-tree" => full_tree = true,
+                "--abbrev" => abbrev = Some(DEFAULT_ABBREV),
+                _ if arg.starts_with("--abbrev=") => {
+                    abbrev = Some(arg["--abbrev=".len()..].parse().map_err(|_| usage.to_string())?);
+                }
+                _ if arg.starts_with("--format=") => format = Some(arg["--format=".len()..].to_string()),
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => positional.push(arg),
+            }
         }
-        let tree_sha = &args[1];
-        let path = format!(".git/objects/{}/{}", &tree_sha[..2], &tree_sha[2..]);
-        let content = fs::read(path).map_err(|e| e.to_string())?;
-        let mut decompressed_data = ZlibDecoder::new(&content[..]);
-        let mut tree_file_contents_vec = Vec::new();
-        decompressed_data.read_to_end(&mut tree_file_contents_vec)
-            .map_err(|e| e.to_string())?;
-
-        let entries = Self::parse_tree_object(&tree_file_contents_vec);
-
-        for name in entries {
-            println!("{}", name);
+        let (tree_ish, paths) = positional.split_first().ok_or(usage)?;
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let tree = Revisions::new(&repo, &odb).resolve_as(tree_ish, ObjectKind::Tree)?;
+
+        // Paths are given and printed relative to the current directory,
+        // which is itself the pathspec when none are given. --full-tree
+        // works from the top of the tree instead, and --full-name prints
+        // from there.
+        let prefix = if full_tree { Vec::new() } else { worktree::cwd_prefix(&repo)? };
+        let mut pathspecs = Vec::new();
+        for path in paths {
+            let mut spec = match full_tree {
+                true => worktree::top_path(&repo, path)?,
+                false => worktree::repo_path(&repo, path)?,
+            };
+            let names_dir = path.ends_with('/') || matches!(path.rsplit('/').next(), Some("." | ".."));
+            if names_dir && !spec.is_empty() {
+                spec.push(b'/');
+            }
+            pathspecs.push(spec);
         }
-        Ok(())
+        if paths.is_empty() && !prefix.is_empty() {
+            pathspecs.push(prefix.clone());
+        }
+
+        let listing = Listing {
+            odb: &odb,
+            pathspecs,
+            recursive,
+            // -d lists trees in place of their contents, so with -r they are
+            // shown even when recursing.
+            show_trees: show_trees || (only_trees && recursive),
+            only_trees,
+            nul_terminated,
+            abbrev,
+            format: format.unwrap_or_else(|| (if long { LONG_FORMAT } else { DEFAULT_FORMAT }).to_string()),
+            prefix: if full_name { Vec::new() } else { prefix },
+        };
+        let mut out = BufWriter::new(io::stdout().lock());
+        listing.list(&tree, b"", &mut out)?;
+        out.flush().map_err(|e| e.to_string())
     }
+}
 
-    fn parse_tree_object(content: &[u8]) -> Vec<String> {
-        let mut entries = Vec::new();
-        let mut i = 0;
+impl Listing<'_> {
+    /// Lists `tree`, whose entries live under `prefix`. A tree is shown as a
+    /// single entry unless recursion was asked for or a pathspec reaches
+    /// inside it.
+    fn list(&self, tree: &ObjectId, prefix: &[u8], out: &mut impl Write) -> Result<(), String> {
+        for entry in self.odb.read_tree(tree)?.entries {
+            let mut path = prefix.to_vec();
+            path.extend_from_slice(&entry.name);
 
-        while i < content.len() && content[i] != 0 {
-            i += 1;
+            let matched = self.match_path(&path);
+            if matched == Match::None {
+                continue;
+            }
+            if entry.is_tree() {
+                let recurse = matched == Match::Leading || self.recursive;
+                if !recurse || self.show_trees {
+                    self.show(&entry, &path, out)?;
+                }
+                if recurse {
+                    path.push(b'/');
+                    self.list(&entry.id, &path, out)?;
+                }
+            } else if matched == Match::Inside && !self.only_trees {
+                self.show(&entry, &path, out)?;
+            }
         }
-        i += 1;
+        Ok(())
+    }
 
-        while i < content.len() {
-            let mut name = Vec::new();
-            while i < content.len() && content[i] != b' ' {
-                i += 1;
+    fn match_path(&self, path: &[u8]) -> Match {
+        if self.pathspecs.is_empty() {
+            return Match::Inside;
+        }
+        let mut best = Match::None;
+        for spec in &self.pathspecs {
+            // A trailing slash asks for the directory's contents only.
+            let (spec, contents_only) = match spec.strip_suffix(b"/") {
+                Some(dir) => (dir, true),
+                None => (spec.as_slice(), false),
+            };
+            // The top of the tree itself, as `.` there names it.
+            if spec.is_empty() {
+                return Match::Inside;
+            }
+            let below = path.len() > spec.len() && path.starts_with(spec) && path[spec.len()] == b'/';
+            if below || (path == spec && !contents_only) {
+                return Match::Inside;
             }
-            i += 1;
-            while i < content.len() && content[i] != 0 {
-                name.push(content[i]);
-                i += 1;
+            let on_the_way = (spec.len() > path.len() && spec.starts_with(path) && spec[path.len()] == b'/')
+                || (path == spec && contents_only);
+            if on_the_way {
+                best = Match::Leading;
             }
-            i += 1;
-            i += 20;
+        }
+        best
+    }
 
-            if let Ok(name_str) = String::from_utf8(name) {
-                entries.push(name_str);
+    fn show(&self, entry: &TreeEntry, path: &[u8], out: &mut impl Write) -> Result<(), String> {
+        let mut line = Vec::new();
+        let mut rest = self.format.as_str();
+        while let Some(pos) = rest.find('%') {
+            line.extend_from_slice(&rest.as_bytes()[..pos]);
+            rest = &rest[pos + 1..];
+            if let Some(after) = rest.strip_prefix('%') {
+                line.push(b'%');
+                rest = after;
+            } else if let Some(byte) = hex_escape(rest) {
+                line.push(byte);
+                rest = &rest[3..];
+            } else if let Some(end) = rest.strip_prefix('(').and_then(|atom| atom.find(')')) {
+                self.expand(&rest[1..end + 1], entry, path, &mut line)?;
+                rest = &rest[end + 2..];
+            } else {
+                line.push(b'%');
             }
         }
+        line.extend_from_slice(rest.as_bytes());
+        line.push(if self.nul_terminated { 0 } else { b'\n' });
+        out.write_all(&line).map_err(|e| e.to_string())
+    }
 
-        entries
+    fn expand(&self, atom: &str, entry: &TreeEntry, path: &[u8], line: &mut Vec<u8>) -> Result<(), String> {
+        let text = match atom {
+            "objectmode" => format!("{:06o}", entry.mode),
+            "objecttype" => entry.kind().to_string(),
+            "objectname" => match self.abbrev {
+                Some(len) => self.odb.abbreviate(&entry.id, len)?,
+                None => entry.id.to_hex(),
+            },
+            "objectsize" | "objectsize:padded" => {
+                let size = match entry.kind() {
+                    ObjectKind::Blob => self.odb.read_header(&entry.id)?.1.to_string(),
+                    _ => "-".to_string(),
+                };
+                if atom == "objectsize" { size } else { format!("{:>7}", size) }
+            }
+            "path" => {
+                let path = worktree::relative_to(path, &self.prefix);
+                match self.nul_terminated {
+                    true => line.extend_from_slice(&path),
+                    false => line.extend_from_slice(quote_path(&path).as_bytes()),
+                }
+                return Ok(());
+            }
+            _ => return Err(format!("bad ls-tree format: unknown element %({})", atom)),
+        };
+        line.extend_from_slice(text.as_bytes());
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// The byte named by a `%xNN` escape, given the text after the `%`.
+fn hex_escape(text: &str) -> Option<u8> {
+    let hex = text.strip_prefix('x')?.get(..2)?;
+    u8::from_str_radix(hex, 16).ok()
+}
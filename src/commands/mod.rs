@@ -4,4 +4,23 @@ pub mod hash_object;
 pub mod ls_tree;
 pub mod commit_tree;
 pub mod write_tree;
-pub mod clone;
\ No newline at end of file
+pub mod clone;
+pub mod index_pack;
+pub mod repack;
+pub mod verify_pack;
+pub mod show_index;
+pub mod fsck;
+pub mod rev_parse;
+pub mod add;
+pub mod ls_files;
+pub mod read_tree;
+pub mod rm;
+pub mod update_index;
+pub mod config;
+pub mod update_ref;
+pub mod symbolic_ref;
+pub mod show_ref;
+pub mod pack_refs;
+pub mod reflog;
+pub mod commit;
+pub mod log;
@@ -0,0 +1,23 @@
+use crate::refs;
+use crate::repo::Repository;
+
+pub struct PackRefs;
+
+impl PackRefs {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: pack-refs [--all] [--no-prune]";
+        let mut all = false;
+        let mut prune = true;
+        for arg in args {
+            match arg.as_str() {
+                "--all" => all = true,
+                "--prune" => prune = true,
+                "--no-prune" => prune = false,
+                _ => return Err(usage.to_string()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        refs::pack_refs(repo.git_dir(), &repo.odb()?, all, prune)
+    }
+}
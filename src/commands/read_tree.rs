@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use crate::index::{self, Index, Stat};
+use crate::lockfile::LockFile;
+use crate::object::{ObjectId, ObjectKind};
+use crate::repo::Repository;
+use crate::revision::Revisions;
+
+pub struct ReadTree;
+
+impl ReadTree {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: read-tree [-m] [--prefix=<prefix>/] (--empty | <tree-ish>)";
+        let mut merge = false;
+        let mut empty = false;
+        let mut prefix: Option<Vec<u8>> = None;
+        let mut trees = Vec::new();
+
+        for arg in args {
+            match arg.as_str() {
+                "-m" => merge = true,
+                "--empty" => empty = true,
+                _ if arg.starts_with("--prefix=") => prefix = Some(arg.as_bytes()["--prefix=".len()..].to_vec()),
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => trees.push(arg),
+            }
+        }
+        if trees.len() > 1 {
+            return Err("read-tree can only read a single tree; merging trees is not supported".to_string());
+        }
+        if empty == (trees.len() == 1) {
+            return Err(usage.to_string());
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let lock = LockFile::acquire(&repo.index_path())?;
+        let mut index = Index::load(&repo.index_path(), repo.hash_kind())?;
+        if merge && index.has_unmerged() {
+            return Err("you need to resolve your current index first".to_string());
+        }
+
+        let tree: Option<ObjectId> = match trees.first() {
+            Some(spec) => Some(Revisions::new(&repo, &odb).resolve_as(spec, ObjectKind::Tree)?),
+            None => None,
+        };
+
+        match prefix {
+            Some(mut prefix) => {
+                if !prefix.is_empty() && !prefix.ends_with(b"/") {
+                    prefix.push(b'/');
+                }
+                if index.entries().iter().any(|entry| entry.path.starts_with(&prefix)) {
+                    return Err(format!("subdirectory '{}' already exists.", String::from_utf8_lossy(&prefix)));
+                }
+                if let Some(tree) = tree {
+                    for entry in index::tree_entries(&odb, &tree, &prefix)? {
+                        index.add(entry);
+                    }
+                }
+            }
+            None => {
+                let mut entries = match tree {
+                    Some(tree) => index::tree_entries(&odb, &tree, b"")?,
+                    None => Vec::new(),
+                };
+                // With -m, paths staged with the same content keep their
+                // stat data, so they are not seen as modified afterwards.
+                if merge {
+                    let old: HashMap<&[u8], (u32, &ObjectId, Stat)> = index
+                        .entries()
+                        .iter()
+                        .map(|entry| (entry.path.as_slice(), (entry.mode, &entry.id, entry.stat)))
+                        .collect();
+                    for entry in &mut entries {
+                        if let Some((mode, id, stat)) = old.get(entry.path.as_slice()) {
+                            if *mode == entry.mode && **id == entry.id {
+                                entry.stat = *stat;
+                            }
+                        }
+                    }
+                }
+                index.replace_entries(entries);
+            }
+        }
+        index.write(lock)
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::io::{self, BufWriter, Write};
+
+use crate::date;
+use crate::object::{ObjectId, ObjectKind};
+use crate::odb::{ObjectDatabase, DEFAULT_ABBREV};
+use crate::reachable;
+use crate::reflog::{self, ReflogEntry};
+use crate::refs;
+use crate::repo::Repository;
+
+pub struct Reflog;
+
+/// Entries older than `total` are dropped, and so are entries older than
+/// `unreachable` whose commits the ref can no longer reach.
+struct Expiry {
+    total: i64,
+    unreachable: i64,
+}
+
+impl Reflog {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let repo = Repository::discover()?;
+        match args.first().map(String::as_str) {
+            Some("show") => Self::show(&repo, &args[1..]),
+            Some("expire") => Self::expire(&repo, &args[1..]),
+            Some("delete") => Self::delete(&repo, &args[1..]),
+            Some("exists") => match &args[1..] {
+                [name] if reflog::exists(repo.git_dir(), name) => Ok(()),
+                [_] => std::process::exit(1),
+                _ => Err("Usage: reflog exists <ref>".to_string()),
+            },
+            _ => Self::show(&repo, args),
+        }
+    }
+
+    /// Lists a reflog newest first as `<id> <ref>@{<n>}: <message>`.
+    fn show(repo: &Repository, args: &[String]) -> Result<(), String> {
+        let name = match args {
+            [] => "HEAD",
+            [name] => name.as_str(),
+            _ => return Err("Usage: reflog [show] [<ref>]".to_string()),
+        };
+        let git_dir = repo.git_dir();
+        let Some(full) = reflog::dwim(git_dir, name) else {
+            // A ref that exists but keeps no reflog has nothing to show.
+            return match refs::dwim_ref(git_dir, name)? {
+                Some(_) => Ok(()),
+                None => Err(format!("ambiguous argument '{}': unknown revision or path not in the working tree.", name)),
+            };
+        };
+
+        let odb = repo.odb()?;
+        let mut out = BufWriter::new(io::stdout().lock());
+        for (n, entry) in reflog::read(git_dir, &full)?.iter().rev().enumerate() {
+            let line = format!(
+                "{} {}@{{{}}}: {}\n",
+                odb.abbreviate(&entry.new, DEFAULT_ABBREV)?,
+                name,
+                n,
+                entry.message
+            );
+            out.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+        }
+        out.flush().map_err(|e| e.to_string())
+    }
+
+    fn expire(repo: &Repository, args: &[String]) -> Result<(), String> {
+        let usage = "Usage: reflog expire [--expire=<time>] [--expire-unreachable=<time>] [--rewrite] \
+                     [-n | --dry-run] [--all] <refs>...";
+        let config = repo.config()?;
+        let mut expiry = Expiry {
+            total: Self::expiry_date(config.get("gc.reflogexpire").unwrap_or("90.days.ago"))?,
+            unreachable: Self::expiry_date(config.get("gc.reflogexpireunreachable").unwrap_or("30.days.ago"))?,
+        };
+        let mut rewrite = false;
+        let mut dry_run = false;
+        let mut all = false;
+        let mut names = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--rewrite" => rewrite = true,
+                "-n" | "--dry-run" => dry_run = true,
+                "--all" => all = true,
+                "--verbose" | "--updateref" | "--stale-fix" => {}
+                _ if arg.starts_with("--expire=") => {
+                    expiry.total = Self::expiry_date(&arg["--expire=".len()..])?;
+                }
+                _ if arg.starts_with("--expire-unreachable=") => {
+                    expiry.unreachable = Self::expiry_date(&arg["--expire-unreachable=".len()..])?;
+                }
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => names.push(arg.clone()),
+            }
+        }
+
+        let git_dir = repo.git_dir();
+        if all {
+            names.extend(reflog::list(git_dir)?);
+        } else if names.is_empty() {
+            return Err("no reflog specified to expire".to_string());
+        }
+
+        let odb = repo.odb()?;
+        for name in names {
+            let full = reflog::dwim(git_dir, &name).ok_or_else(|| format!("{} points nowhere!", name))?;
+            let entries = reflog::read(git_dir, &full)?;
+            let reachable = Self::reachable_from_tips(repo, &odb, &full)?;
+            let kept: Vec<ReflogEntry> = entries
+                .into_iter()
+                .filter(|entry| !Self::is_expired(&odb, entry, &expiry, &reachable))
+                .collect();
+            if !dry_run {
+                reflog::rewrite(git_dir, &full, &Self::rewritten(kept, rewrite))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops `<ref>@{<n>}` entries, counting from the newest as `show` does.
+    fn delete(repo: &Repository, args: &[String]) -> Result<(), String> {
+        let usage = "Usage: reflog delete [--rewrite] [-n | --dry-run] <ref>@{<n>}...";
+        let mut rewrite = false;
+        let mut dry_run = false;
+        let mut specs = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--rewrite" => rewrite = true,
+                "-n" | "--dry-run" => dry_run = true,
+                "--verbose" | "--updateref" => {}
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => specs.push(arg.as_str()),
+            }
+        }
+        if specs.is_empty() {
+            return Err("no reflog specified to delete".to_string());
+        }
+
+        let git_dir = repo.git_dir();
+        for spec in specs {
+            let (name, n) = spec
+                .strip_suffix('}')
+                .and_then(|rest| rest.rsplit_once("@{"))
+                .and_then(|(name, n)| Some((name, n.parse::<usize>().ok()?)))
+                .ok_or_else(|| format!("not a reflog: {}", spec))?;
+            let full = match name {
+                "" => refs::follow_symbolic(git_dir, "HEAD")?,
+                _ => reflog::dwim(git_dir, name).ok_or_else(|| format!("{}: not a reflog", spec))?,
+            };
+
+            let mut entries = reflog::read(git_dir, &full)?;
+            if n >= entries.len() {
+                continue;
+            }
+            entries.remove(entries.len() - 1 - n);
+            if !dry_run {
+                reflog::rewrite(git_dir, &full, &Self::rewritten(entries, rewrite))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `never` or `false` keep everything and `all` or `now` expire
+    /// everything; anything else is an approximate date.
+    fn expiry_date(text: &str) -> Result<i64, String> {
+        match text {
+            "never" | "false" => Ok(i64::MIN),
+            "all" | "now" => Ok(i64::MAX),
+            _ => date::approxidate(text).map_err(|_| format!("'{}' is not a valid timestamp", text)),
+        }
+    }
+
+    fn is_expired(
+        odb: &ObjectDatabase,
+        entry: &ReflogEntry,
+        expiry: &Expiry,
+        reachable: &HashSet<ObjectId>,
+    ) -> bool {
+        let time = entry.committer.time;
+        if time < expiry.total {
+            return true;
+        }
+        let is_null = |id: &ObjectId| id.as_bytes().iter().all(|&b| b == 0);
+        // Entries naming commits that are gone are of no use.
+        if [&entry.old, &entry.new].into_iter().any(|id| !is_null(id) && !odb.exists(id)) {
+            return true;
+        }
+        time < expiry.unreachable
+            && [&entry.old, &entry.new].into_iter().any(|id| !is_null(id) && !reachable.contains(id))
+    }
+
+    /// The commits the ref's current value reaches; for `HEAD`, what any
+    /// ref reaches, since its history spans branches.
+    fn reachable_from_tips(repo: &Repository, odb: &ObjectDatabase, name: &str) -> Result<HashSet<ObjectId>, String> {
+        let git_dir = repo.git_dir();
+        let mut tips: Vec<ObjectId> = refs::resolve(git_dir, name)?.into_iter().collect();
+        if name == "HEAD" {
+            tips.extend(refs::list_refs(git_dir)?.into_iter().map(|(_, id)| id));
+        }
+        let mut reachable = HashSet::new();
+        for tip in tips {
+            if reachable.contains(&tip) || odb.read_header(&tip).map(|(kind, _)| kind) != Ok(ObjectKind::Commit) {
+                continue;
+            }
+            reachable.extend(reachable::ancestors(odb, &tip)?);
+        }
+        Ok(reachable)
+    }
+
+    /// With `--rewrite`, each entry's old value is made the new value of
+    /// the entry now before it, so the log stays a continuous chain.
+    fn rewritten(mut entries: Vec<ReflogEntry>, rewrite: bool) -> Vec<ReflogEntry> {
+        if rewrite {
+            for i in 1..entries.len() {
+                entries[i].old = entries[i - 1].new.clone();
+            }
+        }
+        entries
+    }
+}
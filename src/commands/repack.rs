@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+use std::fs;
+
+use crate::object::ObjectId;
+use crate::pack::deltify::{self, DeltaOptions, PackObject};
+use crate::pack::indexer;
+use crate::reachable;
+use crate::refs;
+use crate::repo::Repository;
+
+pub struct Repack;
+
+impl Repack {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: repack [-a] [-d] [--window=<n>] [--depth=<n>]";
+        let mut all = false;
+        let mut delete = false;
+        let mut options = DeltaOptions::default();
+        for arg in args {
+            match arg.as_str() {
+                "-a" => all = true,
+                "-d" => delete = true,
+                "-ad" | "-da" => {
+                    all = true;
+                    delete = true;
+                }
+                _ => {
+                    if let Some(window) = arg.strip_prefix("--window=") {
+                        options.window = window.parse().map_err(|_| usage.to_string())?;
+                    } else if let Some(depth) = arg.strip_prefix("--depth=") {
+                        options.depth = depth.parse().map_err(|_| usage.to_string())?;
+                    } else {
+                        return Err(usage.to_string());
+                    }
+                }
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+
+        let mut tips: Vec<ObjectId> = refs::list_refs(repo.git_dir())?
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect();
+        if let Some(head) = refs::resolve(repo.git_dir(), "HEAD")? {
+            tips.push(head);
+        }
+
+        // Without -a only objects that are not yet in any pack are packed.
+        let objects: Vec<_> = reachable::reachable_objects(&odb, &tips)?
+            .into_iter()
+            .filter(|object| all || !odb.is_packed(&object.id))
+            .collect();
+        if objects.is_empty() {
+            println!("Nothing new to pack.");
+            return Ok(());
+        }
+
+        let mut to_pack = Vec::with_capacity(objects.len());
+        for object in objects {
+            let raw = odb.read(&object.id)?;
+            to_pack.push(PackObject {
+                id: object.id,
+                kind: raw.kind,
+                name_hash: object.name.as_deref().map_or(0, deltify::name_hash),
+                data: raw.data,
+            });
+        }
+        let (pack, indexed, delta_count) = deltify::write_pack(&to_pack, options, odb.hash_kind());
+        let idx_path = indexer::write_pack(&odb, &pack, &indexed)?;
+        eprintln!("Total {} (delta {})", indexed.entries.len(), delta_count);
+
+        if delete {
+            let packed: HashSet<ObjectId> = indexed.entries.iter().map(|entry| entry.id.clone()).collect();
+            Self::remove_redundant(&repo, &packed, &idx_path, all)?;
+        }
+        Ok(())
+    }
+
+    /// Removes loose objects that are now packed and, after `-a`, any older
+    /// pack whose objects all made it into the new one.
+    fn remove_redundant(
+        repo: &Repository,
+        packed: &HashSet<ObjectId>,
+        new_idx: &std::path::Path,
+        all: bool,
+    ) -> Result<(), String> {
+        let odb = repo.odb()?;
+        for id in odb.loose().ids()? {
+            if odb.is_packed(&id) {
+                odb.loose().remove(&id)?;
+            }
+        }
+
+        if !all {
+            return Ok(());
+        }
+        for pack in odb.packs() {
+            let idx_path = pack.path().with_extension("idx");
+            if idx_path == new_idx {
+                continue;
+            }
+            let index = pack.index();
+            if (0..index.len()).all(|i| packed.contains(&index.id_at(i))) {
+                fs::remove_file(pack.path()).map_err(|e| e.to_string())?;
+                fs::remove_file(&idx_path).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::env;
+
+use crate::odb::{ObjectDatabase, DEFAULT_ABBREV};
+use crate::refs;
+use crate::repo::Repository;
+use crate::revision::{Resolved, RevisionArg, Revisions};
+
+pub struct RevParse;
+
+/// How each resolved revision is printed.
+enum Output {
+    Id,
+    Short(usize),
+    AbbrevRef,
+    SymbolicFullName,
+}
+
+impl RevParse {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let mut verify = false;
+        let mut quiet = false;
+        let mut output = Output::Id;
+        let mut revisions = Vec::new();
+        let repo = Repository::discover()?;
+
+        for arg in args {
+            match arg.as_str() {
+                "--verify" => verify = true,
+                "-q" | "--quiet" => quiet = true,
+                "--short" => output = Output::Short(DEFAULT_ABBREV),
+                "--abbrev-ref" => output = Output::AbbrevRef,
+                "--symbolic-full-name" => output = Output::SymbolicFullName,
+                "--git-dir" => println!("{}", Self::git_dir_display(&repo)?),
+                "--show-toplevel" => println!("{}", repo.work_dir().display()),
+                "--is-inside-work-tree" => println!("true"),
+                "--show-object-format" => println!("{}", repo.hash_kind().name()),
+                _ if arg.starts_with("--short=") => {
+                    let len = arg["--short=".len()..]
+                        .parse()
+                        .map_err(|_| format!("Invalid abbreviation length: {}", arg))?;
+                    output = Output::Short(len);
+                }
+                _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("Unknown option: {}", arg)),
+                _ => revisions.push(arg),
+            }
+        }
+
+        let odb = repo.odb()?;
+        let resolver = Revisions::new(&repo, &odb);
+
+        if verify {
+            let single = match revisions.as_slice() {
+                [spec] => resolver.resolve_full(spec).ok(),
+                _ => None,
+            };
+            return match single {
+                Some(resolved) => Self::print(&repo, &odb, &output, "", &resolved),
+                // With --quiet a missing revision is only signalled through
+                // the exit status.
+                None if quiet => std::process::exit(1),
+                None => Err("Needed a single revision".to_string()),
+            };
+        }
+
+        for spec in revisions {
+            match resolver.parse_arg(spec)? {
+                RevisionArg::Include(resolved) => Self::print(&repo, &odb, &output, "", &resolved)?,
+                RevisionArg::Exclude(resolved) => Self::print(&repo, &odb, &output, "^", &resolved)?,
+                RevisionArg::Range { from, to } => {
+                    Self::print(&repo, &odb, &output, "", &to)?;
+                    Self::print(&repo, &odb, &output, "^", &from)?;
+                }
+                RevisionArg::Symmetric { left, right, bases } => {
+                    Self::print(&repo, &odb, &output, "", &right)?;
+                    Self::print(&repo, &odb, &output, "", &left)?;
+                    for base in bases {
+                        let base = Resolved { id: base, ref_name: None };
+                        Self::print(&repo, &odb, &output, "^", &base)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints one revision. The name-printing modes print nothing for
+    /// revisions that did not come from a ref.
+    fn print(
+        repo: &Repository,
+        odb: &ObjectDatabase,
+        output: &Output,
+        prefix: &str,
+        resolved: &Resolved,
+    ) -> Result<(), String> {
+        let text = match output {
+            Output::Id => resolved.id.to_hex(),
+            Output::Short(len) => odb.abbreviate(&resolved.id, *len)?,
+            Output::AbbrevRef | Output::SymbolicFullName => {
+                let Some(name) = &resolved.ref_name else {
+                    return Ok(());
+                };
+                let full = refs::follow_symbolic(repo.git_dir(), name)?;
+                match output {
+                    Output::AbbrevRef => refs::shorten_ref(&full).to_string(),
+                    _ => full,
+                }
+            }
+        };
+        println!("{}{}", prefix, text);
+        Ok(())
+    }
+
+    /// `.git` when run from the top of the work tree, as git prints it, and
+    /// an absolute path otherwise.
+    fn git_dir_display(repo: &Repository) -> Result<String, String> {
+        let cwd = env::current_dir().map_err(|e| e.to_string())?;
+        if repo.git_dir() == cwd.join(".git") {
+            return Ok(".git".to_string());
+        }
+        Ok(repo.git_dir().display().to_string())
+    }
+}
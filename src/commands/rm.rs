@@ -0,0 +1,180 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::index::{self, Index};
+use crate::lockfile::LockFile;
+use crate::object::ObjectId;
+use crate::odb::ObjectDatabase;
+use crate::quote::quote_path;
+use crate::refs;
+use crate::repo::Repository;
+use crate::worktree::{self, pathspec_matches};
+
+pub struct Rm;
+
+impl Rm {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: rm [-f] [-r] [-q] [-n] [--cached] [--ignore-unmatch] [--] <pathspec>...";
+        let mut cached = false;
+        let mut recursive = false;
+        let mut force = false;
+        let mut quiet = false;
+        let mut dry_run = false;
+        let mut ignore_unmatch = false;
+        let mut pathspecs = Vec::new();
+        let mut options_done = false;
+
+        for arg in args {
+            match arg.as_str() {
+                _ if options_done || !arg.starts_with('-') => pathspecs.push(arg.as_str()),
+                "--" => options_done = true,
+                "--cached" => cached = true,
+                "-r" => recursive = true,
+                "-f" | "--force" => force = true,
+                "-q" | "--quiet" => quiet = true,
+                "-n" | "--dry-run" => dry_run = true,
+                "--ignore-unmatch" => ignore_unmatch = true,
+                _ => return Err(usage.to_string()),
+            }
+        }
+        if pathspecs.is_empty() {
+            return Err("No pathspec was given. Which files should I remove?".to_string());
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let lock = LockFile::acquire(&repo.index_path())?;
+        let mut index = Index::load(&repo.index_path(), repo.hash_kind())?;
+
+        let mut paths = BTreeSet::new();
+        for arg in pathspecs {
+            let spec = worktree::repo_path(&repo, arg)?;
+            let matches: Vec<&[u8]> = index
+                .entries()
+                .iter()
+                .map(|entry| entry.path.as_slice())
+                .filter(|path| pathspec_matches(&spec, path))
+                .collect();
+            if matches.is_empty() && !ignore_unmatch {
+                return Err(format!("pathspec '{}' did not match any files", arg));
+            }
+            if !recursive && matches.iter().any(|path| *path != spec) {
+                return Err(format!("not removing '{}' recursively without -r", arg));
+            }
+            paths.extend(matches.into_iter().map(<[u8]>::to_vec));
+        }
+
+        if !force {
+            Self::check_local_changes(&repo, &odb, &index, &paths, cached)?;
+        }
+
+        for path in &paths {
+            if !quiet {
+                println!("rm '{}'", quote_path(path));
+            }
+            index.remove(path);
+        }
+        if dry_run {
+            return Ok(());
+        }
+
+        if !cached {
+            for path in &paths {
+                let full = repo.work_dir().join(worktree::bytes_path(path)?);
+                match fs::remove_file(&full) {
+                    Ok(()) => Self::remove_empty_parents(repo.work_dir(), &full),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(format!("git rm: '{}': {}", quote_path(path), e)),
+                }
+            }
+        }
+        index.write(lock)
+    }
+
+    /// Refuses to lose work: a file whose staged content is not what HEAD
+    /// has, or whose working copy is not what is staged, is only removed
+    /// with `-f`. With `--cached` the working copy survives, so only a file
+    /// that differs from both is refused.
+    fn check_local_changes(
+        repo: &Repository,
+        odb: &ObjectDatabase,
+        index: &Index,
+        paths: &BTreeSet<Vec<u8>>,
+        cached: bool,
+    ) -> Result<(), String> {
+        let head: HashMap<Vec<u8>, (u32, ObjectId)> = match refs::resolve(repo.git_dir(), "HEAD")? {
+            Some(commit) => index::tree_entries(odb, &odb.read_commit(&commit)?.tree, b"")?
+                .into_iter()
+                .map(|entry| (entry.path, (entry.mode, entry.id)))
+                .collect(),
+            None => HashMap::new(),
+        };
+
+        let (mut both, mut staged, mut local) = (Vec::new(), Vec::new(), Vec::new());
+        for path in paths {
+            // Unmerged paths have no single staged version to protect.
+            let Some(entry) = index.get(path) else {
+                continue;
+            };
+            let full = repo.work_dir().join(worktree::bytes_path(path)?);
+            let metadata = match fs::symlink_metadata(&full) {
+                Ok(metadata) if !metadata.is_dir() => metadata,
+                _ => continue,
+            };
+
+            let local_changes = worktree::is_modified(odb, index, entry, &full, &metadata)?;
+            let staged_changes = head.get(path) != Some(&(entry.mode, entry.id.clone()));
+
+            if local_changes && staged_changes {
+                if !cached || !entry.intent_to_add {
+                    both.push(path);
+                }
+            } else if !cached {
+                if staged_changes {
+                    staged.push(path);
+                }
+                if local_changes {
+                    local.push(path);
+                }
+            }
+        }
+
+        let keep_hint = "(use --cached to keep the file, or -f to force removal)";
+        let mut errors = Vec::new();
+        for (files, what, hint) in [
+            (&both, "staged content different from both the\nfile and the HEAD:", "(use -f to force removal)"),
+            (&staged, "changes staged in the index:", keep_hint),
+            (&local, "local modifications:", keep_hint),
+        ] {
+            if files.is_empty() {
+                continue;
+            }
+            let mut message = match files.len() {
+                1 => format!("the following file has {}\n", what),
+                _ => format!("the following files have {}\n", what),
+            };
+            for path in files {
+                message.push_str(&format!("    {}\n", quote_path(path)));
+            }
+            message.push_str(hint);
+            errors.push(message);
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join("\n")),
+        }
+    }
+
+    /// Removes the directories above a deleted file that it leaves empty,
+    /// stopping at the top of the work tree.
+    fn remove_empty_parents(work_dir: &Path, file: &Path) {
+        let mut dir = file.parent();
+        while let Some(current) = dir {
+            if current == work_dir || fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+}
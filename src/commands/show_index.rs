@@ -0,0 +1,30 @@
+use std::io::Read;
+
+use crate::object::HashKind;
+use crate::pack::index::PackIndex;
+use crate::repo::Repository;
+
+pub struct ShowIndex;
+
+impl ShowIndex {
+    /// Dumps the `.idx` read from stdin as `<offset> <id> (<crc32>)` lines.
+    /// The id width comes from `--object-format`, or else the surrounding
+    /// repository.
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let hash = match args {
+            [] => Repository::discover().map(|repo| repo.hash_kind()).unwrap_or_default(),
+            [arg] if arg.starts_with("--object-format=") => {
+                HashKind::from_name(&arg["--object-format=".len()..])?
+            }
+            _ => return Err("Usage: show-index [--object-format=<hash>] < <pack>.idx".to_string()),
+        };
+        let mut data = Vec::new();
+        std::io::stdin().read_to_end(&mut data).map_err(|e| e.to_string())?;
+        let index = PackIndex::parse(data, hash)?;
+
+        for i in 0..index.len() {
+            println!("{} {} ({:08x})", index.offset_at(i), index.id_at(i), index.crc_at(i));
+        }
+        Ok(())
+    }
+}
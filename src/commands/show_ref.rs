@@ -0,0 +1,131 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::object::ObjectId;
+use crate::odb::{ObjectDatabase, DEFAULT_ABBREV};
+use crate::refs;
+use crate::repo::Repository;
+
+pub struct ShowRef;
+
+struct Output {
+    dereference: bool,
+    hash_only: bool,
+    abbrev: Option<usize>,
+    quiet: bool,
+}
+
+impl ShowRef {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: show-ref [--head] [-d] [-s] [--abbrev[=<n>]] [--tags] [--heads] [-q] [--verify] \
+                     [--] [<pattern>...]";
+        let mut head = false;
+        let mut tags = false;
+        let mut heads = false;
+        let mut verify = false;
+        let mut output = Output { dereference: false, hash_only: false, abbrev: None, quiet: false };
+        let mut patterns = Vec::new();
+        let mut options_done = false;
+
+        for arg in args {
+            match arg.as_str() {
+                _ if options_done || !arg.starts_with('-') => patterns.push(arg.as_str()),
+                "--" => options_done = true,
+                "--head" => head = true,
+                "--tags" => tags = true,
+                "--heads" => heads = true,
+                "--verify" => verify = true,
+                "-d" | "--dereference" => output.dereference = true,
+                "-s" | "--hash" => output.hash_only = true,
+                "--abbrev" => output.abbrev = Some(DEFAULT_ABBREV),
+                "-q" | "--quiet" => output.quiet = true,
+                _ if arg.starts_with("--hash=") || arg.starts_with("--abbrev=") => {
+                    let (_, len) = arg.split_once('=').unwrap_or_default();
+                    let len = len.parse().map_err(|_| format!("Invalid abbreviation length: {}", arg))?;
+                    output.hash_only |= arg.starts_with("--hash=");
+                    output.abbrev = Some(len);
+                }
+                _ => return Err(usage.to_string()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let git_dir = repo.git_dir();
+        let mut out = BufWriter::new(io::stdout().lock());
+
+        // With --verify every argument is a full ref name that must exist.
+        if verify {
+            if patterns.is_empty() {
+                return Err("--verify requires a reference".to_string());
+            }
+            for name in patterns {
+                let id = match name.starts_with("refs/") || (head && name == "HEAD") {
+                    true => refs::resolve(git_dir, name)?,
+                    false => None,
+                };
+                match id {
+                    Some(id) => output.show(&odb, name, &id, &mut out)?,
+                    None if output.quiet => std::process::exit(1),
+                    None => return Err(format!("'{}' - not a valid ref", name)),
+                }
+            }
+            return out.flush().map_err(|e| e.to_string());
+        }
+
+        let mut shown = false;
+        if head {
+            if let Some(id) = refs::resolve(git_dir, "HEAD")? {
+                output.show(&odb, "HEAD", &id, &mut out)?;
+                shown = true;
+            }
+        }
+        for (name, id) in refs::list_refs(git_dir)? {
+            let kind_wanted = (!tags && !heads)
+                || (tags && name.starts_with("refs/tags/"))
+                || (heads && name.starts_with("refs/heads/"));
+            // A pattern matches whole trailing components of the name.
+            let pattern_matches = patterns.is_empty()
+                || patterns.iter().any(|pattern| {
+                    name == *pattern || name.strip_suffix(pattern).is_some_and(|rest| rest.ends_with('/'))
+                });
+            if kind_wanted && pattern_matches {
+                output.show(&odb, &name, &id, &mut out)?;
+                shown = true;
+            }
+        }
+        out.flush().map_err(|e| e.to_string())?;
+        if !shown {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+}
+
+impl Output {
+    /// Prints `<id> <name>`, followed with `-d` by `<peeled> <name>^{}` for
+    /// an annotated tag.
+    fn show(&self, odb: &ObjectDatabase, name: &str, id: &ObjectId, out: &mut impl Write) -> Result<(), String> {
+        if self.quiet {
+            return Ok(());
+        }
+        self.line(odb, name, id, out)?;
+        if self.dereference {
+            if let Some(peeled) = refs::peel_tag(odb, id)? {
+                self.line(odb, &format!("{}^{{}}", name), &peeled, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn line(&self, odb: &ObjectDatabase, name: &str, id: &ObjectId, out: &mut impl Write) -> Result<(), String> {
+        let hex = match self.abbrev {
+            Some(len) => odb.abbreviate(id, len)?,
+            None => id.to_hex(),
+        };
+        let line = match self.hash_only {
+            true => format!("{}\n", hex),
+            false => format!("{} {}\n", hex, name),
+        };
+        out.write_all(line.as_bytes()).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::refs::{self, RefValue};
+use crate::repo::Repository;
+
+pub struct SymbolicRef;
+
+impl SymbolicRef {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: symbolic-ref [-m <reason>] <name> <ref>\n   \
+                     or: symbolic-ref [-q] [--short] <name>\n   \
+                     or: symbolic-ref --delete [-q] <name>";
+        let mut quiet = false;
+        let mut short = false;
+        let mut delete = false;
+        let mut message = None;
+        let mut positional = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-q" | "--quiet" => quiet = true,
+                "--short" => short = true,
+                "-d" | "--delete" => delete = true,
+                "-m" => message = Some(args.next().ok_or(usage)?.as_str()),
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => positional.push(arg.as_str()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let git_dir = repo.git_dir();
+        match (delete, positional.as_slice()) {
+            (false, [name]) => match refs::read_ref(git_dir, name)? {
+                Some(RefValue::Symbolic(target)) => {
+                    println!("{}", if short { refs::shorten_ref(&target) } else { &target });
+                    Ok(())
+                }
+                // -q only silences a ref that exists but is not symbolic.
+                Some(RefValue::Direct(_)) if quiet => std::process::exit(1),
+                _ => Err(format!("ref {} is not a symbolic ref", name)),
+            },
+            (false, [name, target]) => {
+                if *name == "HEAD" && !target.starts_with("refs/") {
+                    return Err("Refusing to point HEAD outside of refs/".to_string());
+                }
+                if !refs::is_valid_name(target) {
+                    return Err(format!("Refusing to set '{}' to invalid ref '{}'", name, target));
+                }
+                refs::write_symbolic(git_dir, name, target, message)
+            }
+            (true, [name]) => {
+                if *name == "HEAD" {
+                    return Err(format!("deleting '{}' is not allowed", name));
+                }
+                match refs::read_ref(git_dir, name)? {
+                    Some(RefValue::Symbolic(_)) => refs::delete_symbolic(git_dir, name),
+                    Some(RefValue::Direct(_)) if quiet => std::process::exit(1),
+                    _ => Err(format!("Cannot delete {}, not a symbolic ref", name)),
+                }
+            }
+            _ => Err(usage.to_string()),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::fs;
+use std::io::{self, BufRead};
+
+use crate::index::{Index, IndexEntry, Stat};
+use crate::lockfile::LockFile;
+use crate::object::tree::{MODE_BLOB, MODE_EXECUTABLE};
+use crate::object::ObjectId;
+use crate::odb::ObjectDatabase;
+use crate::quote::{quote_path, unquote_path};
+use crate::repo::Repository;
+use crate::worktree;
+
+pub struct UpdateIndex;
+
+/// The options in effect for the paths that follow them; like git,
+/// options only apply to the paths given after them.
+struct Updater<'a> {
+    repo: &'a Repository,
+    odb: &'a ObjectDatabase,
+    index: Index,
+    add: bool,
+    remove: bool,
+    force_remove: bool,
+    info_only: bool,
+    quiet: bool,
+    verbose: bool,
+    chmod: Option<bool>,
+    /// Set when `--refresh` finds entries that need updating.
+    needs_update: bool,
+}
+
+impl UpdateIndex {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let lock = LockFile::acquire(&repo.index_path())?;
+        let index = Index::load(&repo.index_path(), repo.hash_kind())?;
+        let mut updater = Updater {
+            repo: &repo,
+            odb: &odb,
+            index,
+            add: false,
+            remove: false,
+            force_remove: false,
+            info_only: false,
+            quiet: false,
+            verbose: false,
+            chmod: None,
+            needs_update: false,
+        };
+
+        let mut args = args.iter();
+        let mut options_done = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                _ if options_done || !arg.starts_with('-') => {
+                    let path = worktree::repo_path(&repo, arg)?;
+                    updater.update_path(&path)?;
+                }
+                "--" => options_done = true,
+                "--add" => updater.add = true,
+                "--remove" => updater.remove = true,
+                "--force-remove" => updater.force_remove = true,
+                "--replace" => {}
+                "--info-only" => updater.info_only = true,
+                "-q" => updater.quiet = true,
+                "--verbose" => updater.verbose = true,
+                "--refresh" | "--really-refresh" => updater.refresh()?,
+                "--chmod=+x" => updater.chmod = Some(true),
+                "--chmod=-x" => updater.chmod = Some(false),
+                "--cacheinfo" => {
+                    let first = args.next().ok_or("option 'cacheinfo' expects <mode>,<sha1>,<path>")?;
+                    let parts: Vec<&str> = match first.splitn(3, ',').collect::<Vec<_>>() {
+                        parts if parts.len() == 3 => parts,
+                        _ => {
+                            let rest: Vec<&str> = args.by_ref().take(2).map(String::as_str).collect();
+                            if rest.len() != 2 {
+                                return Err("option 'cacheinfo' expects <mode>,<sha1>,<path>".to_string());
+                            }
+                            vec![first.as_str(), rest[0], rest[1]]
+                        }
+                    };
+                    updater.cacheinfo(parts[0], parts[1], parts[2])?;
+                }
+                "--index-info" => updater.index_info()?,
+                "--index-version" => {
+                    let version = args.next().ok_or("option 'index-version' requires a value")?;
+                    let version = version.parse().map_err(|_| format!("invalid index-version '{}'", version))?;
+                    updater.index.set_version(version)?;
+                }
+                _ => return Err(format!("unknown option '{}'", arg.trim_start_matches('-'))),
+            }
+        }
+
+        let needs_update = updater.needs_update && !updater.quiet;
+        updater.index.write(lock)?;
+        if needs_update {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+}
+
+impl Updater<'_> {
+    /// Brings the entry for `path` in line with the work tree: stages the
+    /// file, or unstages it if it is gone and `--remove` was given.
+    fn update_path(&mut self, path: &[u8]) -> Result<(), String> {
+        let shown = quote_path(path);
+        if !valid_path(path) {
+            return Err(format!("Ignoring path {}", shown));
+        }
+        if self.force_remove {
+            if self.index.remove(path) && self.verbose {
+                println!("remove '{}'", shown);
+            }
+            return Ok(());
+        }
+
+        let full = self.repo.work_dir().join(worktree::bytes_path(path)?);
+        let metadata = match fs::symlink_metadata(&full) {
+            Ok(metadata) if metadata.is_dir() => {
+                return Err(format!("{}: is a directory - add files inside instead\nUnable to process path {}", shown, shown));
+            }
+            Ok(metadata) => metadata,
+            Err(_) if self.remove => {
+                if self.index.remove(path) && self.verbose {
+                    println!("remove '{}'", shown);
+                }
+                return Ok(());
+            }
+            Err(_) => {
+                return Err(format!("{}: does not exist and --remove not passed\nUnable to process path {}", shown, shown));
+            }
+        };
+
+        if !self.add && !self.index.contains(path) {
+            return Err(format!(
+                "{}: cannot add to the index - missing --add option?\nUnable to process path {}",
+                shown, shown
+            ));
+        }
+        let id = worktree::blob_id(self.odb, &full, &metadata, !self.info_only)?;
+        let mut entry = IndexEntry::new(path.to_vec(), worktree::file_mode(&metadata), id);
+        entry.stat = Stat::from_metadata(&metadata);
+        self.index.add(entry);
+        if self.verbose {
+            println!("add '{}'", shown);
+        }
+        self.apply_chmod(path)
+    }
+
+    /// Stages `id` for `path` without looking at the work tree.
+    fn cacheinfo(&mut self, mode: &str, id: &str, path: &str) -> Result<(), String> {
+        let cannot_add = || format!("git update-index: --cacheinfo cannot add {}", path);
+        let mode = u32::from_str_radix(mode, 8).map_err(|_| cannot_add())?;
+        let id = ObjectId::from_hex(id)
+            .ok()
+            .filter(|id| id.as_bytes().len() == self.odb.hash_kind().id_len())
+            .ok_or_else(cannot_add)?;
+        if !valid_path(path.as_bytes()) {
+            return Err(format!("Invalid path '{}'\n{}", path, cannot_add()));
+        }
+        if !self.add && !self.index.contains(path.as_bytes()) {
+            return Err(format!("{}: cannot add to the index - missing --add option?\n{}", path, cannot_add()));
+        }
+        self.index.add(IndexEntry::new(path.as_bytes().to_vec(), mode, id));
+        if self.verbose {
+            println!("add '{}'", path);
+        }
+        self.apply_chmod(path.as_bytes())
+    }
+
+    /// Reads `<mode> SP [<type> SP] <id> [SP <stage>] TAB <path>` lines from
+    /// standard input, the format `ls-tree` and `ls-files --stage` print. A
+    /// mode of 0 removes the path.
+    fn index_info(&mut self) -> Result<(), String> {
+        for line in io::stdin().lock().lines() {
+            let line = line.map_err(|e| e.to_string())?;
+            let bad_line = || format!("malformed index info {}", line);
+            let (fields, path) = line.split_once('\t').ok_or_else(bad_line)?;
+            let path = unquote_path(path).ok_or_else(bad_line)?;
+            let mut fields: Vec<&str> = fields.split(' ').collect();
+
+            let mode = u32::from_str_radix(fields[0], 8).map_err(|_| bad_line())?;
+            if mode == 0 {
+                if self.index.remove(&path) && self.verbose {
+                    println!("remove '{}'", quote_path(&path));
+                }
+                continue;
+            }
+            // The stage, if any, is a single digit after the id; a type
+            // before the id is ignored.
+            let stage = match fields.last() {
+                Some(stage) if fields.len() > 2 && stage.len() == 1 => {
+                    let stage = fields.pop().unwrap_or_default();
+                    stage.parse().map_err(|_| bad_line())?
+                }
+                _ => 0,
+            };
+            if fields.len() < 2 || fields.len() > 3 || stage > 3 || !valid_path(&path) {
+                return Err(bad_line());
+            }
+            let id = ObjectId::from_hex(fields[fields.len() - 1]).map_err(|_| bad_line())?;
+            let mut entry = IndexEntry::new(path, mode, id);
+            entry.stage = stage;
+            if self.verbose {
+                println!("add '{}'", quote_path(&entry.path));
+            }
+            self.index.add(entry);
+        }
+        Ok(())
+    }
+
+    /// Updates the stat data of entries whose files are unchanged, and
+    /// reports the ones that differ from what is staged.
+    fn refresh(&mut self) -> Result<(), String> {
+        let mut i = 0;
+        while i < self.index.entries().len() {
+            let entry = &self.index.entries()[i];
+            let shown = quote_path(&entry.path);
+            i += 1;
+            if entry.stage != 0 {
+                if !self.quiet {
+                    println!("{}: needs merge", shown);
+                }
+                self.needs_update = true;
+                while i < self.index.entries().len() && self.index.entries()[i].path == entry.path {
+                    i += 1;
+                }
+                continue;
+            }
+
+            let full = self.repo.work_dir().join(worktree::bytes_path(&entry.path)?);
+            let metadata = match fs::symlink_metadata(&full) {
+                Ok(metadata) if !metadata.is_dir() => metadata,
+                _ => {
+                    if !self.quiet {
+                        println!("{}: needs update", shown);
+                    }
+                    self.needs_update = true;
+                    continue;
+                }
+            };
+            if entry.stat_matches(&metadata) && !self.index.is_racy(entry) {
+                continue;
+            }
+            if !worktree::is_modified(self.odb, &self.index, entry, &full, &metadata)? {
+                self.index.refresh_entry(i - 1, Stat::from_metadata(&metadata));
+            } else {
+                if !self.quiet {
+                    println!("{}: needs update", shown);
+                }
+                self.needs_update = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_chmod(&mut self, path: &[u8]) -> Result<(), String> {
+        let Some(executable) = self.chmod else {
+            return Ok(());
+        };
+        let Ok(i) = self.index.position(path, 0) else {
+            return Ok(());
+        };
+        if !matches!(self.index.entries()[i].mode, MODE_BLOB | MODE_EXECUTABLE) {
+            return Err(format!(
+                "git update-index: cannot chmod {}x '{}'",
+                if executable { '+' } else { '-' },
+                quote_path(path)
+            ));
+        }
+        self.index.set_mode(i, if executable { MODE_EXECUTABLE } else { MODE_BLOB });
+        if self.verbose {
+            println!("chmod {}x '{}'", if executable { '+' } else { '-' }, quote_path(path));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` may be staged: relative, without empty, `.` or `..`
+/// components, and not inside `.git`.
+fn valid_path(path: &[u8]) -> bool {
+    !path.is_empty()
+        && path.split(|&b| b == b'/').all(|component| {
+            !component.is_empty()
+                && component != b"."
+                && component != b".."
+                && !component.eq_ignore_ascii_case(b".git")
+        })
+}
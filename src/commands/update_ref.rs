@@ -0,0 +1,258 @@
+use std::io::{self, Read};
+
+use crate::object::ObjectId;
+use crate::odb::ObjectDatabase;
+use crate::refs::{Change, Expected, RefUpdate, Transaction};
+use crate::repo::Repository;
+use crate::revision::Revisions;
+
+pub struct UpdateRef;
+
+/// One `--stdin` instruction: the verb, the ref (or option) it names and
+/// its value fields, where an empty field counts as absent.
+struct Instruction {
+    verb: String,
+    name: String,
+    values: Vec<Option<String>>,
+}
+
+impl UpdateRef {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: update-ref [-m <reason>] [--no-deref] (-d <ref> [<old-oid>] | <ref> <new-oid> [<old-oid>] \
+                     | --stdin [-z])";
+        let mut message = String::new();
+        let mut create_reflog = false;
+        let mut delete = false;
+        let mut deref = true;
+        let mut stdin = false;
+        let mut nul_terminated = false;
+        let mut positional = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-m" => message = args.next().ok_or(usage)?.clone(),
+                "-d" => delete = true,
+                "--no-deref" => deref = false,
+                "--create-reflog" => create_reflog = true,
+                "--stdin" => stdin = true,
+                "-z" => nul_terminated = true,
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => positional.push(arg.as_str()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let revisions = Revisions::new(&repo, &odb);
+        let new_transaction = || {
+            let mut transaction = Transaction::new(repo.git_dir());
+            transaction.set_message(&message);
+            if create_reflog {
+                transaction.create_reflogs();
+            }
+            transaction
+        };
+
+        if stdin {
+            if delete || !positional.is_empty() {
+                return Err(usage.to_string());
+            }
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input).map_err(|e| e.to_string())?;
+            let input = String::from_utf8(input).map_err(|_| "update-ref: input is not valid UTF-8")?;
+            let instructions = match nul_terminated {
+                true => Self::parse_nul_terminated(&input)?,
+                false => Self::parse_lines(&input)?,
+            };
+            return Self::run_instructions(&odb, &revisions, instructions, deref, new_transaction);
+        }
+        if nul_terminated {
+            return Err("-z only makes sense with --stdin".to_string());
+        }
+
+        let update = match (delete, positional.as_slice()) {
+            (true, [name, old @ ..]) if old.len() <= 1 => RefUpdate {
+                name: name.to_string(),
+                change: Change::Delete,
+                expected: match old.first() {
+                    Some(old) => Self::expected(&revisions, old)?,
+                    None => Expected::Any,
+                },
+                deref,
+            },
+            (false, [name, new, old @ ..]) if old.len() <= 1 => RefUpdate {
+                name: name.to_string(),
+                change: match Self::existing(&odb, name, Self::value(&revisions, new)?)
+                    .map_err(|e| format!("update_ref failed for ref '{}': {}", name, e))?
+                {
+                    Some(id) => Change::Set(id),
+                    None => Change::Delete,
+                },
+                expected: match old.first() {
+                    Some(old) => Self::expected(&revisions, old)?,
+                    None => Expected::Any,
+                },
+                deref,
+            },
+            _ => return Err(usage.to_string()),
+        };
+
+        let name = update.name.clone();
+        let mut transaction = new_transaction();
+        transaction.add(update);
+        transaction.commit().map_err(|e| match delete {
+            true => e,
+            false => format!("update_ref failed for ref '{}': {}", name, e),
+        })
+    }
+
+    /// An object id given on the command line, `None` for the null id.
+    fn value(revisions: &Revisions, text: &str) -> Result<Option<ObjectId>, String> {
+        let id = revisions.resolve(text).map_err(|_| format!("{}: not a valid SHA1", text))?;
+        Ok((!id.as_bytes().iter().all(|&b| b == 0)).then_some(id))
+    }
+
+    /// Checks that a new value, unless it is the null id, names an object
+    /// that exists.
+    fn existing(odb: &ObjectDatabase, name: &str, id: Option<ObjectId>) -> Result<Option<ObjectId>, String> {
+        match &id {
+            Some(id) if !odb.exists(id) => Err(format!(
+                "cannot update ref '{}': trying to write ref '{}' with nonexistent object {}",
+                name, name, id
+            )),
+            _ => Ok(id),
+        }
+    }
+
+    /// An expected old value, where the null id or an empty string says
+    /// the ref must not exist yet.
+    fn expected(revisions: &Revisions, text: &str) -> Result<Expected, String> {
+        if text.is_empty() {
+            return Ok(Expected::Missing);
+        }
+        Ok(match Self::value(revisions, text)? {
+            Some(id) => Expected::Value(id),
+            None => Expected::Missing,
+        })
+    }
+
+    /// `<verb> SP <ref> [SP <value>]...` lines.
+    fn parse_lines(input: &str) -> Result<Vec<Instruction>, String> {
+        let mut instructions = Vec::new();
+        for line in input.lines().filter(|line| !line.is_empty()) {
+            let mut words = line.split(' ');
+            let verb = words.next().unwrap_or_default().to_string();
+            let name = words.next().unwrap_or_default().to_string();
+            let values = words.map(|word| Some(word.to_string()).filter(|word| !word.is_empty())).collect();
+            instructions.push(Instruction { verb, name, values });
+        }
+        Ok(instructions)
+    }
+
+    /// With `-z`, each instruction is `<verb> SP <ref>` followed by a fixed
+    /// number of NUL-terminated value fields, which may be empty.
+    fn parse_nul_terminated(input: &str) -> Result<Vec<Instruction>, String> {
+        let mut fields = input.split('\0');
+        let mut instructions = Vec::new();
+        while let Some(head) = fields.next().filter(|head| !head.is_empty()) {
+            let (verb, name) = head.split_once(' ').unwrap_or((head, ""));
+            let count = match verb {
+                "update" => 2,
+                "create" | "delete" | "verify" => 1,
+                _ => 0,
+            };
+            let mut values = Vec::new();
+            for _ in 0..count {
+                let field = fields.next().ok_or_else(|| format!("{} {}: unexpected end of input", verb, name))?;
+                values.push(Some(field.to_string()).filter(|field| !field.is_empty()));
+            }
+            instructions.push(Instruction { verb: verb.to_string(), name: name.to_string(), values });
+        }
+        Ok(instructions)
+    }
+
+    /// Queues updates and commits them together at `commit` or the end of
+    /// input, reporting `start`, `prepare`, `commit` and `abort` as git does.
+    fn run_instructions<'a>(
+        odb: &ObjectDatabase,
+        revisions: &Revisions,
+        instructions: Vec<Instruction>,
+        deref: bool,
+        new_transaction: impl Fn() -> Transaction<'a>,
+    ) -> Result<(), String> {
+        let mut transaction = new_transaction();
+        let mut next_deref = deref;
+
+        for Instruction { verb, name, values } in instructions {
+            let (min, max) = match verb.as_str() {
+                "update" => (1, 2),
+                "create" => (1, 1),
+                "delete" | "verify" => (0, 1),
+                "option" => {
+                    match name.as_str() {
+                        "no-deref" => next_deref = false,
+                        _ => return Err(format!("option unknown: {}", name)),
+                    }
+                    continue;
+                }
+                "start" | "prepare" => {
+                    println!("{}: ok", verb);
+                    continue;
+                }
+                "commit" => {
+                    std::mem::replace(&mut transaction, new_transaction()).commit()?;
+                    println!("commit: ok");
+                    continue;
+                }
+                "abort" => {
+                    transaction = new_transaction();
+                    println!("abort: ok");
+                    continue;
+                }
+                _ => return Err(format!("unknown command: {} {}", verb, name)),
+            };
+            if name.is_empty() {
+                return Err(format!("{}: missing <ref>", verb));
+            }
+            let present = values.iter().take_while(|value| value.is_some()).count();
+            if values.len() > max {
+                let extra: Vec<&str> = values[max..].iter().flatten().map(String::as_str).collect();
+                return Err(format!("{} {}: extra input: {}", verb, name, extra.join(" ")));
+            }
+            if present < min {
+                return Err(format!("{} {}: missing <newvalue>", verb, name));
+            }
+
+            let value = |at: usize| values.get(at).cloned().flatten();
+            let invalid = |what: &str, text: &str| format!("{} {}: invalid <{}>: {}", verb, name, what, text);
+            let new = |text: String| -> Result<Option<ObjectId>, String> {
+                let id = Self::value(revisions, &text).map_err(|_| invalid("newvalue", &text))?;
+                Self::existing(odb, &name, id)
+            };
+            let old = |at: usize, missing: Expected| -> Result<Expected, String> {
+                match value(at) {
+                    Some(text) => Ok(Self::value(revisions, &text)
+                        .map_err(|_| invalid("oldvalue", &text))?
+                        .map_or(Expected::Missing, Expected::Value)),
+                    None => Ok(missing),
+                }
+            };
+            let (change, expected) = match verb.as_str() {
+                "update" => {
+                    let new = new(value(0).unwrap_or_default())?;
+                    (new.map_or(Change::Delete, Change::Set), old(1, Expected::Any)?)
+                }
+                "create" => match new(value(0).unwrap_or_default())? {
+                    Some(id) => (Change::Set(id), Expected::Missing),
+                    None => return Err(format!("create {}: zero <newvalue>", name)),
+                },
+                "delete" => (Change::Delete, old(0, Expected::Any)?),
+                _ => (Change::Verify, old(0, Expected::Missing)?),
+            };
+            transaction.add(RefUpdate { name, change, expected, deref: next_deref });
+            next_deref = deref;
+        }
+        transaction.commit()
+    }
+}
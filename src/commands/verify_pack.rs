@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::object::{HashKind, ObjectId, ObjectKind};
+use crate::pack::indexer;
+use crate::pack::{EntryKind, Pack};
+use crate::repo::Repository;
+
+pub struct VerifyPack;
+
+/// Per-entry facts reported by `-v`.
+struct EntryStats {
+    id: ObjectId,
+    kind: ObjectKind,
+    /// Inflated size of the entry: the delta's size for deltified objects.
+    size: usize,
+    offset: u64,
+    packed_size: u64,
+    delta_base: Option<u64>,
+}
+
+impl VerifyPack {
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let usage = "Usage: verify-pack [-v] [-s] [--object-format=<hash>] <pack>.idx...";
+        let mut verbose = false;
+        let mut stat_only = false;
+        let mut object_format = None;
+        let mut paths = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "-v" | "--verbose" => verbose = true,
+                "-s" | "--stat-only" => stat_only = true,
+                _ if arg.starts_with("--object-format=") => {
+                    object_format = Some(HashKind::from_name(&arg["--object-format=".len()..])?);
+                }
+                _ if arg.starts_with('-') => return Err(usage.to_string()),
+                _ => paths.push(arg),
+            }
+        }
+        if paths.is_empty() {
+            return Err(usage.to_string());
+        }
+
+        let hash = object_format
+            .unwrap_or_else(|| Repository::discover().map(|repo| repo.hash_kind()).unwrap_or_default());
+
+        let mut failed = false;
+        for path in paths {
+            let idx_path = Path::new(path).with_extension("idx");
+            if let Err(e) = Self::verify(&idx_path, hash, verbose, stat_only) {
+                eprintln!("error: {}: {}", idx_path.with_extension("pack").display(), e);
+                failed = true;
+            }
+        }
+        if failed {
+            return Err("verify-pack failed".to_string());
+        }
+        Ok(())
+    }
+
+    fn verify(idx_path: &Path, hash: HashKind, verbose: bool, stat_only: bool) -> Result<(), String> {
+        let pack = Pack::open(idx_path, hash)?;
+        let index = pack.index();
+        let data = pack.data()?;
+
+        if !index.verify_checksum() {
+            return Err("index checksum mismatch".to_string());
+        }
+        let count = indexer::verify_pack_header(data, hash)?;
+        if count as usize != index.len() {
+            return Err(format!("pack has {} objects but the index lists {}", count, index.len()));
+        }
+        if index.pack_checksum() != &data[data.len() - index.hash_len()..] {
+            return Err("index does not belong to this pack".to_string());
+        }
+
+        // Entries in pack order; each one ends where the next begins.
+        let mut entries: Vec<(u64, usize)> = (0..index.len()).map(|i| (index.offset_at(i), i)).collect();
+        entries.sort();
+        let pack_end = (data.len() - index.hash_len()) as u64;
+
+        let mut stats = Vec::with_capacity(entries.len());
+        for (n, &(offset, i)) in entries.iter().enumerate() {
+            let end = entries.get(n + 1).map_or(pack_end, |&(next, _)| next);
+            let id = index.id_at(i);
+
+            let crc = crc32fast::hash(&data[offset as usize..end as usize]);
+            if crc != index.crc_at(i) {
+                return Err(format!("CRC mismatch for object {}", id));
+            }
+            let object = pack.read_at(offset)?;
+            if hash.hash_object(object.kind, &object.data) != id {
+                return Err(format!("object {} at offset {} does not match its id", id, offset));
+            }
+
+            let header = pack.entry_header(offset)?;
+            let delta_base = match header.kind {
+                EntryKind::Base(_) => None,
+                EntryKind::OfsDelta(base) => Some(base),
+                EntryKind::RefDelta(base) => Some(
+                    index.lookup(&base).ok_or_else(|| format!("delta base {} is not in the pack", base))?,
+                ),
+            };
+            stats.push(EntryStats {
+                id,
+                kind: object.kind,
+                size: header.size,
+                offset,
+                packed_size: end - offset,
+                delta_base,
+            });
+        }
+
+        if verbose || stat_only {
+            Self::report(&stats, stat_only);
+            println!("{}: ok", pack.path().display());
+        }
+        Ok(())
+    }
+
+    fn report(stats: &[EntryStats], stat_only: bool) {
+        let by_offset: HashMap<u64, &EntryStats> = stats.iter().map(|entry| (entry.offset, entry)).collect();
+        let mut depths: HashMap<u64, usize> = HashMap::new();
+        let mut histogram: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut non_delta = 0;
+
+        for entry in stats {
+            let depth = Self::depth(entry.offset, &by_offset, &mut depths);
+            if depth == 0 {
+                non_delta += 1;
+            } else {
+                *histogram.entry(depth).or_default() += 1;
+            }
+            if stat_only {
+                continue;
+            }
+
+            let mut line = format!(
+                "{} {:<6} {} {} {}",
+                entry.id, entry.kind.as_str(), entry.size, entry.packed_size, entry.offset
+            );
+            if let Some(base) = entry.delta_base {
+                line.push_str(&format!(" {} {}", depth, by_offset[&base].id));
+            }
+            println!("{}", line);
+        }
+
+        println!("non delta: {} objects", non_delta);
+        for (depth, count) in histogram {
+            println!("chain length = {}: {} object{}", depth, count, if count == 1 { "" } else { "s" });
+        }
+    }
+
+    fn depth(offset: u64, by_offset: &HashMap<u64, &EntryStats>, memo: &mut HashMap<u64, usize>) -> usize {
+        let mut chain = Vec::new();
+        let mut current = offset;
+        let mut depth = loop {
+            if let Some(&known) = memo.get(&current) {
+                break known;
+            }
+            match by_offset.get(&current).and_then(|entry| entry.delta_base) {
+                Some(base) => {
+                    chain.push(current);
+                    current = base;
+                }
+                None => {
+                    memo.insert(current, 0);
+                    break 0;
+                }
+            }
+        };
+        for &link in chain.iter().rev() {
+            depth += 1;
+            memo.insert(link, depth);
+        }
+        memo[&offset]
+    }
+}
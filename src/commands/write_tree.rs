@@ -1,90 +1,100 @@
-use std::fs;
-use sha1::{Digest, Sha1};
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use std::io::Write;
+use crate::index::{Index, IndexEntry};
+use crate::object::tree::{self, MODE_GITLINK, MODE_TREE};
+use crate::object::{Object, ObjectId, Tree, TreeEntry};
+use crate::odb::ObjectDatabase;
+use crate::quote::quote_path;
+use crate::repo::Repository;
 
 pub struct WriteTree;
 
 impl WriteTree {
-    pub fn run(_args: &[String]) -> Result<(), String> {
-        let tree_hash = Self::write_tree(".")?;
-        print!("{}", tree_hash);
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let mut missing_ok = false;
+        let mut prefix = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--missing-ok" => missing_ok = true,
+                _ if arg.starts_with("--prefix=") => prefix = arg.as_bytes()["--prefix=".len()..].to_vec(),
+                _ => return Err("Usage: write-tree [--missing-ok] [--prefix=<prefix>/]".to_string()),
+            }
+        }
+
+        let repo = Repository::discover()?;
+        let odb = repo.odb()?;
+        let index = Index::load(&repo.index_path(), repo.hash_kind())?;
+        let tree_hash = Self::write_tree(&odb, &index, &prefix, missing_ok)?;
+        println!("{}", tree_hash);
         Ok(())
     }
 
-    fn write_tree(path: &str) -> Result<String, String> {
-        let mut entries = Vec::new();
-        for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
-            let entry = entry.map_err(|e| e.to_string())?;
-            let file_name = entry.file_name().into_string().map_err(|_| "Invalid file name".to_string())?;
-            if file_name == ".git" { continue; }
-            
-            let file_type = entry.file_type().map_err(|e| e.to_string())?;
-            let mode = if file_type.is_dir() { "40000" } else { "100644" };
-            
-            let raw_hash = if file_type.is_dir() {
-                let subtree_hash = Self::write_tree(&entry.path().to_str().ok_or("Invalid path")?)?;
-                hex::decode(subtree_hash).map_err(|e| e.to_string())?
-            } else {
-                let contents = fs::read(entry.path()).map_err(|e| e.to_string())?;
-                let (_, raw_hash) = Self::hash_object("blob", &contents);
-                raw_hash
-            };
-            
-            entries.push((mode, file_name, raw_hash));
-        }
-        
-        entries.sort_by(|a, b| a.1.cmp(&b.1));
-        
-        let mut tree_content = Vec::new();
-        for (mode, name, hash) in entries {
-            tree_content.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
-            tree_content.extend_from_slice(&hash);
+    /// Writes the trees for what is staged in `index` below `prefix` (empty
+    /// for the whole index) and returns the top one. Every staged object
+    /// must exist unless `missing_ok` is set, and nothing may be unmerged.
+    /// Paths added with `add -N` are left out.
+    pub fn write_tree(odb: &ObjectDatabase, index: &Index, prefix: &[u8], missing_ok: bool) -> Result<ObjectId, String> {
+        let unmerged: Vec<String> = index
+            .entries()
+            .iter()
+            .filter(|entry| entry.stage != 0)
+            .map(|entry| format!("{}: unmerged ({})", quote_path(&entry.path), entry.id))
+            .collect();
+        if !unmerged.is_empty() {
+            return Err(format!("{}\ngit-write-tree: error building trees", unmerged.join("\n")));
         }
-        
-        Self::write_object(path, "tree", &tree_content)
-    }
-
-    fn hash_object(object_type: &str, contents: &[u8]) -> (String, Vec<u8>) {
-        let size = contents.len();
-        let mut sha_input = Vec::new();
-        sha_input.extend_from_slice(object_type.as_bytes());
-        sha_input.extend_from_slice(b" ");
-        sha_input.extend_from_slice(size.to_string().as_bytes());
-        sha_input.extend_from_slice(b"\0");
-        sha_input.extend_from_slice(contents);
 
-        let mut hasher = Sha1::new();
-        hasher.update(&sha_input);
-        let hash_result = hasher.finalize();
-        
-        (format!("{:x}", hash_result), hash_result.to_vec())
+        let mut prefix = prefix.to_vec();
+        if !prefix.is_empty() && !prefix.ends_with(b"/") {
+            prefix.push(b'/');
+        }
+        let entries: Vec<&IndexEntry> = index
+            .entries()
+            .iter()
+            .filter(|entry| !entry.intent_to_add && entry.path.starts_with(&prefix))
+            .collect();
+        if !prefix.is_empty() && entries.is_empty() {
+            return Err(format!(
+                "git-write-tree: prefix {} not found",
+                String::from_utf8_lossy(&prefix[..prefix.len() - 1])
+            ));
+        }
+        Self::build(odb, &entries, prefix.len(), missing_ok)
     }
 
-    fn write_object(path: &str, object_type: &str, contents: &[u8]) -> Result<String, String> {
-        let (hash, _) = Self::hash_object(object_type, contents);
-
-        let dir_name = &hash[..2];
-        let file_name = &hash[2..];
-        let dir_path = format!("{}/.git/objects/{}", path, dir_name);
-        let file_path = format!("{}/{}", dir_path, file_name);
-
-        fs::create_dir_all(&dir_path).map_err(|e| e.to_string())?;
+    /// Writes the tree for `entries`, which all share the same first `base`
+    /// bytes of path. Entries in one subdirectory are contiguous in index
+    /// order, so each subtree is built from a slice.
+    fn build(odb: &ObjectDatabase, entries: &[&IndexEntry], base: usize, missing_ok: bool) -> Result<ObjectId, String> {
+        let mut tree_entries = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            let rest = &entries[i].path[base..];
+            if let Some(slash) = rest.iter().position(|&b| b == b'/') {
+                let dir = &rest[..=slash];
+                let len = entries[i..]
+                    .iter()
+                    .take_while(|entry| entry.path[base..].starts_with(dir))
+                    .count();
+                let id = Self::build(odb, &entries[i..i + len], base + dir.len(), missing_ok)?;
+                tree_entries.push(TreeEntry::new(MODE_TREE, rest[..slash].to_vec(), id));
+                i += len;
+                continue;
+            }
 
-        let size = contents.len();
-        let mut object_content = Vec::new();
-        object_content.extend_from_slice(object_type.as_bytes());
-        object_content.extend_from_slice(b" ");
-        object_content.extend_from_slice(size.to_string().as_bytes());
-        object_content.extend_from_slice(b"\0");
-        object_content.extend_from_slice(contents);
-
-        let blob_object_file = fs::File::create(file_path).map_err(|e| e.to_string())?;
-        let mut encoder = ZlibEncoder::new(blob_object_file, Compression::default());
-        encoder.write_all(&object_content).map_err(|e| e.to_string())?;
-        encoder.finish().map_err(|e| e.to_string())?;
+            let entry = entries[i];
+            // Gitlinks name commits in another repository.
+            if !missing_ok && entry.mode != MODE_GITLINK && !odb.exists(&entry.id) {
+                return Err(format!(
+                    "invalid object {:06o} {} for '{}'\ngit-write-tree: error building trees",
+                    entry.mode,
+                    entry.id,
+                    String::from_utf8_lossy(&entry.path)
+                ));
+            }
+            tree_entries.push(TreeEntry::new(entry.mode, rest.to_vec(), entry.id.clone()));
+            i += 1;
+        }
 
-        Ok(hash)
+        tree_entries.sort_by(|a, b| tree::compare_names(&a.name, a.is_tree(), &b.name, b.is_tree()));
+        odb.write_object(&Object::Tree(Tree { entries: tree_entries }))
     }
-}
\ No newline at end of file
+}
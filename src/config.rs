@@ -0,0 +1,1239 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::lockfile::LockFile;
+use crate::refs::{self, RefValue};
+
+/// Included files may include others, down to this depth.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Where a config file sits in git's precedence order, lowest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    System,
+    Global,
+    Local,
+    Command,
+}
+
+impl Scope {
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::System => "system",
+            Scope::Global => "global",
+            Scope::Local => "local",
+            Scope::Command => "command",
+        }
+    }
+}
+
+/// Where an entry was read from: a file, or the command line when `path`
+/// is `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Origin {
+    pub scope: Scope,
+    pub path: Option<PathBuf>,
+}
+
+impl Origin {
+    /// The origin as `--show-origin` prints it.
+    pub fn describe(&self) -> String {
+        match &self.path {
+            Some(path) => format!("file:{}", path.display()),
+            None => "command line:".to_string(),
+        }
+    }
+}
+
+/// One `key = value` line, with its section and subsection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigEntry {
+    /// Lower-cased section name.
+    pub section: String,
+    /// Subsection, which unlike the other parts is case-sensitive.
+    pub subsection: Option<String>,
+    /// Lower-cased variable name.
+    pub key: String,
+    /// `None` for a bare `key` line, which means boolean true.
+    pub value: Option<String>,
+    /// `None` for entries parsed from a string.
+    pub origin: Option<Rc<Origin>>,
+}
+
+impl ConfigEntry {
+    /// The dotted `section.subsection.key` name.
+    pub fn name(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!("{}.{}.{}", self.section, subsection, self.key),
+            None => format!("{}.{}", self.section, self.key),
+        }
+    }
+
+    fn is(&self, section: &str, subsection: Option<&str>, key: &str) -> bool {
+        self.section == section && self.subsection.as_deref() == subsection && self.key == key
+    }
+}
+
+/// The git config, from one file or merged from every scope. Later entries
+/// override earlier ones, so entries are kept in precedence order.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+impl Config {
+    /// Reads `path` alone, without following includes, treating a missing
+    /// file as empty.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let mut config = Config::default();
+        config.add_file(path, Scope::Local, None)?;
+        Ok(config)
+    }
+
+    /// Reads everything git would for a repository at `git_dir` (or outside
+    /// any repository): the system config, the global one, the repository's
+    /// own and finally `GIT_CONFIG_COUNT` settings, following includes.
+    pub fn load_all(git_dir: Option<&Path>) -> Result<Config, String> {
+        let includes = IncludeContext { git_dir: git_dir.map(Path::to_path_buf) };
+        let mut config = Config::default();
+        if let Some(path) = Config::system_path() {
+            config.add_file(&path, Scope::System, Some(&includes))?;
+        }
+        for path in Config::global_paths() {
+            config.add_file(&path, Scope::Global, Some(&includes))?;
+        }
+        if let Some(git_dir) = git_dir {
+            config.add_file(&git_dir.join("config"), Scope::Local, Some(&includes))?;
+        }
+        config.add_environment()?;
+        Ok(config)
+    }
+
+    /// `/etc/gitconfig` or `GIT_CONFIG_SYSTEM`, unless `GIT_CONFIG_NOSYSTEM`
+    /// turns the system config off.
+    pub fn system_path() -> Option<PathBuf> {
+        if env::var("GIT_CONFIG_NOSYSTEM").is_ok_and(|value| parse_bool(Some(&value)) == Some(true)) {
+            return None;
+        }
+        Some(env::var_os("GIT_CONFIG_SYSTEM").map_or_else(|| PathBuf::from("/etc/gitconfig"), PathBuf::from))
+    }
+
+    /// The global files in the order they are read: `GIT_CONFIG_GLOBAL`
+    /// alone, or the XDG `git/config` followed by `~/.gitconfig`.
+    pub fn global_paths() -> Vec<PathBuf> {
+        if let Some(path) = env::var_os("GIT_CONFIG_GLOBAL") {
+            return vec![PathBuf::from(path)];
+        }
+        let xdg = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("git/config")),
+            _ => home_dir().map(|home| home.join(".config/git/config")),
+        };
+        xdg.into_iter().chain(home_dir().map(|home| home.join(".gitconfig"))).collect()
+    }
+
+    /// The global file that `--global` reads and writes: `~/.gitconfig`,
+    /// unless only the XDG file exists.
+    pub fn global_path() -> Result<PathBuf, String> {
+        let paths = Config::global_paths();
+        paths
+            .iter()
+            .rev()
+            .find(|path| path.exists())
+            .or(paths.last())
+            .cloned()
+            .ok_or_else(|| "$HOME not set".to_string())
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let entries = parse_items(text)?
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Entry { entry, .. } => Some(entry),
+                Item::Section { .. } => None,
+            })
+            .collect();
+        Ok(Config { entries })
+    }
+
+    /// Appends the entries of `path`, a missing file adding none. With
+    /// `includes`, `include.path` and matching `includeIf.<condition>.path`
+    /// entries pull in the named files right after themselves.
+    pub fn add_file(&mut self, path: &Path, scope: Scope, includes: Option<&IncludeContext>) -> Result<(), String> {
+        self.add_file_at_depth(path, scope, includes, 0)
+    }
+
+    fn add_file_at_depth(
+        &mut self,
+        path: &Path,
+        scope: Scope,
+        includes: Option<&IncludeContext>,
+        depth: usize,
+    ) -> Result<(), String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+        let origin = Rc::new(Origin { scope, path: Some(path.to_path_buf()) });
+        let items = parse_items(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        for item in items {
+            let Item::Entry { mut entry, .. } = item else {
+                continue;
+            };
+            entry.origin = Some(origin.clone());
+            let include = match includes {
+                Some(includes) => includes.target(&entry, path)?,
+                None => None,
+            };
+            self.entries.push(entry);
+
+            if let Some(target) = include {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(format!(
+                        "exceeded maximum include depth ({}) while including\n\t{}\nfrom\n\t{}\n\
+                         This might be due to circular includes.",
+                        MAX_INCLUDE_DEPTH,
+                        target.display(),
+                        path.display()
+                    ));
+                }
+                self.add_file_at_depth(&target, scope, includes, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings passed through `GIT_CONFIG_COUNT` with `GIT_CONFIG_KEY_<n>`
+    /// and `GIT_CONFIG_VALUE_<n>`.
+    fn add_environment(&mut self) -> Result<(), String> {
+        let Ok(count) = env::var("GIT_CONFIG_COUNT") else {
+            return Ok(());
+        };
+        let count: usize = count.trim().parse().map_err(|_| "bogus count in GIT_CONFIG_COUNT")?;
+        let origin = Rc::new(Origin { scope: Scope::Command, path: None });
+        for i in 0..count {
+            let key = env::var(format!("GIT_CONFIG_KEY_{}", i))
+                .map_err(|_| format!("missing config key GIT_CONFIG_KEY_{}", i))?;
+            let value = env::var(format!("GIT_CONFIG_VALUE_{}", i))
+                .map_err(|_| format!("missing config value GIT_CONFIG_VALUE_{}", i))?;
+            let (section, subsection, name) = key_parts(&key)?;
+            self.entries.push(ConfigEntry {
+                section: section.to_ascii_lowercase(),
+                subsection: subsection.map(str::to_string),
+                key: name.to_ascii_lowercase(),
+                value: Some(value),
+                origin: Some(origin.clone()),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+
+    /// Every entry for `name`, in precedence order.
+    pub fn matching(&self, name: &str) -> Vec<&ConfigEntry> {
+        let Some((section, subsection, key)) = split_name(name) else {
+            return Vec::new();
+        };
+        self.entries.iter().filter(|entry| entry.is(&section, subsection, &key)).collect()
+    }
+
+    /// The last value set for `name` (`section.key` or
+    /// `section.subsection.key`); later lines override earlier ones.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.matching(name).last().map(|entry| entry.value.as_deref().unwrap_or("true"))
+    }
+
+    /// Every value set for a multi-valued `name`, in file order.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.matching(name)
+            .into_iter()
+            .map(|entry| entry.value.as_deref().unwrap_or("true"))
+            .collect()
+    }
+
+    /// `name` as a boolean, erroring on a value that is not one.
+    pub fn get_bool(&self, name: &str) -> Result<Option<bool>, String> {
+        let Some(entry) = self.matching(name).pop() else {
+            return Ok(None);
+        };
+        parse_bool(entry.value.as_deref())
+            .map(Some)
+            .ok_or_else(|| format!("bad boolean config value '{}' for '{}'", entry.value.as_deref().unwrap_or(""), name))
+    }
+
+    /// `name` as an integer, which may carry a `k`, `m` or `g` suffix.
+    pub fn get_int(&self, name: &str) -> Result<Option<i64>, String> {
+        let Some(entry) = self.matching(name).pop() else {
+            return Ok(None);
+        };
+        let value = entry.value.as_deref().unwrap_or("");
+        parse_int(value)
+            .map(Some)
+            .ok_or_else(|| format!("bad numeric config value '{}' for '{}': invalid unit", value, name))
+    }
+
+    /// `name` as a path, with a leading `~/` expanded.
+    pub fn get_path(&self, name: &str) -> Result<Option<PathBuf>, String> {
+        match self.matching(name).pop() {
+            Some(entry) => expand_path(entry.value.as_deref().unwrap_or("")).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Splits a dotted name, lower-casing the section and key.
+pub fn split_name(name: &str) -> Option<(String, Option<&str>, String)> {
+    let first = name.find('.')?;
+    let last = name.rfind('.')?;
+    let section = name[..first].to_ascii_lowercase();
+    let key = name[last + 1..].to_ascii_lowercase();
+    let subsection = (last > first).then(|| &name[first + 1..last]);
+    if section.is_empty() || key.is_empty() {
+        return None;
+    }
+    Some((section, subsection, key))
+}
+
+/// Splits a dotted name as written, checking that the section and the key
+/// are names a config file can hold.
+pub fn key_parts(name: &str) -> Result<(&str, Option<&str>, &str), String> {
+    let (Some(first), Some(last)) = (name.find('.'), name.rfind('.')) else {
+        return Err(format!("key does not contain a section: {}", name));
+    };
+    let section = &name[..first];
+    let key = &name[last + 1..];
+    let subsection = (last > first).then(|| &name[first + 1..last]);
+    if section.is_empty() || !section.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+        return Err(format!("invalid key: {}", name));
+    }
+    if key.is_empty() {
+        return Err(format!("key does not contain variable name: {}", name));
+    }
+    if !key.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        || subsection.is_some_and(|sub| sub.contains('\n'))
+    {
+        return Err(format!("invalid key: {}", name));
+    }
+    Ok((section, subsection, key))
+}
+
+/// `true`, `yes`, `on`, `false`, `no`, `off`, the empty string or a number;
+/// a bare key (`None`) is true.
+pub fn parse_bool(value: Option<&str>) -> Option<bool> {
+    let Some(value) = value else {
+        return Some(true);
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" | "" => Some(false),
+        _ => parse_int(value).map(|n| n != 0),
+    }
+}
+
+/// A decimal integer with an optional `k`, `m` or `g` (binary) suffix.
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, factor) = match value.char_indices().last()? {
+        (at, 'k' | 'K') => (&value[..at], 1 << 10),
+        (at, 'm' | 'M') => (&value[..at], 1 << 20),
+        (at, 'g' | 'G') => (&value[..at], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+/// Expands a leading `~/` (or a bare `~`) to the home directory.
+pub fn expand_path(value: &str) -> Result<PathBuf, String> {
+    let rest = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.trim_start_matches('/'),
+        Some(_) => return Err(format!("user lookup is not supported in '{}'", value)),
+        None => return Ok(PathBuf::from(value)),
+    };
+    let home = home_dir().ok_or("$HOME not set")?;
+    Ok(if rest.is_empty() { home } else { home.join(rest) })
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").filter(|home| !home.is_empty()).map(PathBuf::from)
+}
+
+/// Turns a color setting such as `bold red`, `ul #ff0000 black` or `reset`
+/// into the ANSI escape sequence git writes for it. The first color is the
+/// foreground and the second the background; attributes come first in the
+/// sequence, in code order. Returns `None` for a setting git would reject.
+pub fn parse_color(value: &str) -> Option<String> {
+    const ATTRIBUTES: [(&str, u8); 7] =
+        [("bold", 1), ("dim", 2), ("italic", 3), ("ul", 4), ("blink", 5), ("reverse", 7), ("strike", 9)];
+    let mut attributes = Vec::new();
+    let mut negated = Vec::new();
+    let mut colors: Vec<Option<String>> = Vec::new();
+
+    for word in value.split_whitespace() {
+        let lower = word.to_ascii_lowercase();
+        if lower == "reset" {
+            return match value.split_whitespace().count() {
+                1 => Some("\u{1b}[m".to_string()),
+                _ => None,
+            };
+        }
+        if let Some(color) = color_code(&lower) {
+            if colors.len() == 2 {
+                return None;
+            }
+            colors.push(color);
+            continue;
+        }
+        let (name, negate) = match lower.strip_prefix("no") {
+            Some(name) => (name.strip_prefix('-').unwrap_or(name), true),
+            None => (lower.as_str(), false),
+        };
+        let &(_, code) = ATTRIBUTES.iter().find(|(attribute, _)| *attribute == name)?;
+        match negate {
+            // Bold and dim are both turned off by 22.
+            true => negated.push(if code == 1 { 22 } else { 20 + code }),
+            false => attributes.push(code),
+        }
+    }
+
+    attributes.sort_unstable();
+    negated.sort_unstable();
+    negated.dedup();
+    let mut parts: Vec<String> = attributes.iter().chain(&negated).map(u8::to_string).collect();
+    let mut colors = colors.into_iter();
+    if let Some(Some(foreground)) = colors.next() {
+        parts.push(foreground);
+    }
+    if let Some(Some(background)) = colors.next() {
+        // Background codes are the foreground ones plus ten, in each form.
+        let (base, rest) = background.split_once(';').unwrap_or((&background, ""));
+        let base = base.parse::<u8>().ok()? + 10;
+        parts.push(match rest {
+            "" => base.to_string(),
+            rest => format!("{};{}", base, rest),
+        });
+    }
+    match parts.is_empty() {
+        true => Some(String::new()),
+        false => Some(format!("\u{1b}[{}m", parts.join(";"))),
+    }
+}
+
+/// The foreground code for one color word; `Some(None)` for `normal`, which
+/// leaves the color alone.
+fn color_code(word: &str) -> Option<Option<String>> {
+    const NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+    if word == "normal" {
+        return Some(None);
+    }
+    if word == "default" {
+        return Some(Some("39".to_string()));
+    }
+    if let Some(index) = NAMES.iter().position(|name| *name == word) {
+        return Some(Some((30 + index).to_string()));
+    }
+    if let Some(index) = word.strip_prefix("bright").and_then(|name| NAMES.iter().position(|n| *n == name)) {
+        return Some(Some((90 + index).to_string()));
+    }
+    if let Some(hex) = word.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+        return Some(Some(format!("38;2;{};{};{}", channel(0)?, channel(2)?, channel(4)?)));
+    }
+    match word.parse::<i32>().ok()? {
+        -1 => Some(None),
+        n @ 0..=7 => Some(Some((30 + n).to_string())),
+        n @ 8..=15 => Some(Some((90 + n - 8).to_string())),
+        n @ 16..=255 => Some(Some(format!("38;5;{}", n))),
+        _ => None,
+    }
+}
+
+/// What `includeIf` conditions are tested against.
+pub struct IncludeContext {
+    /// The repository being configured, if any; `gitdir:` and `onbranch:`
+    /// conditions never hold outside one.
+    pub git_dir: Option<PathBuf>,
+}
+
+impl IncludeContext {
+    /// The file an `include.path` or matching `includeIf.<condition>.path`
+    /// entry names, relative paths being taken from the including file.
+    fn target(&self, entry: &ConfigEntry, file: &Path) -> Result<Option<PathBuf>, String> {
+        if entry.key != "path" {
+            return Ok(None);
+        }
+        let applies = match (entry.section.as_str(), entry.subsection.as_deref()) {
+            ("include", None) => true,
+            ("includeif", Some(condition)) => self.holds(condition, file),
+            _ => false,
+        };
+        let Some(value) = entry.value.as_deref().filter(|_| applies) else {
+            return Ok(None);
+        };
+        let target = expand_path(value)?;
+        Ok(Some(match target.is_absolute() {
+            true => target,
+            false => file.parent().unwrap_or(Path::new("")).join(target),
+        }))
+    }
+
+    fn holds(&self, condition: &str, file: &Path) -> bool {
+        let Some(git_dir) = &self.git_dir else {
+            return false;
+        };
+        if let Some(pattern) = condition.strip_prefix("gitdir:") {
+            return Self::gitdir_matches(git_dir, pattern, file, false);
+        }
+        if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+            return Self::gitdir_matches(git_dir, pattern, file, true);
+        }
+        if let Some(pattern) = condition.strip_prefix("onbranch:") {
+            let Ok(Some(RefValue::Symbolic(target))) = refs::read_ref(git_dir, "HEAD") else {
+                return false;
+            };
+            let Some(branch) = target.strip_prefix("refs/heads/") else {
+                return false;
+            };
+            let mut pattern = pattern.to_string();
+            if pattern.ends_with('/') {
+                pattern.push_str("**");
+            }
+            return wildmatch(pattern.as_bytes(), branch.as_bytes());
+        }
+        false
+    }
+
+    /// Matches the repository's git directory against a `gitdir:` pattern,
+    /// which may start with `~/` or `./` (the including file's directory),
+    /// matches at any depth unless absolute, and covers everything below a
+    /// directory when it ends in `/`.
+    fn gitdir_matches(git_dir: &Path, pattern: &str, file: &Path, ignore_case: bool) -> bool {
+        let mut pattern = match pattern.strip_prefix("./") {
+            Some(rest) => file.parent().unwrap_or(Path::new("")).join(rest).to_string_lossy().into_owned(),
+            None => match expand_path(pattern) {
+                Ok(path) => path.to_string_lossy().into_owned(),
+                Err(_) => return false,
+            },
+        };
+        if !pattern.starts_with('/') {
+            pattern.insert_str(0, "**/");
+        }
+        if pattern.ends_with('/') {
+            pattern.push_str("**");
+        }
+
+        let lower = |text: &str| if ignore_case { text.to_lowercase() } else { text.to_string() };
+        let pattern = lower(&pattern);
+        let real = fs::canonicalize(git_dir).unwrap_or_else(|_| git_dir.to_path_buf());
+        [git_dir.to_path_buf(), real]
+            .iter()
+            .any(|dir| wildmatch(pattern.as_bytes(), lower(&dir.to_string_lossy()).as_bytes()))
+    }
+}
+
+/// Glob matching with path semantics: `*`, `?` and classes stop at `/`,
+/// while `**` crosses directories and `**/` also matches nothing.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            wildmatch(rest, text)
+                || text.iter().enumerate().any(|(i, &b)| b == b'/' && wildmatch(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| wildmatch(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let run = text.iter().position(|&b| b == b'/').unwrap_or(text.len());
+            (0..=run).any(|i| wildmatch(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => matches!(text.first(), Some(&b) if b != b'/') && wildmatch(rest, &text[1..]),
+        [b'[', rest @ ..] => {
+            let Some((&c, text_rest)) = text.split_first().filter(|(&c, _)| c != b'/') else {
+                return false;
+            };
+            match class_matches(rest, c) {
+                Some((true, after)) => wildmatch(after, text_rest),
+                Some((false, _)) => false,
+                // An unterminated class is a literal bracket.
+                None => c == b'[' && wildmatch(rest, text_rest),
+            }
+        }
+        [b'\\', escaped, rest @ ..] => text.first() == Some(escaped) && wildmatch(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && wildmatch(rest, &text[1..]),
+    }
+}
+
+/// Matches `c` against the class that `pattern` starts just inside of,
+/// returning the result and the pattern after the closing `]`.
+fn class_matches(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut rest) = match pattern {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [] => return None,
+            [b']', after @ ..] if !first => return Some((matched != negated, after)),
+            [low, b'-', high, after @ ..] if *high != b']' => {
+                matched |= (*low..=*high).contains(&c);
+                rest = after;
+            }
+            [b'\\', literal, after @ ..] | [literal, after @ ..] => {
+                matched |= *literal == c;
+                rest = after;
+            }
+        }
+        first = false;
+    }
+}
+
+/// A parsed piece of a config file, with the byte range it spans so that
+/// an editor can replace or remove it.
+enum Item {
+    Section { section: String, subsection: Option<String>, start: usize, end: usize },
+    Entry { entry: ConfigEntry, start: usize, end: usize },
+}
+
+fn parse_items(text: &str) -> Result<Vec<Item>, String> {
+    let mut parser = Parser { text, pos: 0, line: 1 };
+    let mut items = Vec::new();
+    let mut section: Option<(String, Option<String>)> = None;
+
+    loop {
+        let start = parser.pos;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => break,
+            Some('\n') => parser.advance(),
+            Some('#') | Some(';') => parser.skip_line(),
+            Some('[') => {
+                let (name, sub) = parser.section_header()?;
+                parser.skip_whitespace();
+                if parser.peek() == Some('\n') {
+                    parser.advance();
+                }
+                section = Some((name.clone(), sub.clone()));
+                items.push(Item::Section { section: name, subsection: sub, start, end: parser.pos });
+            }
+            Some(_) => {
+                let (name, sub) = section
+                    .clone()
+                    .ok_or_else(|| parser.error("key outside of any section"))?;
+                let (key, value) = parser.variable()?;
+                if parser.peek() == Some('\n') {
+                    parser.advance();
+                }
+                let entry = ConfigEntry { section: name, subsection: sub, key, value, origin: None };
+                items.push(Item::Entry { entry, start, end: parser.pos });
+            }
+        }
+    }
+    Ok(items)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset into `text`.
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn advance(&mut self) {
+        if let Some(c) = self.peek() {
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("bad config line {}: {}", self.line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c != '\n' && c.is_whitespace()) {
+            self.advance();
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.peek() {
+            self.advance();
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// `[section]`, `[section "subsection"]` or the legacy `[section.sub]`.
+    fn section_header(&mut self) -> Result<(String, Option<String>), String> {
+        self.advance();
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                name.push(c);
+                self.advance();
+            } else {
+                break;
+            }
+        }
+
+        self.skip_whitespace();
+        let subsection = if self.peek() == Some('"') {
+            self.advance();
+            let mut sub = String::new();
+            loop {
+                match self.peek() {
+                    Some('"') => {
+                        self.advance();
+                        break;
+                    }
+                    Some('\\') => {
+                        self.advance();
+                        sub.push(self.peek().ok_or_else(|| self.error("unterminated subsection"))?);
+                        self.advance();
+                    }
+                    Some('\n') | None => return Err(self.error("unterminated subsection")),
+                    Some(c) => {
+                        sub.push(c);
+                        self.advance();
+                    }
+                }
+            }
+            Some(sub)
+        } else {
+            None
+        };
+
+        if self.peek() != Some(']') || name.is_empty() {
+            return Err(self.error("invalid section header"));
+        }
+        self.advance();
+
+        match (subsection, name.split_once('.')) {
+            (None, Some((section, sub))) => Ok((section.to_ascii_lowercase(), Some(sub.to_ascii_lowercase()))),
+            (subsection, _) => Ok((name.to_ascii_lowercase(), subsection)),
+        }
+    }
+
+    fn variable(&mut self) -> Result<(String, Option<String>), String> {
+        let mut key = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                key.push(c);
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        if key.is_empty() || !key.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(self.error("invalid variable name"));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            None | Some('\n') | Some('#') | Some(';') => {
+                self.skip_line();
+                return Ok((key.to_ascii_lowercase(), None));
+            }
+            Some('=') => self.advance(),
+            Some(_) => return Err(self.error("expected '='")),
+        }
+        self.skip_whitespace();
+        Ok((key.to_ascii_lowercase(), Some(self.value()?)))
+    }
+
+    /// A value runs to the end of the line or a comment. Quotes protect
+    /// whitespace and comment characters, backslash escapes `\n`, `\t`,
+    /// `\b`, `\"` and `\\`, and a backslash at the end of a line continues
+    /// the value on the next one. Unquoted whitespace reads as spaces, and
+    /// is dropped at the end.
+    fn value(&mut self) -> Result<String, String> {
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut committed_len = 0;
+
+        while let Some(c) = self.peek() {
+            match c {
+                '\n' if !quoted => break,
+                '\n' => return Err(self.error("unterminated quoted value")),
+                '#' | ';' if !quoted => {
+                    self.skip_line();
+                    value.truncate(committed_len);
+                    return Ok(value);
+                }
+                '"' => {
+                    quoted = !quoted;
+                    self.advance();
+                    committed_len = value.len();
+                }
+                '\\' => {
+                    self.advance();
+                    match self.peek() {
+                        Some('\n') => {}
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('b') => value.push('\u{8}'),
+                        Some('"') => value.push('"'),
+                        Some('\\') => value.push('\\'),
+                        _ => return Err(self.error("invalid escape sequence")),
+                    }
+                    self.advance();
+                    committed_len = value.len();
+                }
+                // Unquoted whitespace is kept, but as plain spaces.
+                c if !quoted && c.is_whitespace() => {
+                    value.push(' ');
+                    self.advance();
+                }
+                c => {
+                    value.push(c);
+                    self.advance();
+                    committed_len = value.len();
+                }
+            }
+        }
+        if quoted {
+            return Err(self.error("unterminated quoted value"));
+        }
+        value.truncate(committed_len);
+        Ok(value)
+    }
+}
+
+/// One config file opened for editing under its lock. Edits rewrite only
+/// the lines they touch, so comments and layout elsewhere survive, and
+/// nothing reaches the file until `commit`.
+pub struct ConfigEditor {
+    lock: LockFile,
+    text: String,
+}
+
+impl ConfigEditor {
+    /// Locks `path` and reads it; a missing file starts out empty.
+    pub fn open(path: &Path) -> Result<ConfigEditor, String> {
+        let lock = LockFile::acquire(path)?;
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+        parse_items(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(ConfigEditor { lock, text })
+    }
+
+    fn items(&self) -> Vec<Item> {
+        // Every edit leaves valid text behind, and `open` checked the rest.
+        parse_items(&self.text).expect("config text stays parseable")
+    }
+
+    /// The byte ranges of the entries for `name`, in file order.
+    fn spans(&self, name: &str) -> Result<Vec<(usize, usize)>, String> {
+        let (section, subsection, key) = key_parts(name)?;
+        let (section, key) = (section.to_ascii_lowercase(), key.to_ascii_lowercase());
+        Ok(self
+            .items()
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Entry { entry, start, end } if entry.is(&section, subsection, &key) => Some((start, end)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// How many entries this file has for `name`.
+    pub fn count(&self, name: &str) -> Result<usize, String> {
+        Ok(self.spans(name)?.len())
+    }
+
+    /// Sets `name` to `value`, rewriting the existing entry in place (the
+    /// last one, dropping any others) or adding a new one.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let spans = self.spans(name)?;
+        let Some(&(start, end)) = spans.last() else {
+            return self.add(name, value);
+        };
+        let (_, _, key) = key_parts(name)?;
+        let line = format!("\t{} = {}\n", key, quote_value(value));
+        self.text.replace_range(start..end, &line);
+        for &(start, end) in spans[..spans.len() - 1].iter().rev() {
+            self.text.replace_range(start..end, "");
+        }
+        Ok(())
+    }
+
+    /// Adds another value for `name` after the last entry of its section,
+    /// creating the section at the end of the file if there is none.
+    pub fn add(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let (section, subsection, key) = key_parts(name)?;
+        let line = format!("\t{} = {}\n", key, quote_value(value));
+        let lower = section.to_ascii_lowercase();
+
+        let mut insert_at = None;
+        let mut in_section = false;
+        for item in self.items() {
+            match item {
+                Item::Section { section, subsection: sub, end, .. } => {
+                    in_section = section == lower && sub.as_deref() == subsection;
+                    if in_section {
+                        insert_at = Some(end);
+                    }
+                }
+                Item::Entry { end, .. } if in_section => insert_at = Some(end),
+                Item::Entry { .. } => {}
+            }
+        }
+
+        match insert_at {
+            Some(at) => {
+                let mut text = line;
+                if at > 0 && !self.text[..at].ends_with('\n') {
+                    text.insert(0, '\n');
+                }
+                self.text.insert_str(at, &text);
+            }
+            None => {
+                if !self.text.is_empty() && !self.text.ends_with('\n') {
+                    self.text.push('\n');
+                }
+                match subsection {
+                    Some(sub) => {
+                        let sub = sub.replace('\\', "\\\\").replace('"', "\\\"");
+                        self.text.push_str(&format!("[{} \"{}\"]\n", section, sub));
+                    }
+                    None => self.text.push_str(&format!("[{}]\n", section)),
+                }
+                self.text.push_str(&line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every entry for `name`, returning how many there were, and
+    /// then the section header if that leaves the section empty.
+    pub fn unset(&mut self, name: &str) -> Result<usize, String> {
+        let spans = self.spans(name)?;
+        for &(start, end) in spans.iter().rev() {
+            self.text.replace_range(start..end, "");
+        }
+        if !spans.is_empty() {
+            let (section, subsection, _) = key_parts(name)?;
+            self.remove_empty_sections(&section.to_ascii_lowercase(), subsection);
+        }
+        Ok(spans.len())
+    }
+
+    /// Drops headers of the named section that have nothing but blank
+    /// lines under them; a comment keeps its section.
+    fn remove_empty_sections(&mut self, name: &str, subsection: Option<&str>) {
+        let items = self.items();
+        let mut empty = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let Item::Section { section, subsection: sub, start, end } = item else {
+                continue;
+            };
+            if section != name || sub.as_deref() != subsection {
+                continue;
+            }
+            let next = match items.get(i + 1) {
+                Some(Item::Section { start, .. }) => *start,
+                Some(Item::Entry { .. }) => continue,
+                None => self.text.len(),
+            };
+            if self.text[*end..next].trim().is_empty() {
+                empty.push((*start, next));
+            }
+        }
+        for (start, end) in empty.into_iter().rev() {
+            self.text.replace_range(start..end, "");
+        }
+    }
+
+    /// Writes the edited file into place.
+    pub fn commit(mut self) -> Result<(), String> {
+        self.lock.write_all(self.text.as_bytes())?;
+        self.lock.commit()
+    }
+}
+
+/// Writes a value so that it reads back unchanged: quoted when it has
+/// leading or trailing spaces or comment characters, with backslash,
+/// quote, newline and tab escaped.
+fn quote_value(value: &str) -> String {
+    let quote = value.starts_with(' ') || value.ends_with(' ') || value.contains(['#', ';']);
+    let mut quoted = String::new();
+    if quote {
+        quoted.push('"');
+    }
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    if quote {
+        quoted.push('"');
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempRepo;
+
+    fn values(config: &Config) -> Vec<(String, Option<String>)> {
+        config.entries().iter().map(|entry| (entry.name(), entry.value.clone())).collect()
+    }
+
+    fn entry(name: &str, value: &str) -> (String, Option<String>) {
+        (name.to_string(), Some(value.to_string()))
+    }
+
+    #[test]
+    fn parses_quoting_and_escapes() {
+        let config = Config::parse(
+            "[core]\n\
+             \tplain = some value   \n\
+             \tquoted = \"  keeps # and ; \"  # comment\n\
+             \tmixed = a\" b \"c ; comment\n\
+             \tescapes = tab\\there\\nnew \\\"q\\\" back\\\\slash\\b\n\
+             \tbare\n\
+             \tempty =\n\
+             ; a comment line\n\
+             # another\n",
+        )
+        .unwrap();
+        assert_eq!(
+            values(&config),
+            [
+                entry("core.plain", "some value"),
+                entry("core.quoted", "  keeps # and ; "),
+                entry("core.mixed", "a b c"),
+                entry("core.escapes", "tab\there\nnew \"q\" back\\slash\u{8}"),
+                ("core.bare".to_string(), None),
+                entry("core.empty", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn joins_continuation_lines() {
+        let config = Config::parse("[alias]\n\tlg = log \\\n\t--oneline\\\n \"  x\"\n[b]\n\tk = v\n").unwrap();
+        assert_eq!(values(&config), [entry("alias.lg", "log  --oneline   x"), entry("b.k", "v")]);
+    }
+
+    #[test]
+    fn reads_subsections() {
+        let config = Config::parse(
+            "[Remote \"Origin\"]\n\
+             \tURL = one\n\
+             [remote \"with \\\"quote\\\" and \\\\\"]\n\
+             \turl = two\n\
+             [branch.Main]\n\
+             \tremote = origin\n\
+             [a] k = inline\n",
+        )
+        .unwrap();
+        assert_eq!(
+            values(&config),
+            [
+                entry("remote.Origin.url", "one"),
+                entry("remote.with \"quote\" and \\.url", "two"),
+                entry("branch.main.remote", "origin"),
+                entry("a.k", "inline"),
+            ]
+        );
+        // Section and key are case-insensitive; the subsection is not.
+        assert_eq!(config.get("REMOTE.Origin.Url"), Some("one"));
+        assert_eq!(config.get("remote.origin.url"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for text in ["k = v\n", "[core\n", "[core]\n\tk = \"open\n", "[core]\n\tk = bad\\q\n", "[core]\n\t1k = v\n"] {
+            assert!(Config::parse(text).is_err(), "{:?}", text);
+        }
+        assert_eq!(Config::parse("[core]\n\n\tk v\n").unwrap_err(), "bad config line 3: expected '='");
+    }
+
+    #[test]
+    fn quoted_values_read_back() {
+        for value in ["plain", " leading", "trailing ", "a # b", "semi;colon", "tab\tand\nnewline", "q\"uote\\", ""] {
+            let text = format!("[s]\n\tk = {}\n", quote_value(value));
+            assert_eq!(Config::parse(&text).unwrap().get("s.k"), Some(value), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn typed_getters() {
+        let config = Config::parse(
+            "[n]\n\
+             \tplain = 42\n\
+             \tkilo = 2k\n\
+             \tmega = 3M\n\
+             \tgiga = 1g\n\
+             \tnegative = -5\n\
+             \tspaced = \" 7 \"\n\
+             \tbad = 12x\n\
+             \thuge = 9999999999g\n\
+             [b]\n\
+             \tbare\n\
+             \tyes = YES\n\
+             \toff = off\n\
+             \tempty =\n\
+             \tnumber = 2\n\
+             \tzero = 0\n\
+             \tbad = maybe\n",
+        )
+        .unwrap();
+        assert_eq!(config.get_int("n.plain"), Ok(Some(42)));
+        assert_eq!(config.get_int("n.kilo"), Ok(Some(2048)));
+        assert_eq!(config.get_int("n.mega"), Ok(Some(3 << 20)));
+        assert_eq!(config.get_int("n.giga"), Ok(Some(1 << 30)));
+        assert_eq!(config.get_int("n.negative"), Ok(Some(-5)));
+        assert_eq!(config.get_int("n.spaced"), Ok(Some(7)));
+        assert_eq!(config.get_int("n.missing"), Ok(None));
+        assert_eq!(
+            config.get_int("n.bad"),
+            Err("bad numeric config value '12x' for 'n.bad': invalid unit".to_string())
+        );
+        assert!(config.get_int("n.huge").is_err());
+
+        assert_eq!(config.get_bool("b.bare"), Ok(Some(true)));
+        assert_eq!(config.get_bool("b.yes"), Ok(Some(true)));
+        assert_eq!(config.get_bool("b.off"), Ok(Some(false)));
+        assert_eq!(config.get_bool("b.empty"), Ok(Some(false)));
+        assert_eq!(config.get_bool("b.number"), Ok(Some(true)));
+        assert_eq!(config.get_bool("b.zero"), Ok(Some(false)));
+        assert_eq!(config.get_bool("b.missing"), Ok(None));
+        assert_eq!(config.get_bool("b.bad"), Err("bad boolean config value 'maybe' for 'b.bad'".to_string()));
+        // A bare key reads as "true" through the string getter too.
+        assert_eq!(config.get("b.bare"), Some("true"));
+    }
+
+    #[test]
+    fn later_values_win() {
+        let config = Config::parse("[a]\n\tk = 1\n[b]\n\tk = x\n[a]\n\tk = 2\n").unwrap();
+        assert_eq!(config.get("a.k"), Some("2"));
+        assert_eq!(config.get_all("a.k"), ["1", "2"]);
+    }
+
+    #[test]
+    fn follows_includes() {
+        let t = TempRepo::new();
+        let dir = t.repo.git_dir();
+        fs::write(
+            dir.join("main.cfg"),
+            "[a]\n\tk = before\n\
+             [include]\n\tpath = inc/one.cfg\n\
+             [includeIf \"onbranch:main\"]\n\tpath = branch.cfg\n\
+             [includeIf \"onbranch:other\"]\n\tpath = never.cfg\n\
+             [includeIf \"gitdir:**/.git\"]\n\tpath = gitdir.cfg\n\
+             [a]\n\tk = after\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.join("inc")).unwrap();
+        // Relative paths in an included file are taken from its directory.
+        fs::write(dir.join("inc/one.cfg"), "[a]\n\tk = one\n[include]\n\tpath = two.cfg\n").unwrap();
+        fs::write(dir.join("inc/two.cfg"), "[a]\n\tk = two\n").unwrap();
+        fs::write(dir.join("branch.cfg"), "[a]\n\tk = branch\n").unwrap();
+        fs::write(dir.join("never.cfg"), "[a]\n\tk = never\n").unwrap();
+        fs::write(dir.join("gitdir.cfg"), "[a]\n\tk = gitdir\n").unwrap();
+
+        let includes = IncludeContext { git_dir: Some(dir.to_path_buf()) };
+        let mut config = Config::default();
+        config.add_file(&dir.join("main.cfg"), Scope::Local, Some(&includes)).unwrap();
+        assert_eq!(config.get_all("a.k"), ["before", "one", "two", "branch", "gitdir", "after"]);
+        let origin = config.matching("a.k")[2].origin.clone().unwrap();
+        assert_eq!(origin.path.as_deref(), Some(dir.join("inc/two.cfg").as_path()));
+
+        // Without a repository only plain includes apply; without an
+        // include context none do.
+        let mut config = Config::default();
+        config.add_file(&dir.join("main.cfg"), Scope::Local, Some(&IncludeContext { git_dir: None })).unwrap();
+        assert_eq!(config.get_all("a.k"), ["before", "one", "two", "after"]);
+        assert_eq!(Config::load(&dir.join("main.cfg")).unwrap().get_all("a.k"), ["before", "after"]);
+    }
+
+    #[test]
+    fn circular_includes_stop() {
+        let t = TempRepo::new();
+        let path = t.repo.git_dir().join("loop.cfg");
+        fs::write(&path, "[include]\n\tpath = loop.cfg\n").unwrap();
+        let includes = IncludeContext { git_dir: None };
+        let error = Config::default().add_file(&path, Scope::Local, Some(&includes)).unwrap_err();
+        assert!(error.starts_with("exceeded maximum include depth (10)"), "{}", error);
+    }
+
+    fn edit(text: &str, change: impl FnOnce(&mut ConfigEditor)) -> String {
+        let t = TempRepo::new();
+        let path = t.repo.git_dir().join("edited.cfg");
+        fs::write(&path, text).unwrap();
+        let mut editor = ConfigEditor::open(&path).unwrap();
+        change(&mut editor);
+        editor.commit().unwrap();
+        fs::read_to_string(&path).unwrap()
+    }
+
+    const FILE: &str = "# top comment\n\
+                        [core]\n\
+                        \tbare = true   ; why\n\
+                        \tname = old\n\
+                        \n\
+                        [remote \"origin\"]\n\
+                        \turl = there\n\
+                        \tfetch = one\n\
+                        \tfetch = two\n";
+
+    #[test]
+    fn set_rewrites_only_its_line() {
+        let text = edit(FILE, |editor| editor.set("core.name", "new value").unwrap());
+        assert_eq!(text, FILE.replace("\tname = old\n", "\tname = new value\n"));
+
+        let text = edit(FILE, |editor| editor.set("remote.origin.fetch", "# both").unwrap());
+        assert_eq!(text, FILE.replace("\tfetch = one\n\tfetch = two\n", "\tfetch = \"# both\"\n"));
+
+        let text = edit(FILE, |editor| editor.set("core.added", "x").unwrap());
+        assert_eq!(text, FILE.replace("\tname = old\n", "\tname = old\n\tadded = x\n"));
+
+        let text = edit(FILE, |editor| editor.set("new.sub.key", "y").unwrap());
+        assert_eq!(text, format!("{}[new \"sub\"]\n\tkey = y\n", FILE));
+    }
+
+    #[test]
+    fn unset_drops_lines_and_empty_sections() {
+        let text = edit(FILE, |editor| assert_eq!(editor.unset("remote.origin.fetch").unwrap(), 2));
+        assert_eq!(text, FILE.replace("\tfetch = one\n\tfetch = two\n", ""));
+
+        let text = edit(FILE, |editor| {
+            assert_eq!(editor.unset("core.bare").unwrap(), 1);
+            assert_eq!(editor.unset("core.name").unwrap(), 1);
+            assert_eq!(editor.unset("core.missing").unwrap(), 0);
+        });
+        assert_eq!(text, FILE.replace("[core]\n\tbare = true   ; why\n\tname = old\n\n", ""));
+
+        // A comment keeps its section.
+        let text = edit("[a]\n\t# keep\n\tk = v\n", |editor| assert_eq!(editor.unset("a.k").unwrap(), 1));
+        assert_eq!(text, "[a]\n\t# keep\n");
+    }
+}
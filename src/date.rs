@@ -0,0 +1,303 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::object::signature::{format_offset, parse_offset};
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Seconds since the epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// The local timezone's offset from UTC at `time`, in minutes east.
+#[cfg(unix)]
+pub fn local_offset(time: i64) -> i32 {
+    let time = time as libc::time_t;
+    // SAFETY: `tm` is plain data that localtime_r fills in, and both
+    // pointers are valid for the duration of the call.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        (tm.tm_gmtoff / 60) as i32
+    }
+}
+
+#[cfg(not(unix))]
+pub fn local_offset(_time: i64) -> i32 {
+    0
+}
+
+/// Parses a date in one of the forms git accepts for `GIT_AUTHOR_DATE`:
+/// its internal `[@]<seconds> <+hhmm>`, RFC 2822 (`Thu, 07 Apr 2005
+/// 22:13:13 +0200`) or ISO 8601 (`2005-04-07T22:13:13`, `2005-04-07
+/// 22:13:13 +02:00`). A date without a timezone is taken as local time.
+/// Returns the time and its offset in minutes.
+pub fn parse_date(text: &str) -> Result<(i64, i32), String> {
+    let invalid = || format!("invalid date format: {}", text);
+    let mut date = DateFields::default();
+    for token in text.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+        date.token(token).ok_or_else(invalid)?;
+    }
+
+    if let Some(timestamp) = date.timestamp {
+        return Ok((timestamp, date.offset.unwrap_or_else(|| local_offset(timestamp))));
+    }
+    let (Some(year), Some(month), Some(day)) = (date.year, date.month, date.day) else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let (hour, minute, second) = date.time.unwrap_or((0, 0, 0));
+    let local = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    let offset = match date.offset {
+        Some(offset) => offset,
+        // The offset depends on the instant, which depends on the offset;
+        // one refinement settles it outside the hour around a DST change.
+        None => local_offset(local - local_offset(local) as i64 * 60),
+    };
+    Ok((local - offset as i64 * 60, offset))
+}
+
+/// Parses a date the loose way git does for options like `--since` and
+/// `--expire` and for `@{...}` reflog selectors: `now`, `yesterday`,
+/// relative dates such as `2.weeks.ago` or `3 days 4 hours ago`, or any
+/// date `parse_date` accepts. Returns seconds since the epoch.
+pub fn approxidate(text: &str) -> Result<i64, String> {
+    let now = now();
+    let lower = text.trim().to_ascii_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| c == '.' || c == '_' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    match words.as_slice() {
+        ["now"] => return Ok(now),
+        ["yesterday"] => return Ok(now - 86400),
+        [amounts @ .., "ago"] if !amounts.is_empty() && amounts.len() % 2 == 0 => {
+            let mut time = now;
+            for pair in amounts.chunks(2) {
+                let count: i64 = pair[0].parse().map_err(|_| format!("invalid date format: {}", text))?;
+                time = go_back(time, count, pair[1]).ok_or_else(|| format!("invalid date format: {}", text))?;
+            }
+            return Ok(time);
+        }
+        _ => {}
+    }
+    parse_date(text).map(|(time, _)| time)
+}
+
+/// Steps `count` units back from `time`; months and years go back by the
+/// calendar rather than by a fixed number of seconds.
+fn go_back(time: i64, count: i64, unit: &str) -> Option<i64> {
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    let seconds = match unit {
+        "sec" | "second" => 1,
+        "min" | "minute" => 60,
+        "hour" => 3600,
+        "day" => 86400,
+        "week" => 7 * 86400,
+        "month" | "year" => {
+            let months = if unit == "year" { count * 12 } else { count };
+            let (days, seconds) = (time.div_euclid(86400), time.rem_euclid(86400));
+            let (year, month, day) = civil_from_days(days);
+            let index = year * 12 + month - 1 - months;
+            let (year, month) = (index.div_euclid(12), index.rem_euclid(12) + 1);
+            return Some(days_from_civil(year, month, day) * 86400 + seconds);
+        }
+        _ => return None,
+    };
+    Some(time - count * seconds)
+}
+
+/// The ways `--date` can show a timestamp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `Tue Nov 14 23:13:20 2023 +0100`
+    Default,
+    /// `2023-11-14 23:13:20 +0100`
+    Iso,
+    /// `2023-11-14`
+    Short,
+    /// `Tue, 14 Nov 2023 23:13:20 +0100`
+    Rfc,
+    /// `1700000000 +0100`
+    Raw,
+    /// `1700000000`
+    Unix,
+}
+
+impl DateFormat {
+    pub fn from_name(name: &str) -> Result<DateFormat, String> {
+        match name {
+            "default" => Ok(DateFormat::Default),
+            "iso" | "iso8601" => Ok(DateFormat::Iso),
+            "short" => Ok(DateFormat::Short),
+            "rfc" | "rfc2822" => Ok(DateFormat::Rfc),
+            "raw" => Ok(DateFormat::Raw),
+            "unix" => Ok(DateFormat::Unix),
+            _ => Err(format!("unknown date format {}", name)),
+        }
+    }
+}
+
+/// Shows `time` in the timezone it was recorded in, `offset` minutes east.
+pub fn format_date(time: i64, offset: i32, format: DateFormat) -> String {
+    let zone = format_offset(offset);
+    let local = time + offset as i64 * 60;
+    let days = local.div_euclid(86400);
+    let seconds = local.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    let clock = format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let capitalize = |name: &str| name[..1].to_ascii_uppercase() + &name[1..];
+    let weekday = capitalize(WEEKDAYS[(days + 4).rem_euclid(7) as usize]);
+    let month_name = capitalize(MONTHS[month as usize - 1]);
+    match format {
+        DateFormat::Default => format!("{} {} {} {} {} {}", weekday, month_name, day, clock, year, zone),
+        DateFormat::Iso => format!("{}-{:02}-{:02} {} {}", year, month, day, clock, zone),
+        DateFormat::Short => format!("{}-{:02}-{:02}", year, month, day),
+        DateFormat::Rfc => format!("{}, {} {} {} {} {}", weekday, day, month_name, year, clock, zone),
+        DateFormat::Raw => format!("{} {}", time, zone),
+        DateFormat::Unix => time.to_string(),
+    }
+}
+
+#[derive(Default)]
+struct DateFields {
+    timestamp: Option<i64>,
+    year: Option<i64>,
+    month: Option<i64>,
+    day: Option<i64>,
+    time: Option<(i64, i64, i64)>,
+    offset: Option<i32>,
+}
+
+impl DateFields {
+    /// Takes in one word of a date, returning `None` if it makes no sense.
+    fn token(&mut self, token: &str) -> Option<()> {
+        if let Some(seconds) = token.strip_prefix('@') {
+            self.timestamp = Some(seconds.parse().ok()?);
+            return Some(());
+        }
+        if let Some(offset) = zone(token) {
+            self.offset = Some(offset);
+            return Some(());
+        }
+        if let Some((date, time)) = token.split_once('T').filter(|(date, _)| date.contains('-')) {
+            self.token(date)?;
+            return self.clock(time);
+        }
+        if token.contains(':') {
+            return self.clock(token);
+        }
+        if let Some(parts) = split_date(token) {
+            let (year, month, day) = match parts {
+                [year, month, day] if year > 31 => (year, month, day),
+                // 04/07/2005 is American; 07.04.2005 is European.
+                [month, day, year] if token.contains('/') => (year, month, day),
+                [day, month, year] => (year, month, day),
+            };
+            self.year = Some(year);
+            self.month = Some(month);
+            self.day = Some(day);
+            return Some(());
+        }
+
+        let lower = token.to_ascii_lowercase();
+        if lower.len() >= 3 {
+            if let Some(month) = MONTHS.iter().position(|m| lower.starts_with(m)) {
+                self.month = Some(month as i64 + 1);
+                return Some(());
+            }
+            if WEEKDAYS.iter().any(|d| lower.starts_with(d)) {
+                return Some(());
+            }
+        }
+
+        if !token.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let number: i64 = token.parse().ok()?;
+        match token.len() {
+            // Large numbers are seconds since the epoch, as git writes them.
+            9.. => self.timestamp = Some(number),
+            4 => self.year = Some(number),
+            1 | 2 if self.day.is_none() => self.day = Some(number),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// `hh:mm[:ss[.fraction]]`, perhaps with a timezone stuck to the end.
+    fn clock(&mut self, text: &str) -> Option<()> {
+        let zone_start = text.find(['+', '-', 'Z']).unwrap_or(text.len());
+        if zone_start < text.len() {
+            self.offset = Some(zone(&text[zone_start..])?);
+        }
+        let clock = text[..zone_start].split('.').next()?;
+        let parts: Vec<i64> = clock.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+        let (hour, minute, second) = match parts[..] {
+            [hour, minute] => (hour, minute, 0),
+            [hour, minute, second] => (hour, minute, second),
+            _ => return None,
+        };
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        self.time = Some((hour, minute, second));
+        Some(())
+    }
+}
+
+/// A timezone: `+hhmm`, `+hh:mm`, `Z`, `UTC` or `GMT`.
+fn zone(token: &str) -> Option<i32> {
+    if ["Z", "UTC", "GMT"].contains(&token) {
+        return Some(0);
+    }
+    if !token.starts_with(['+', '-']) {
+        return None;
+    }
+    match token.len() {
+        5 => parse_offset(token),
+        6 if token.as_bytes()[3] == b':' => parse_offset(&token.replacen(':', "", 1)),
+        _ => None,
+    }
+}
+
+/// Splits `2005-04-07`, `04/07/2005` or `07.04.2005` into numbers.
+fn split_date(token: &str) -> Option<[i64; 3]> {
+    let separator = token.chars().find(|c| matches!(c, '-' | '/' | '.'))?;
+    let parts: Vec<i64> = token.split(separator).map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    parts.try_into().ok()
+}
+
+/// Days from 1970-01-01 to the given proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The proleptic Gregorian `(year, month, day)` that is `days` after
+/// 1970-01-01; the inverse of `days_from_civil`.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,207 @@
+use std::cmp::Ordering;
+
+use crate::object::tree::{self, MODE_BLOB, MODE_EXECUTABLE, MODE_GITLINK, MODE_SYMLINK, MODE_TREE};
+use crate::object::{parse_headers, signature, HashKind, ObjectId, ObjectKind};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+fn error(message: impl Into<String>) -> Problem {
+    Problem { severity: Severity::Error, message: message.into() }
+}
+
+fn warning(message: impl Into<String>) -> Problem {
+    Problem { severity: Severity::Warning, message: message.into() }
+}
+
+/// Checks that `data` is a well-formed object of `kind`. Problems are
+/// reported rather than stopping at the first one.
+pub fn check_object(kind: ObjectKind, data: &[u8], hash: HashKind) -> Vec<Problem> {
+    match kind {
+        ObjectKind::Blob => Vec::new(),
+        ObjectKind::Tree => check_tree(data, hash),
+        ObjectKind::Commit => check_commit(data, hash),
+        ObjectKind::Tag => check_tag(data, hash),
+    }
+}
+
+/// Walks the raw tree bytes rather than a parsed `Tree` so that oddities the
+/// parser would normalise away (zero-padded modes) are still visible.
+fn check_tree(data: &[u8], hash: HashKind) -> Vec<Problem> {
+    let hash_len = hash.id_len();
+    let mut problems = Vec::new();
+    let mut previous: Option<(Vec<u8>, bool)> = None;
+    let mut i = 0;
+
+    while i < data.len() {
+        let Some(space) = data[i..].iter().position(|&b| b == b' ') else {
+            problems.push(error("badTree: entry without a mode"));
+            break;
+        };
+        let mode_bytes = &data[i..i + space];
+        i += space + 1;
+        let Some(nul) = data[i..].iter().position(|&b| b == 0) else {
+            problems.push(error("badTree: unterminated entry name"));
+            break;
+        };
+        let name = data[i..i + nul].to_vec();
+        i += nul + 1;
+        if i + hash_len > data.len() {
+            problems.push(error("badTree: truncated entry"));
+            break;
+        }
+        i += hash_len;
+
+        let mode = std::str::from_utf8(mode_bytes).ok().and_then(|m| u32::from_str_radix(m, 8).ok());
+        let Some(mode) = mode else {
+            problems.push(error(format!("badFilemode: invalid mode {:?}", String::from_utf8_lossy(mode_bytes))));
+            continue;
+        };
+        if mode_bytes.first() == Some(&b'0') {
+            problems.push(warning("zeroPaddedFilemode: contains zero-padded file modes"));
+        }
+        match mode {
+            MODE_TREE | MODE_BLOB | MODE_EXECUTABLE | MODE_SYMLINK | MODE_GITLINK => {}
+            0o100664 => problems.push(warning("badFilemode: contains bad file modes")),
+            _ => problems.push(error(format!("badFilemode: contains bad file mode {:o}", mode))),
+        }
+
+        let display = String::from_utf8_lossy(&name).into_owned();
+        if name.is_empty() {
+            problems.push(error("emptyName: contains empty pathname"));
+        } else if name.contains(&b'/') {
+            problems.push(error(format!("fullPathname: contains full pathname {}", display)));
+        } else if name == b"." || name == b".." {
+            problems.push(error(format!("hasDot: contains '{}'", display)));
+        } else if name.eq_ignore_ascii_case(b".git") {
+            problems.push(error("hasDotgit: contains '.git'"));
+        }
+
+        let is_tree = mode == MODE_TREE;
+        if let Some((prev_name, prev_is_tree)) = &previous {
+            if prev_name == &name {
+                problems.push(error(format!("duplicateEntries: contains duplicate file entries ({})", display)));
+            } else if tree::compare_names(prev_name, *prev_is_tree, &name, is_tree) != Ordering::Less {
+                problems.push(error(format!("treeNotSorted: not properly sorted ({})", display)));
+            }
+        }
+        previous = Some((name, is_tree));
+    }
+    problems
+}
+
+fn check_id(value: &[u8], what: &str, hash: HashKind) -> Option<Problem> {
+    let text = String::from_utf8_lossy(value);
+    if text.len() == hash.hex_len() && ObjectId::from_hex(&text).is_ok() {
+        return None;
+    }
+    Some(error(format!("bad{}Sha1: invalid '{}' line format - bad sha1", what, what.to_lowercase())))
+}
+
+fn check_ident(value: &[u8], field: &str) -> Option<Problem> {
+    let text = String::from_utf8_lossy(value);
+    let bad = |id: &str, detail: &str| Some(error(format!("{}: invalid {} line - {}", id, field, detail)));
+
+    let Some(lt) = text.find('<') else { return bad("missingEmail", "missing email") };
+    let Some(gt) = text[lt..].find('>').map(|p| lt + p) else { return bad("badEmail", "bad email") };
+    if text[..lt].contains('>') {
+        return bad("badName", "bad name");
+    }
+    if lt == 0 || !text[..lt].ends_with(' ') {
+        return bad("missingSpaceBeforeEmail", "missing space before email");
+    }
+
+    let rest: Vec<&str> = text[gt + 1..].split(' ').collect();
+    let [leading, time, tz] = rest[..] else { return bad("badDate", "bad date") };
+    let time_ok = !time.is_empty()
+        && time.bytes().all(|b| b.is_ascii_digit())
+        && !(time.len() > 1 && time.starts_with('0'));
+    if !leading.is_empty() || !time_ok {
+        return bad("badDate", "bad date");
+    }
+    if signature::parse_offset(tz).is_none() {
+        return bad("badTimezone", "bad time zone");
+    }
+    None
+}
+
+/// The commit header must start `tree`, then any `parent`s, `author` and
+/// `committer`; anything after that is free-form.
+fn check_commit(data: &[u8], hash: HashKind) -> Vec<Problem> {
+    let headers = match parse_headers(data) {
+        Ok((headers, _)) => headers,
+        Err(e) => return vec![error(e)],
+    };
+    let mut problems = Vec::new();
+    let mut iter = headers.iter().peekable();
+
+    match iter.next() {
+        Some((key, value)) if key == "tree" => problems.extend(check_id(value, "Tree", hash)),
+        _ => return vec![error("missingTree: invalid format - expected 'tree' line")],
+    }
+    while let Some((_, value)) = iter.next_if(|(key, _)| key == "parent") {
+        problems.extend(check_id(value, "Parent", hash));
+    }
+    for field in ["author", "committer"] {
+        match iter.next() {
+            Some((key, value)) if key == field => problems.extend(check_ident(value, field)),
+            _ => {
+                problems.push(error(format!("missing{}: invalid format - expected '{}' line", capitalise(field), field)));
+                break;
+            }
+        }
+    }
+    problems
+}
+
+fn check_tag(data: &[u8], hash: HashKind) -> Vec<Problem> {
+    let headers = match parse_headers(data) {
+        Ok((headers, _)) => headers,
+        Err(e) => return vec![error(e)],
+    };
+    let mut problems = Vec::new();
+    let mut iter = headers.iter().peekable();
+
+    match iter.next() {
+        Some((key, value)) if key == "object" => problems.extend(check_id(value, "Object", hash)),
+        _ => return vec![error("missingObject: invalid format - expected 'object' line")],
+    }
+    match iter.next() {
+        Some((key, value)) if key == "type" => {
+            if ObjectKind::parse(value).is_err() {
+                problems.push(error("badType: invalid 'type' value"));
+            }
+        }
+        _ => return vec![error("missingTypeEntry: invalid format - expected 'type' line")],
+    }
+    match iter.next() {
+        Some((key, value)) if key == "tag" => {
+            if value.is_empty() {
+                problems.push(error("badTagName: invalid 'tag' name"));
+            }
+        }
+        _ => return vec![error("missingTagEntry: invalid format - expected 'tag' line")],
+    }
+    match iter.next_if(|(key, _)| key == "tagger") {
+        Some((_, value)) => problems.extend(check_ident(value, "tagger")),
+        None => problems.push(warning("missingTaggerEntry: invalid format - expected 'tagger' line")),
+    }
+    problems
+}
+
+fn capitalise(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
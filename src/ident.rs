@@ -0,0 +1,82 @@
+use std::env;
+
+use crate::config::Config;
+use crate::date;
+use crate::object::Signature;
+
+/// Whose identity is wanted: the author of a change, or the committer
+/// who recorded it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Author,
+    Committer,
+}
+
+impl Role {
+    fn env_prefix(self) -> &'static str {
+        match self {
+            Role::Author => "GIT_AUTHOR",
+            Role::Committer => "GIT_COMMITTER",
+        }
+    }
+
+    fn config_section(self) -> &'static str {
+        match self {
+            Role::Author => "author",
+            Role::Committer => "committer",
+        }
+    }
+}
+
+/// Works out the identity for `role` the way git does. The name and email
+/// come from `GIT_<ROLE>_NAME` and `GIT_<ROLE>_EMAIL`, then the `<role>.name`
+/// and `<role>.email` config, then `user.name` and `user.email` (and for
+/// the email, `EMAIL`). The time comes from `GIT_<ROLE>_DATE` or the clock,
+/// in the local timezone.
+pub fn identity(config: &Config, role: Role) -> Result<Signature, String> {
+    let lookup = |key: &str| {
+        env::var(format!("{}_{}", role.env_prefix(), key.to_ascii_uppercase()))
+            .ok()
+            .or_else(|| config.get(&format!("{}.{}", role.config_section(), key)).map(str::to_string))
+            .or_else(|| config.get(&format!("user.{}", key)).map(str::to_string))
+    };
+    let name = lookup("name");
+    let email = lookup("email").or_else(|| env::var("EMAIL").ok());
+    let (Some(name), Some(email)) = (name, email) else {
+        return Err(format!(
+            "{} identity unknown\n\n*** Please tell me who you are.\n\nRun\n\n  \
+             git config --global user.email \"you@example.com\"\n  \
+             git config --global user.name \"Your Name\"\n\n\
+             to set your account's default identity.\n\
+             Omit --global to set the identity only in this repository.",
+            match role {
+                Role::Author => "Author",
+                Role::Committer => "Committer",
+            }
+        ));
+    };
+
+    let name = sanitize(&name);
+    let email = sanitize(&email);
+    if name.is_empty() {
+        return Err(format!("empty ident name (for <{}>) not allowed", email));
+    }
+
+    let (time, offset) = match env::var(format!("{}_DATE", role.env_prefix())) {
+        Ok(text) => date::parse_date(&text)?,
+        Err(_) => {
+            let now = date::now();
+            (now, date::local_offset(now))
+        }
+    };
+    Ok(Signature::new(name, email, time, offset))
+}
+
+/// Drops the characters that would break the `name <email>` syntax and
+/// trims punctuation and whitespace git considers crud from both ends.
+fn sanitize(text: &str) -> String {
+    let cleaned: String = text.chars().filter(|c| !matches!(c, '<' | '>' | '\n')).collect();
+    cleaned
+        .trim_matches(|c: char| c.is_whitespace() || matches!(c, '.' | ',' | ':' | ';' | '"' | '\\' | '\''))
+        .to_string()
+}
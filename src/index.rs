@@ -0,0 +1,711 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::lockfile::LockFile;
+use crate::object::{HashKind, ObjectId};
+use crate::odb::ObjectDatabase;
+use crate::worktree;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const HEADER_LEN: usize = 12;
+/// The ten 32-bit stat and mode fields that open every entry.
+const STAT_LEN: usize = 40;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_INTENT_TO_ADD: u16 = 0x2000;
+
+/// Extensions carried over when the index is rewritten. The rest (the
+/// untracked cache, fsmonitor data, the end-of-index offsets) are caches
+/// that describe the old file, and git rebuilds them when they are absent.
+const KEPT_EXTENSIONS: [&[u8; 4]; 2] = [b"TREE", b"REUC"];
+
+/// The file metadata an entry was last seen with, truncated to 32 bits the
+/// way the index stores it. A file whose metadata still matches is assumed
+/// to be unchanged without reading it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stat {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl Stat {
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &fs::Metadata) -> Stat {
+        use std::os::unix::fs::MetadataExt;
+        Stat {
+            ctime: metadata.ctime() as u32,
+            ctime_nsec: metadata.ctime_nsec() as u32,
+            mtime: metadata.mtime() as u32,
+            mtime_nsec: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.len() as u32,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(metadata: &fs::Metadata) -> Stat {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Stat {
+            mtime: mtime.as_secs() as u32,
+            mtime_nsec: mtime.subsec_nanos(),
+            size: metadata.len() as u32,
+            ..Stat::default()
+        }
+    }
+}
+
+/// One staged path. Unmerged paths have an entry per stage (1 for the
+/// common ancestor, 2 for ours, 3 for theirs); everything else is stage 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub stat: Stat,
+    pub mode: u32,
+    pub id: ObjectId,
+    pub stage: u8,
+    pub assume_valid: bool,
+    pub skip_worktree: bool,
+    /// Recorded by `add -N`: the path is tracked but nothing is staged yet.
+    pub intent_to_add: bool,
+    pub path: Vec<u8>,
+}
+
+impl IndexEntry {
+    pub fn new(path: Vec<u8>, mode: u32, id: ObjectId) -> IndexEntry {
+        IndexEntry {
+            stat: Stat::default(),
+            mode,
+            id,
+            stage: 0,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            path,
+        }
+    }
+
+    /// Whether `metadata`, taken with `symlink_metadata`, is what the entry
+    /// recorded. The device number is ignored, as it is by default in git.
+    pub fn stat_matches(&self, metadata: &fs::Metadata) -> bool {
+        let current = Stat { dev: self.stat.dev, ..Stat::from_metadata(metadata) };
+        current == self.stat && worktree::file_mode(metadata) == self.mode
+    }
+
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+}
+
+/// The staging area, `.git/index`: every tracked path with the blob staged
+/// for it, sorted by path and stage. Versions 2 to 4 are read; version 3
+/// adds extended flags, and version 4 prefix-compresses the paths.
+pub struct Index {
+    version: u32,
+    entries: Vec<IndexEntry>,
+    extensions: Vec<([u8; 4], Vec<u8>)>,
+    hash: HashKind,
+    /// When the file was last written, for spotting racily clean entries.
+    timestamp: Option<(u32, u32)>,
+    /// Whether the cached trees in the `TREE` extension still describe
+    /// the entries.
+    cache_tree_valid: bool,
+}
+
+impl Index {
+    pub fn new(hash: HashKind) -> Index {
+        Index {
+            version: 2,
+            entries: Vec::new(),
+            extensions: Vec::new(),
+            hash,
+            timestamp: None,
+            cache_tree_valid: true,
+        }
+    }
+
+    /// Reads the index at `path`, treating a missing file as empty.
+    pub fn load(path: &Path, hash: HashKind) -> Result<Index, String> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Index::new(hash)),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+        let mut index = Index::parse(&data, hash).map_err(|e| format!("{}: {}", path.display(), e))?;
+        index.timestamp = fs::metadata(path).ok().map(|metadata| {
+            let stat = Stat::from_metadata(&metadata);
+            (stat.mtime, stat.mtime_nsec)
+        });
+        Ok(index)
+    }
+
+    pub fn parse(data: &[u8], hash: HashKind) -> Result<Index, String> {
+        let hash_len = hash.id_len();
+        if data.len() < HEADER_LEN + hash_len || &data[..4] != SIGNATURE {
+            return Err("bad index file signature".to_string());
+        }
+        let (body, trailer) = data.split_at(data.len() - hash_len);
+        if hash.digest(body) != trailer {
+            return Err("bad index file checksum".to_string());
+        }
+        let version = read_u32(body, 4);
+        if !(2..=4).contains(&version) {
+            return Err(format!("bad index version {}", version));
+        }
+        let count = read_u32(body, 8) as usize;
+
+        let truncated = || "index file is truncated".to_string();
+        let mut entries: Vec<IndexEntry> = Vec::with_capacity(count);
+        let mut pos = HEADER_LEN;
+        for _ in 0..count {
+            let start = pos;
+            if body.len() < pos + STAT_LEN + hash_len + 2 {
+                return Err(truncated());
+            }
+            let field = |i: usize| read_u32(body, start + i * 4);
+            let stat = Stat {
+                ctime: field(0),
+                ctime_nsec: field(1),
+                mtime: field(2),
+                mtime_nsec: field(3),
+                dev: field(4),
+                ino: field(5),
+                uid: field(7),
+                gid: field(8),
+                size: field(9),
+            };
+            let mode = field(6);
+            pos += STAT_LEN;
+            let id = ObjectId::from_bytes(&body[pos..pos + hash_len]);
+            pos += hash_len;
+            let flags = read_u16(body, pos);
+            pos += 2;
+
+            let mut extended = 0;
+            if flags & FLAG_EXTENDED != 0 {
+                if version < 3 {
+                    return Err(format!("index version {} entry has extended flags", version));
+                }
+                if body.len() < pos + 2 {
+                    return Err(truncated());
+                }
+                extended = read_u16(body, pos);
+                pos += 2;
+            }
+
+            let path = if version == 4 {
+                let (strip, used) = read_varint(&body[pos..]).ok_or_else(truncated)?;
+                pos += used;
+                let previous = entries.last().map(|entry| entry.path.as_slice()).unwrap_or_default();
+                if strip > previous.len() {
+                    return Err("malformed name field in the index".to_string());
+                }
+                let len = body[pos..].iter().position(|&b| b == 0).ok_or_else(truncated)?;
+                let mut path = previous[..previous.len() - strip].to_vec();
+                path.extend_from_slice(&body[pos..pos + len]);
+                pos += len + 1;
+                path
+            } else {
+                // Names of 0xfff bytes or more store 0xfff and rely on the
+                // NUL padding that follows every name.
+                let len = match (flags & FLAG_NAME_MASK) as usize {
+                    len if len < FLAG_NAME_MASK as usize => len,
+                    _ => body[pos..].iter().position(|&b| b == 0).ok_or_else(truncated)?,
+                };
+                let end = start + ((pos - start + len + 8) & !7);
+                if body.len() < end {
+                    return Err(truncated());
+                }
+                let path = body[pos..pos + len].to_vec();
+                pos = end;
+                path
+            };
+
+            entries.push(IndexEntry {
+                stat,
+                mode,
+                id,
+                stage: ((flags >> FLAG_STAGE_SHIFT) & 3) as u8,
+                assume_valid: flags & FLAG_ASSUME_VALID != 0,
+                skip_worktree: extended & EXTENDED_SKIP_WORKTREE != 0,
+                intent_to_add: extended & EXTENDED_INTENT_TO_ADD != 0,
+                path,
+            });
+        }
+
+        let mut extensions = Vec::new();
+        while pos < body.len() {
+            if body.len() < pos + 8 {
+                return Err(truncated());
+            }
+            let signature: [u8; 4] = body[pos..pos + 4].try_into().unwrap();
+            let size = read_u32(body, pos + 4) as usize;
+            pos += 8;
+            if body.len() < pos + size {
+                return Err(truncated());
+            }
+            // Extensions whose names start with a capital letter are
+            // optional; anything else changes how the entries must be read.
+            if !signature[0].is_ascii_uppercase() {
+                return Err(format!(
+                    "index uses {} extension, which we do not understand",
+                    String::from_utf8_lossy(&signature)
+                ));
+            }
+            extensions.push((signature, body[pos..pos + size].to_vec()));
+            pos += size;
+        }
+
+        Ok(Index { version, entries, extensions, hash, timestamp: None, cache_tree_valid: true })
+    }
+
+    /// Encodes the index. Version 2 is upgraded to 3 when an entry needs
+    /// extended flags. Entries modified in the same second as `now` have
+    /// their size recorded as 0, so that a later change within that second
+    /// cannot go unnoticed once the index file itself looks older.
+    pub fn serialize(&self, now: u32) -> Vec<u8> {
+        let version = match self.entries.iter().any(IndexEntry::is_extended) {
+            true => self.version.max(3),
+            false => self.version,
+        };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(SIGNATURE);
+        out.extend_from_slice(&version.to_be_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        let mut previous: &[u8] = &[];
+        for entry in &self.entries {
+            let start = out.len();
+            let stat = &entry.stat;
+            let size = if stat.mtime >= now { 0 } else { stat.size };
+            for field in [
+                stat.ctime, stat.ctime_nsec, stat.mtime, stat.mtime_nsec, stat.dev, stat.ino,
+                entry.mode, stat.uid, stat.gid, size,
+            ] {
+                out.extend_from_slice(&field.to_be_bytes());
+            }
+            out.extend_from_slice(entry.id.as_bytes());
+
+            let mut flags = (entry.path.len().min(FLAG_NAME_MASK as usize) as u16)
+                | ((entry.stage as u16 & 3) << FLAG_STAGE_SHIFT);
+            if entry.assume_valid {
+                flags |= FLAG_ASSUME_VALID;
+            }
+            if entry.is_extended() {
+                flags |= FLAG_EXTENDED;
+            }
+            out.extend_from_slice(&flags.to_be_bytes());
+            if entry.is_extended() {
+                let mut extended = 0u16;
+                if entry.skip_worktree {
+                    extended |= EXTENDED_SKIP_WORKTREE;
+                }
+                if entry.intent_to_add {
+                    extended |= EXTENDED_INTENT_TO_ADD;
+                }
+                out.extend_from_slice(&extended.to_be_bytes());
+            }
+
+            if version == 4 {
+                let common = previous.iter().zip(&entry.path).take_while(|(a, b)| a == b).count();
+                out.extend_from_slice(&write_varint((previous.len() - common) as u64));
+                out.extend_from_slice(&entry.path[common..]);
+                out.push(0);
+                previous = &entry.path;
+            } else {
+                out.extend_from_slice(&entry.path);
+                let len = (out.len() - start + 8) & !7;
+                out.resize(start + len, 0);
+            }
+        }
+
+        for (signature, data) in &self.extensions {
+            if !KEPT_EXTENSIONS.contains(&signature) || (signature == b"TREE" && !self.cache_tree_valid) {
+                continue;
+            }
+            out.extend_from_slice(signature);
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            out.extend_from_slice(data);
+        }
+
+        let checksum = self.hash.digest(&out);
+        out.extend_from_slice(&checksum);
+        out
+    }
+
+    /// Writes the index through `lock`, which should have been taken before
+    /// the index was loaded so that no concurrent update is lost.
+    pub fn write(&self, mut lock: LockFile) -> Result<(), String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+        lock.write_all(&self.serialize(now))?;
+        lock.commit()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: u32) -> Result<(), String> {
+        if !(2..=4).contains(&version) {
+            return Err(format!("index-version {} not in range: 2..4", version));
+        }
+        self.version = version;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Where the entry for `path` at `stage` is, or where it would go.
+    pub fn position(&self, path: &[u8], stage: u8) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|entry| (entry.path.as_slice(), entry.stage).cmp(&(path, stage)))
+    }
+
+    /// The stage 0 entry for `path`.
+    pub fn get(&self, path: &[u8]) -> Option<&IndexEntry> {
+        self.position(path, 0).ok().map(|i| &self.entries[i])
+    }
+
+    /// Whether `path` is tracked at any stage.
+    pub fn contains(&self, path: &[u8]) -> bool {
+        let i = self.position(path, 0).unwrap_or_else(|i| i);
+        self.entries.get(i).is_some_and(|entry| entry.path == path)
+    }
+
+    pub fn has_unmerged(&self) -> bool {
+        self.entries.iter().any(|entry| entry.stage != 0)
+    }
+
+    /// The trees the `TREE` extension still vouches for, or none once the
+    /// entries have changed since it was read. Each cached directory is
+    /// its path, the entry count and subtree count in ASCII, and the tree
+    /// id unless the count is -1 (invalidated).
+    pub fn cache_tree(&self) -> Vec<ObjectId> {
+        let mut trees = Vec::new();
+        let Some((_, data)) = self.extensions.iter().find(|(signature, _)| signature == b"TREE") else {
+            return trees;
+        };
+        if !self.cache_tree_valid {
+            return trees;
+        }
+        let mut data = data.as_slice();
+        let hash_len = self.hash.id_len();
+        while let Some(nul) = data.iter().position(|&b| b == 0) {
+            let Some(newline) = data[nul..].iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            let counts = &data[nul + 1..nul + newline];
+            data = &data[nul + newline + 1..];
+            if counts.starts_with(b"-") {
+                continue;
+            }
+            if data.len() < hash_len {
+                break;
+            }
+            trees.push(ObjectId::from_bytes(&data[..hash_len]));
+            data = &data[hash_len..];
+        }
+        trees
+    }
+
+    /// Whether `entry` was modified so soon before the index was written
+    /// that a later change in the same timestamp tick would leave its stat
+    /// data unchanged; such entries must be checked by content.
+    pub fn is_racy(&self, entry: &IndexEntry) -> bool {
+        match self.timestamp {
+            Some(timestamp) => (entry.stat.mtime, entry.stat.mtime_nsec) >= timestamp,
+            None => false,
+        }
+    }
+
+    /// Stages `entry`. At stage 0 it replaces whatever was staged for its
+    /// path at any stage; a file replacing a directory drops the entries
+    /// below it, and a file below what used to be a file drops that file.
+    /// A higher stage only replaces the same path at the same stage.
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.cache_tree_valid = false;
+        if entry.stage != 0 {
+            match self.position(&entry.path, entry.stage) {
+                Ok(i) => self.entries[i] = entry,
+                Err(i) => self.entries.insert(i, entry),
+            }
+            return;
+        }
+
+        // Everything below a directory sorts together, right after the
+        // names that are smaller than `<dir>/`.
+        let mut dir = entry.path.clone();
+        dir.push(b'/');
+        let start = self.entries.partition_point(|existing| existing.path < dir);
+        let below = self.entries[start..].iter().take_while(|existing| existing.path.starts_with(&dir)).count();
+        self.entries.drain(start..start + below);
+        for (i, _) in entry.path.iter().enumerate().filter(|&(_, &b)| b == b'/') {
+            let above = self.stages(&entry.path[..i]);
+            self.entries.drain(above);
+        }
+        let same = self.stages(&entry.path);
+        self.entries.splice(same, [entry]);
+    }
+
+    /// Where the entries for `path` at every stage are, or where they would
+    /// go.
+    fn stages(&self, path: &[u8]) -> Range<usize> {
+        let start = self.position(path, 0).unwrap_or_else(|i| i);
+        let len = self.entries[start..].iter().take_while(|entry| entry.path == path).count();
+        start..start + len
+    }
+
+    /// Unstages `path` at every stage, returning whether it was tracked.
+    pub fn remove(&mut self, path: &[u8]) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.path != path);
+        self.cache_tree_valid &= self.entries.len() == before;
+        self.entries.len() != before
+    }
+
+    /// Replaces every entry, as reading a tree into the index does.
+    pub fn replace_entries(&mut self, mut entries: Vec<IndexEntry>) {
+        entries.sort_by(|a, b| (&a.path, a.stage).cmp(&(&b.path, b.stage)));
+        self.entries = entries;
+        self.cache_tree_valid = false;
+    }
+
+    /// Records fresh stat data for the entry at `i` after its contents were
+    /// found to match.
+    pub fn refresh_entry(&mut self, i: usize, stat: Stat) {
+        self.entries[i].stat = stat;
+    }
+
+    /// Changes the executable bit of the regular file staged at `i`.
+    pub fn set_mode(&mut self, i: usize, mode: u32) {
+        self.cache_tree_valid &= self.entries[i].mode == mode;
+        self.entries[i].mode = mode;
+    }
+}
+
+/// The blobs and gitlinks of `tree`, recursively, as stage 0 entries with
+/// empty stat data and paths below `prefix`.
+pub fn tree_entries(odb: &ObjectDatabase, tree: &ObjectId, prefix: &[u8]) -> Result<Vec<IndexEntry>, String> {
+    let mut entries = Vec::new();
+    collect_tree(odb, tree, prefix, &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn collect_tree(odb: &ObjectDatabase, tree: &ObjectId, prefix: &[u8], out: &mut Vec<IndexEntry>) -> Result<(), String> {
+    for entry in odb.read_tree(tree)?.entries {
+        let mut path = prefix.to_vec();
+        path.extend_from_slice(&entry.name);
+        if entry.is_tree() {
+            path.push(b'/');
+            collect_tree(odb, &entry.id, &path, out)?;
+        } else {
+            out.push(IndexEntry::new(path, entry.mode, entry.id));
+        }
+    }
+    Ok(())
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes(data[pos..pos + 2].try_into().unwrap())
+}
+
+/// Reads the variable-length integer version 4 uses for the number of
+/// bytes to drop from the previous path: big-endian groups of seven bits,
+/// with one added at each continuation so that every value has a single
+/// encoding. Returns the value and the bytes consumed.
+fn read_varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, &byte) in data.iter().enumerate() {
+        value = value.checked_mul(1 << 7)? | (byte & 0x7f) as usize;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+        value = value.checked_add(1)?;
+    }
+    None
+}
+
+fn write_varint(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    while value >= 0x80 {
+        value = (value >> 7) - 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+    }
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, stage: u8) -> IndexEntry {
+        let id = HashKind::Sha1.digest(path.as_bytes());
+        let mut entry = IndexEntry::new(path.as_bytes().to_vec(), 0o100644, ObjectId::from_bytes(&id));
+        entry.stage = stage;
+        entry.stat = Stat { ctime: 1, mtime: 2, ino: 3, size: 4, ..Stat::default() };
+        entry
+    }
+
+    fn paths(index: &Index) -> Vec<(String, u8)> {
+        index.entries().iter().map(|entry| (String::from_utf8(entry.path.clone()).unwrap(), entry.stage)).collect()
+    }
+
+    fn sample(version: u32) -> Index {
+        let mut index = Index::new(HashKind::Sha1);
+        index.set_version(version).unwrap();
+        for path in ["README", "src/lib.rs", "src/main.rs", "src/bin/tool.rs", "z"] {
+            index.add(entry(path, 0));
+        }
+        index.add(IndexEntry { path: "x".repeat(0x1000).into_bytes(), ..entry("long", 0) });
+        if version >= 3 {
+            index.add(IndexEntry { intent_to_add: true, ..entry("new", 0) });
+            index.add(IndexEntry { skip_worktree: true, ..entry("sparse", 0) });
+        }
+        index
+    }
+
+    /// Cuts the body of `data` short at `len` and seals it with a fresh
+    /// checksum, so only the truncation is wrong.
+    fn truncate(data: &[u8], len: usize) -> Vec<u8> {
+        let mut cut = data[..len].to_vec();
+        let checksum = HashKind::Sha1.digest(&cut);
+        cut.extend_from_slice(&checksum);
+        cut
+    }
+
+    #[test]
+    fn round_trips_every_version() {
+        for version in 2..=4 {
+            let index = sample(version);
+            let data = index.serialize(100);
+            assert_eq!(read_u32(&data, 4), version);
+            let parsed = Index::parse(&data, HashKind::Sha1).unwrap();
+            assert_eq!(parsed.version(), version);
+            assert_eq!(parsed.entries(), index.entries());
+            assert_eq!(parsed.serialize(100), data);
+        }
+    }
+
+    #[test]
+    fn extended_flags_upgrade_version_2() {
+        let mut index = sample(2);
+        index.add(IndexEntry { intent_to_add: true, ..entry("new", 0) });
+        let data = index.serialize(100);
+        assert_eq!(read_u32(&data, 4), 3);
+        let parsed = Index::parse(&data, HashKind::Sha1).unwrap();
+        assert!(parsed.get(b"new").unwrap().intent_to_add);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        for version in 2..=4 {
+            let data = sample(version).serialize(100);
+            let body_len = data.len() - HashKind::Sha1.id_len();
+            for len in HEADER_LEN..body_len {
+                let result = Index::parse(&truncate(&data, len), HashKind::Sha1);
+                assert_eq!(result.err().as_deref(), Some("index file is truncated"), "v{} cut at {}", version, len);
+            }
+        }
+    }
+
+    #[test]
+    fn version_4_compresses_paths_against_the_previous_one() {
+        let mut index = Index::new(HashKind::Sha1);
+        index.set_version(4).unwrap();
+        for path in ["a/b", "a/c", "b"] {
+            index.add(entry(path, 0));
+        }
+        let data = index.serialize(100);
+        // Each entry is the stat data, the id and the flags, then how much
+        // of the previous path to drop and the NUL-terminated rest.
+        let fixed = STAT_LEN + 20 + 2;
+        let first = HEADER_LEN + fixed;
+        assert_eq!(&data[first..first + 5], b"\x00a/b\x00");
+        let second = first + 5 + fixed;
+        assert_eq!(&data[second..second + 3], b"\x01c\x00");
+        let third = second + 3 + fixed;
+        assert_eq!(&data[third..third + 3], b"\x03b\x00");
+        let parsed = Index::parse(&data, HashKind::Sha1).unwrap();
+        assert_eq!(paths(&parsed), [("a/b".into(), 0), ("a/c".into(), 0), ("b".into(), 0)]);
+    }
+
+    #[test]
+    fn version_4_rejects_bad_prefix_lengths() {
+        let mut index = Index::new(HashKind::Sha1);
+        index.set_version(4).unwrap();
+        index.add(entry("a", 0));
+        let mut data = index.serialize(100);
+        let strip = HEADER_LEN + STAT_LEN + 20 + 2;
+        data[strip] = 2;
+        let body_len = data.len() - 20;
+        let result = Index::parse(&truncate(&data, body_len), HashKind::Sha1);
+        assert_eq!(result.err().as_deref(), Some("malformed name field in the index"));
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 0x7f, 0x80, 0x3fff, 0x407f, 0x4080, u32::MAX as u64, u64::MAX >> 8] {
+            let bytes = write_varint(value);
+            assert_eq!(read_varint(&bytes), Some((value as usize, bytes.len())), "{}", value);
+        }
+        assert_eq!(write_varint(0x80), [0x80, 0x00]);
+        assert_eq!(write_varint(0x4080), [0x80, 0x80, 0x00]);
+        assert_eq!(read_varint(&[0x80]), None);
+        assert_eq!(read_varint(&[0xff; 12]), None);
+    }
+
+    #[test]
+    fn add_replaces_files_and_directories() {
+        let mut index = Index::new(HashKind::Sha1);
+        for path in ["a", "b/c", "b/d/e", "b-x", "b.txt", "c"] {
+            index.add(entry(path, 0));
+        }
+        // A file in place of a directory drops everything below it, but not
+        // the names that merely start the same way.
+        index.add(entry("b", 0));
+        let expected = [("a".into(), 0), ("b".into(), 0), ("b-x".into(), 0), ("b.txt".into(), 0), ("c".into(), 0)];
+        assert_eq!(paths(&index), expected);
+        // A file below what was a file drops that file.
+        index.add(entry("a/x/y", 0));
+        assert_eq!(paths(&index)[..2], [("a/x/y".into(), 0), ("b".into(), 0)]);
+
+        // Stage 0 replaces every stage; higher stages sit side by side.
+        index.add(entry("c", 1));
+        index.add(entry("c", 3));
+        index.add(entry("c", 2));
+        assert_eq!(paths(&index)[4..], [("c".into(), 0), ("c".into(), 1), ("c".into(), 2), ("c".into(), 3)]);
+        index.add(entry("c", 0));
+        assert_eq!(paths(&index)[4..], [("c".into(), 0)]);
+        assert_eq!(index.entries().len(), 5);
+    }
+}
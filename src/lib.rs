@@ -0,0 +1,22 @@
+pub mod commands;
+pub mod config;
+pub mod date;
+pub mod fsck;
+pub mod ident;
+pub mod index;
+pub mod lockfile;
+pub mod object;
+pub mod odb;
+pub mod pattern;
+pub mod pack;
+pub mod protocol;
+pub mod quote;
+pub mod reachable;
+pub mod reflog;
+pub mod refs;
+pub mod repo;
+pub mod revision;
+pub mod revwalk;
+#[cfg(test)]
+mod test_support;
+pub mod worktree;
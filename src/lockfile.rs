@@ -0,0 +1,71 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Exclusive access to a file that is being rewritten, held by creating
+/// `<path>.lock`. The new contents are written to the lock file and renamed
+/// over the original on commit, so readers only ever see the old or the
+/// new version; dropping an uncommitted lock discards it.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub fn acquire(path: &Path) -> Result<LockFile, String> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        if let Some(dir) = lock_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => format!(
+                    "Unable to create '{}': File exists.\n\nAnother git process seems to be running in \
+                     this repository. If no other process is running, a previous one probably \
+                     crashed; remove the file manually to continue.",
+                    lock_path.display()
+                ),
+                _ => format!("Unable to create '{}': {}", lock_path.display(), e),
+            })?;
+        Ok(LockFile { path: path.to_path_buf(), lock_path, file: Some(file) })
+    }
+
+    /// The file this lock protects.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        let file = self.file.as_mut().ok_or("Lock file is already closed")?;
+        file.write_all(data)
+            .map_err(|e| format!("Could not write {}: {}", self.lock_path.display(), e))
+    }
+
+    /// Replaces the protected file with what was written.
+    pub fn commit(mut self) -> Result<(), String> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()
+                .map_err(|e| format!("Could not write {}: {}", self.lock_path.display(), e))?;
+        }
+        fs::rename(&self.lock_path, &self.path)
+            .map_err(|e| format!("Could not rename {} into place: {}", self.lock_path.display(), e))?;
+        self.lock_path.clear();
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.lock_path.as_os_str().is_empty() {
+            self.file.take();
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
@@ -1,7 +1,7 @@
-mod commands;
-
 use std::env;
 
+use codecrafters_git::commands;
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     
@@ -18,6 +18,25 @@ fn main() {
         "commit-tree" => commands::commit_tree::CommitTree::run(&args[2..]),
         "write-tree" => commands::write_tree::WriteTree::run(&args[2..]),
         "clone" => commands::clone::Clone::run(&args[2..]),
+        "index-pack" => commands::index_pack::IndexPack::run(&args[2..]),
+        "repack" => commands::repack::Repack::run(&args[2..]),
+        "verify-pack" => commands::verify_pack::VerifyPack::run(&args[2..]),
+        "show-index" => commands::show_index::ShowIndex::run(&args[2..]),
+        "fsck" => commands::fsck::Fsck::run(&args[2..]),
+        "rev-parse" => commands::rev_parse::RevParse::run(&args[2..]),
+        "add" => commands::add::Add::run(&args[2..]),
+        "rm" => commands::rm::Rm::run(&args[2..]),
+        "ls-files" => commands::ls_files::LsFiles::run(&args[2..]),
+        "update-index" => commands::update_index::UpdateIndex::run(&args[2..]),
+        "read-tree" => commands::read_tree::ReadTree::run(&args[2..]),
+        "config" => commands::config::Config::run(&args[2..]),
+        "update-ref" => commands::update_ref::UpdateRef::run(&args[2..]),
+        "symbolic-ref" => commands::symbolic_ref::SymbolicRef::run(&args[2..]),
+        "show-ref" => commands::show_ref::ShowRef::run(&args[2..]),
+        "pack-refs" => commands::pack_refs::PackRefs::run(&args[2..]),
+        "reflog" => commands::reflog::Reflog::run(&args[2..]),
+        "commit" => commands::commit::Commit::run(&args[2..]),
+        "log" => commands::log::Log::run(&args[2..]),
         _ => Err(format!("Unknown command: {}", args[1])),
     };
 
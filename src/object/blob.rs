@@ -0,0 +1,15 @@
+/// File contents, stored verbatim.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Blob {
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    pub fn parse(data: &[u8]) -> Result<Blob, String> {
+        Ok(Blob { data: data.to_vec() })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
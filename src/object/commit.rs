@@ -0,0 +1,125 @@
+use super::signature::Signature;
+use super::{parse_header_end, parse_headers, write_header, write_header_end, Headers, ObjectId};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// Headers other than the ones above (`encoding`, `gpgsig`, `mergetag`,
+    /// ...), kept in their original order.
+    pub extra_headers: Headers,
+    pub message: Vec<u8>,
+    /// How the headers ended when they were not followed by a blank line.
+    header_end: Option<Vec<u8>>,
+}
+
+impl Commit {
+    pub fn new(tree: ObjectId, parents: Vec<ObjectId>, author: Signature, committer: Signature, message: Vec<u8>) -> Commit {
+        Commit { tree, parents, author, committer, extra_headers: Vec::new(), message, header_end: None }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Commit, String> {
+        let (headers, message) = parse_headers(data)?;
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers = Vec::new();
+
+        for (key, value) in headers {
+            match key.as_str() {
+                "tree" if tree.is_none() => tree = Some(parse_id(&value)?),
+                "parent" => parents.push(parse_id(&value)?),
+                "author" if author.is_none() => author = Some(Signature::parse(&value)?),
+                "committer" if committer.is_none() => committer = Some(Signature::parse(&value)?),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+
+        Ok(Commit {
+            tree: tree.ok_or("Malformed commit: missing tree")?,
+            parents,
+            author: author.ok_or("Malformed commit: missing author")?,
+            committer: committer.ok_or("Malformed commit: missing committer")?,
+            extra_headers,
+            message: message.to_vec(),
+            header_end: parse_header_end(data, message),
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_header(&mut out, "tree", self.tree.to_hex().as_bytes());
+        for parent in &self.parents {
+            write_header(&mut out, "parent", parent.to_hex().as_bytes());
+        }
+        write_header(&mut out, "author", &self.author.serialize());
+        write_header(&mut out, "committer", &self.committer.serialize());
+        for (key, value) in &self.extra_headers {
+            write_header(&mut out, key, value);
+        }
+        write_header_end(&mut out, self.header_end.as_deref(), &self.message);
+        out.extend_from_slice(&self.message);
+        out
+    }
+
+    /// The first line of the message.
+    pub fn summary(&self) -> String {
+        let message = String::from_utf8_lossy(&self.message);
+        message.lines().next().unwrap_or("").to_string()
+    }
+}
+
+fn parse_id(value: &[u8]) -> Result<ObjectId, String> {
+    ObjectId::from_hex(&String::from_utf8_lossy(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n";
+    const PARENT: &str = "parent 1111111111111111111111111111111111111111\n";
+
+    fn commit(rest: &[u8]) -> Vec<u8> {
+        [TREE.as_bytes(), PARENT.as_bytes(), rest].concat()
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let objects = [
+            commit(b"author A <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 -0000\n\nsubject\n\nbody\n"),
+            commit(b"author A  <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 +0000\nencoding ISO-8859-1\n\nCaf\xe9\n"),
+            commit(b"author A <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 +0000\ngpgsig -----BEGIN-----\n line\n \n -----END-----\n\nsigned\n"),
+            commit(b"author A <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 +0000\n\n"),
+            commit(b"author A <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 +0000\n"),
+            commit(b"author A <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 +0000"),
+        ];
+        for data in &objects {
+            let parsed = Commit::parse(data).unwrap();
+            assert_eq!(parsed.serialize(), *data, "{}", String::from_utf8_lossy(data));
+        }
+
+        let parsed = Commit::parse(&objects[2]).unwrap();
+        assert_eq!(parsed.parents.len(), 1);
+        assert_eq!(parsed.extra_headers[0].0, "gpgsig");
+        assert_eq!(parsed.extra_headers[0].1, b"-----BEGIN-----\nline\n\n-----END-----");
+        assert_eq!(parsed.summary(), "signed");
+    }
+
+    #[test]
+    fn a_message_added_later_gets_its_blank_line() {
+        let mut parsed = Commit::parse(&commit(b"author A <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 +0000")).unwrap();
+        parsed.message = b"now with a message\n".to_vec();
+        assert!(parsed.serialize().ends_with(b"+0000\n\nnow with a message\n"));
+    }
+
+    #[test]
+    fn requires_the_core_headers() {
+        assert!(Commit::parse(TREE.as_bytes()).is_err());
+        assert!(Commit::parse(b"author A <a@example.com> 1 +0000\ncommitter C <c@example.com> 2 +0000\n\nx").is_err());
+    }
+}
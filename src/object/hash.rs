@@ -0,0 +1,124 @@
+use std::io::{Read, Write};
+
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use super::{encode_header, ObjectId, ObjectKind};
+
+/// The hash function a repository names its objects with, chosen by the
+/// `extensions.objectFormat` config setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HashKind {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashKind {
+    /// Length of a raw id in bytes.
+    pub fn id_len(self) -> usize {
+        match self {
+            HashKind::Sha1 => 20,
+            HashKind::Sha256 => 32,
+        }
+    }
+
+    pub fn hex_len(self) -> usize {
+        self.id_len() * 2
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashKind::Sha1 => "sha1",
+            HashKind::Sha256 => "sha256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<HashKind, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha1" => Ok(HashKind::Sha1),
+            "sha256" => Ok(HashKind::Sha256),
+            _ => Err(format!("Unknown object format '{}'", name)),
+        }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            HashKind::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashKind::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// The id `data` would have when stored as an object of `kind`.
+    pub fn hash_object(self, kind: ObjectKind, data: &[u8]) -> ObjectId {
+        let mut hasher = self.hasher();
+        hasher.update(&encode_header(kind, data.len()));
+        hasher.update(data);
+        ObjectId::from_bytes(&hasher.finalize())
+    }
+
+    /// Hashes an object of `kind` whose `size` bytes are read from `reader`
+    /// a chunk at a time, copying the encoded object (header included) to
+    /// `out` as it goes. Fails if `reader` does not hold exactly `size`
+    /// bytes, as when a file changes while it is being read.
+    pub fn hash_stream(
+        self,
+        kind: ObjectKind,
+        size: u64,
+        reader: &mut dyn Read,
+        out: &mut dyn Write,
+    ) -> Result<ObjectId, String> {
+        let mut hasher = self.hasher();
+        let header = encode_header(kind, size as usize);
+        hasher.update(&header);
+        out.write_all(&header).map_err(|e| e.to_string())?;
+
+        let mut buf = vec![0; 64 * 1024];
+        let mut total = 0u64;
+        loop {
+            let n = reader.read(&mut buf).map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            total += n as u64;
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+        }
+        if total != size {
+            return Err(format!("Expected {} bytes of content but read {}", size, total));
+        }
+        Ok(ObjectId::from_bytes(&hasher.finalize()))
+    }
+
+    /// The all-zero id, used where git means "no object".
+    pub fn null_id(self) -> ObjectId {
+        ObjectId::from_bytes(&vec![0; self.id_len()])
+    }
+}
+
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+pub mod blob;
+pub mod commit;
+pub mod hash;
+pub mod signature;
+pub mod tag;
+pub mod tree;
+
+use std::fmt;
+
+pub use blob::Blob;
+pub use commit::Commit;
+pub use hash::{HashKind, Hasher};
+pub use signature::Signature;
+pub use tag::Tag;
+pub use tree::{Tree, TreeEntry};
+
+/// The name of an object: the raw bytes of the hash over its header and content.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(Vec<u8>);
+
+impl ObjectId {
+    pub fn from_bytes(bytes: &[u8]) -> ObjectId {
+        ObjectId(bytes.to_vec())
+    }
+
+    /// Parses a full SHA-1 or SHA-256 id.
+    pub fn from_hex(hex: &str) -> Result<ObjectId, String> {
+        if hex.len() != HashKind::Sha1.hex_len() && hex.len() != HashKind::Sha256.hex_len() {
+            return Err(format!("Not a valid object name: {}", hex));
+        }
+        hex::decode(hex)
+            .map(ObjectId)
+            .map_err(|_| format!("Not a valid object name: {}", hex))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.0)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ObjectKind {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+impl ObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Blob => "blob",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tag => "tag",
+        }
+    }
+
+    pub fn parse(name: &[u8]) -> Result<ObjectKind, String> {
+        match name {
+            b"blob" => Ok(ObjectKind::Blob),
+            b"tree" => Ok(ObjectKind::Tree),
+            b"commit" => Ok(ObjectKind::Commit),
+            b"tag" => Ok(ObjectKind::Tag),
+            _ => Err(format!("Unknown object type: {}", String::from_utf8_lossy(name))),
+        }
+    }
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Builds the `<type> <size>\0` header that prefixes every stored object.
+pub fn encode_header(kind: ObjectKind, size: usize) -> Vec<u8> {
+    format!("{} {}\0", kind, size).into_bytes()
+}
+
+/// Splits a decompressed object into its type, declared size and the offset
+/// at which the content starts.
+pub fn parse_header(data: &[u8]) -> Result<(ObjectKind, usize, usize), String> {
+    let nul = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or("Object header is not terminated")?;
+    let header = &data[..nul];
+    let space = header
+        .iter()
+        .position(|&b| b == b' ')
+        .ok_or("Object header has no size")?;
+    let kind = ObjectKind::parse(&header[..space])?;
+    let size = std::str::from_utf8(&header[space + 1..])
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .ok_or("Object header has an invalid size")?;
+    Ok((kind, size, nul + 1))
+}
+
+/// A parsed object of any kind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Object {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl Object {
+    /// Parses `data` as an object of `kind`; `hash` gives the width of the
+    /// raw ids inside trees.
+    pub fn parse(kind: ObjectKind, data: &[u8], hash: HashKind) -> Result<Object, String> {
+        Ok(match kind {
+            ObjectKind::Blob => Object::Blob(Blob::parse(data)?),
+            ObjectKind::Tree => Object::Tree(Tree::parse(data, hash)?),
+            ObjectKind::Commit => Object::Commit(Commit::parse(data)?),
+            ObjectKind::Tag => Object::Tag(Tag::parse(data)?),
+        })
+    }
+
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            Object::Blob(_) => ObjectKind::Blob,
+            Object::Tree(_) => ObjectKind::Tree,
+            Object::Commit(_) => ObjectKind::Commit,
+            Object::Tag(_) => ObjectKind::Tag,
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            Object::Blob(blob) => blob.serialize(),
+            Object::Tree(tree) => tree.serialize(),
+            Object::Commit(commit) => commit.serialize(),
+            Object::Tag(tag) => tag.serialize(),
+        }
+    }
+}
+
+/// Header lines of a commit or tag, in order, as `(key, value)` pairs.
+pub type Headers = Vec<(String, Vec<u8>)>;
+
+/// Splits a commit or tag body into its `key value` header lines and the
+/// message that follows the first blank line. Continuation lines (starting
+/// with a space) are folded into the previous header with a newline.
+pub fn parse_headers(data: &[u8]) -> Result<(Headers, &[u8]), String> {
+    let mut headers: Headers = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let end = data[i..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|p| i + p)
+            .unwrap_or(data.len());
+        let line = &data[i..end];
+        i = (end + 1).min(data.len());
+
+        if line.is_empty() {
+            return Ok((headers, &data[i..]));
+        }
+        if line[0] == b' ' {
+            let (_, value) = headers
+                .last_mut()
+                .ok_or("Malformed object: continuation line without a header")?;
+            value.push(b'\n');
+            value.extend_from_slice(&line[1..]);
+            continue;
+        }
+
+        let (key, value) = match line.iter().position(|&b| b == b' ') {
+            Some(space) => (&line[..space], &line[space + 1..]),
+            None => (line, &line[line.len()..]),
+        };
+        headers.push((String::from_utf8_lossy(key).into_owned(), value.to_vec()));
+    }
+
+    Ok((headers, &data[data.len()..]))
+}
+
+/// How the header block of `data` ends, given the `message` that
+/// `parse_headers` split off from it. `None` is the usual blank line; a
+/// message-less object may instead stop right after its last header, or
+/// even before that header's newline, and then the bytes actually there
+/// are returned.
+pub(crate) fn parse_header_end(data: &[u8], message: &[u8]) -> Option<Vec<u8>> {
+    let headers = &data[..data.len() - message.len()];
+    match headers.ends_with(b"\n\n") {
+        true => None,
+        false => Some(headers.strip_suffix(b"\n").map_or(Vec::new(), |_| b"\n".to_vec())),
+    }
+}
+
+/// Ends a header block written with `write_header` the way
+/// `parse_header_end` found it, or with a blank line. Only an empty message
+/// can follow anything but the blank line.
+pub(crate) fn write_header_end(out: &mut Vec<u8>, end: Option<&[u8]>, message: &[u8]) {
+    match end {
+        Some(end) if message.is_empty() => {
+            out.pop();
+            out.extend_from_slice(end);
+        }
+        _ => out.push(b'\n'),
+    }
+}
+
+/// Writes one header, re-indenting any embedded newlines as continuation lines.
+pub(crate) fn write_header(out: &mut Vec<u8>, key: &str, value: &[u8]) {
+    out.extend_from_slice(key.as_bytes());
+    out.push(b' ');
+    for &b in value {
+        out.push(b);
+        if b == b'\n' {
+            out.push(b' ');
+        }
+    }
+    out.push(b'\n');
+}
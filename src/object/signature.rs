@@ -0,0 +1,168 @@
+use std::fmt;
+
+/// An identity line as found in commit `author`/`committer` and tag
+/// `tagger` headers: `Name <email> <unix time> <+hhmm>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub time: i64,
+    /// Offset from UTC in minutes.
+    pub offset: i32,
+    /// The line as it was parsed, written back out for as long as the fields
+    /// above still describe it.
+    raw: Option<RawSignature>,
+}
+
+/// The three parts of an identity line either side of `<email>`, kept as
+/// bytes: names need not be UTF-8 or tidily spaced, and a `-0000` timezone
+/// reads the same as `+0000` but is not stored the same.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct RawSignature {
+    name: Vec<u8>,
+    email: Vec<u8>,
+    date: Vec<u8>,
+}
+
+impl Signature {
+    pub fn new(name: String, email: String, time: i64, offset: i32) -> Signature {
+        Signature { name, email, time, offset, raw: None }
+    }
+
+    pub fn parse(line: &[u8]) -> Result<Signature, String> {
+        let lt = line.iter().position(|&b| b == b'<').ok_or("Malformed identity: missing '<'")?;
+        let gt = line.iter().rposition(|&b| b == b'>').ok_or("Malformed identity: missing '>'")?;
+        if gt < lt {
+            return Err("Malformed identity: misplaced '>'".to_string());
+        }
+        let raw = RawSignature {
+            name: line[..lt].to_vec(),
+            email: line[lt + 1..gt].to_vec(),
+            date: line[gt + 1..].to_vec(),
+        };
+        let mut signature = raw.interpret()?;
+        signature.raw = Some(raw);
+        Ok(signature)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        match &self.raw {
+            Some(raw) if raw.interpret().is_ok_and(|parsed| self.same_fields(&parsed)) => {
+                let mut out = raw.name.clone();
+                out.push(b'<');
+                out.extend_from_slice(&raw.email);
+                out.push(b'>');
+                out.extend_from_slice(&raw.date);
+                out
+            }
+            _ => format!("{} <{}> {} {}", self.name, self.email, self.time, format_offset(self.offset)).into_bytes(),
+        }
+    }
+
+    /// The `+hhmm` form of the timezone offset, as written when there is one.
+    pub fn offset_string(&self) -> String {
+        let written = self.raw.as_ref().and_then(|raw| {
+            let date = String::from_utf8_lossy(&raw.date).into_owned();
+            let tz = date.split_whitespace().nth(1)?.to_string();
+            (parse_offset(&tz) == Some(self.offset)).then_some(tz)
+        });
+        written.unwrap_or_else(|| format_offset(self.offset))
+    }
+
+    fn same_fields(&self, other: &Signature) -> bool {
+        (&self.name, &self.email, self.time, self.offset) == (&other.name, &other.email, other.time, other.offset)
+    }
+}
+
+impl RawSignature {
+    /// The fields the raw parts stand for.
+    fn interpret(&self) -> Result<Signature, String> {
+        let name = String::from_utf8_lossy(&self.name).trim_end().to_string();
+        let email = String::from_utf8_lossy(&self.email).into_owned();
+        let date = String::from_utf8_lossy(&self.date);
+        let mut rest = date.split_whitespace();
+        let time = rest
+            .next()
+            .and_then(|t| t.parse::<i64>().ok())
+            .ok_or("Malformed identity: invalid timestamp")?;
+        let offset = rest
+            .next()
+            .and_then(parse_offset)
+            .ok_or("Malformed identity: invalid timezone")?;
+        Ok(Signature::new(name, email, time, offset))
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.serialize()))
+    }
+}
+
+/// Parses a `+hhmm`/`-hhmm` timezone into minutes east of UTC.
+pub fn parse_offset(tz: &str) -> Option<i32> {
+    let (sign, digits) = match tz.as_bytes().first()? {
+        b'+' => (1, &tz[1..]),
+        b'-' => (-1, &tz[1..]),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+pub fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let abs = offset.abs();
+    format!("{}{:02}{:02}", sign, abs / 60, abs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_fields() {
+        let signature = Signature::parse(b"A U Thor <author@example.com> 1700000000 -0130").unwrap();
+        assert_eq!(signature.name, "A U Thor");
+        assert_eq!(signature.email, "author@example.com");
+        assert_eq!(signature.time, 1700000000);
+        assert_eq!(signature.offset, -90);
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let lines: [&[u8]; 5] = [
+            b"A U Thor <author@example.com> 1700000000 +0100",
+            b"Negative Zero <nz@example.com> 1700000000 -0000",
+            b"Wide Gap   <gap@example.com>  1700000000  +0000",
+            b"Caf\xe9 <latin1@example.com> 0 +0000",
+            b"<nobody@example.com> 1 +1400",
+        ];
+        for line in lines {
+            assert_eq!(Signature::parse(line).unwrap().serialize(), line);
+        }
+        let negative_zero = Signature::parse(lines[1]).unwrap();
+        assert_eq!(negative_zero.offset, 0);
+        assert_eq!(negative_zero.offset_string(), "-0000");
+    }
+
+    #[test]
+    fn edited_fields_are_written_out() {
+        let mut signature = Signature::parse(b"Wide Gap   <gap@example.com> 1 -0000").unwrap();
+        signature.time = 2;
+        assert_eq!(signature.serialize(), b"Wide Gap <gap@example.com> 2 +0000");
+        assert_eq!(Signature::new("N".to_string(), "e".to_string(), 3, 330).to_string(), "N <e> 3 +0530");
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(Signature::parse(b"No Email 1 +0000").is_err());
+        assert!(Signature::parse(b"Backwards >e< 1 +0000").is_err());
+        assert!(Signature::parse(b"Bad Zone <e> 1 0100").is_err());
+        assert!(Signature::parse(b"Bad Time <e> soon +0000").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use super::signature::Signature;
+use super::{parse_header_end, parse_headers, write_header, write_header_end, Headers, ObjectId, ObjectKind};
+
+/// An annotated tag pointing at another object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub object: ObjectId,
+    pub kind: ObjectKind,
+    pub name: String,
+    pub tagger: Option<Signature>,
+    pub extra_headers: Headers,
+    pub message: Vec<u8>,
+    /// How the headers ended when they were not followed by a blank line.
+    header_end: Option<Vec<u8>>,
+}
+
+impl Tag {
+    pub fn parse(data: &[u8]) -> Result<Tag, String> {
+        let (headers, message) = parse_headers(data)?;
+
+        let mut object = None;
+        let mut kind = None;
+        let mut name = None;
+        let mut tagger = None;
+        let mut extra_headers = Vec::new();
+
+        for (key, value) in headers {
+            match key.as_str() {
+                "object" if object.is_none() => {
+                    object = Some(ObjectId::from_hex(&String::from_utf8_lossy(&value))?)
+                }
+                "type" if kind.is_none() => kind = Some(ObjectKind::parse(&value)?),
+                "tag" if name.is_none() => name = Some(String::from_utf8_lossy(&value).into_owned()),
+                "tagger" if tagger.is_none() => tagger = Some(Signature::parse(&value)?),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+
+        Ok(Tag {
+            object: object.ok_or("Malformed tag: missing object")?,
+            kind: kind.ok_or("Malformed tag: missing type")?,
+            name: name.ok_or("Malformed tag: missing tag name")?,
+            tagger,
+            extra_headers,
+            message: message.to_vec(),
+            header_end: parse_header_end(data, message),
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_header(&mut out, "object", self.object.to_hex().as_bytes());
+        write_header(&mut out, "type", self.kind.as_str().as_bytes());
+        write_header(&mut out, "tag", self.name.as_bytes());
+        if let Some(tagger) = &self.tagger {
+            write_header(&mut out, "tagger", &tagger.serialize());
+        }
+        for (key, value) in &self.extra_headers {
+            write_header(&mut out, key, value);
+        }
+        write_header_end(&mut out, self.header_end.as_deref(), &self.message);
+        out.extend_from_slice(&self.message);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEAD: &[u8] = b"object 1111111111111111111111111111111111111111\ntype commit\ntag v1.0\n";
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let objects = [
+            [HEAD, b"tagger T <t@example.com> 1 -0000\n\nRelease 1.0\n"].concat(),
+            [HEAD, b"tagger T   <t@example.com> 1 +0000\n\n"].concat(),
+            [HEAD, b"tagger T <t@example.com> 1 +0000\n"].concat(),
+            // Very old tags have no tagger at all.
+            [HEAD, b"\nancient\n"].concat(),
+            HEAD.to_vec(),
+        ];
+        for data in &objects {
+            let parsed = Tag::parse(data).unwrap();
+            assert_eq!(parsed.serialize(), *data, "{}", String::from_utf8_lossy(data));
+        }
+
+        let parsed = Tag::parse(&objects[0]).unwrap();
+        assert_eq!(parsed.kind, ObjectKind::Commit);
+        assert_eq!(parsed.name, "v1.0");
+        assert_eq!(parsed.tagger.unwrap().offset_string(), "-0000");
+        assert!(Tag::parse(&objects[3]).unwrap().tagger.is_none());
+    }
+
+    #[test]
+    fn requires_object_type_and_name() {
+        assert!(Tag::parse(b"type commit\ntag v1\n\n").is_err());
+        assert!(Tag::parse(b"object 1111111111111111111111111111111111111111\ntag v1\n\n").is_err());
+    }
+}
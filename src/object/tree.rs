@@ -0,0 +1,180 @@
+use std::cmp::Ordering;
+
+use super::{HashKind, ObjectId, ObjectKind};
+
+pub const MODE_TREE: u32 = 0o40000;
+pub const MODE_BLOB: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+pub const MODE_GITLINK: u32 = 0o160000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: u32,
+    pub name: Vec<u8>,
+    pub id: ObjectId,
+    /// The mode as written, when that is not git's own spelling of `mode`
+    /// (`040000` for `40000`, say), so the tree still hashes the same.
+    raw_mode: Option<Vec<u8>>,
+}
+
+impl TreeEntry {
+    pub fn new(mode: u32, name: Vec<u8>, id: ObjectId) -> TreeEntry {
+        TreeEntry { mode, name, id, raw_mode: None }
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.mode == MODE_TREE
+    }
+
+    /// The kind of object the entry points at; gitlinks name a commit in
+    /// another repository.
+    pub fn kind(&self) -> ObjectKind {
+        match self.mode {
+            MODE_TREE => ObjectKind::Tree,
+            MODE_GITLINK => ObjectKind::Commit,
+            _ => ObjectKind::Blob,
+        }
+    }
+
+    pub fn name_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.name).into_owned()
+    }
+}
+
+/// git's tree entry order: bytewise by name, except that a tree's name is
+/// compared as if it ended in `/`, so `foo` (a directory) sorts after
+/// `foo.c` but before `foo0`.
+pub fn compare_names(a: &[u8], a_is_tree: bool, b: &[u8], b_is_tree: bool) -> Ordering {
+    let common = a.len().min(b.len());
+    match a[..common].cmp(&b[..common]) {
+        Ordering::Equal => {}
+        other => return other,
+    }
+    let next = |name: &[u8], is_tree: bool| match name.get(common) {
+        Some(&b) => Some(b),
+        None if is_tree => Some(b'/'),
+        None => None,
+    };
+    next(a, a_is_tree).cmp(&next(b, b_is_tree))
+}
+
+/// A directory listing: `<octal mode> <name>\0<raw id>` repeated.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    pub fn parse(data: &[u8], hash: HashKind) -> Result<Tree, String> {
+        let hash_len = hash.id_len();
+        let mut entries = Vec::new();
+        let mut i = 0;
+
+        while i < data.len() {
+            let space = data[i..]
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or("Malformed tree entry: missing mode")?;
+            let mode_str = std::str::from_utf8(&data[i..i + space])
+                .map_err(|_| "Malformed tree entry: invalid mode")?;
+            let mode = u32::from_str_radix(mode_str, 8)
+                .map_err(|_| format!("Malformed tree entry: invalid mode {}", mode_str))?;
+            let raw_mode = (format!("{:o}", mode) != mode_str).then(|| mode_str.as_bytes().to_vec());
+            i += space + 1;
+
+            let nul = data[i..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or("Malformed tree entry: unterminated name")?;
+            let name = data[i..i + nul].to_vec();
+            i += nul + 1;
+
+            if i + hash_len > data.len() {
+                return Err("Malformed tree entry: truncated object id".to_string());
+            }
+            let id = ObjectId::from_bytes(&data[i..i + hash_len]);
+            i += hash_len;
+
+            entries.push(TreeEntry { mode, name, id, raw_mode });
+        }
+
+        Ok(Tree { entries })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for entry in &self.entries {
+            match &entry.raw_mode {
+                Some(raw) if u32::from_str_radix(&String::from_utf8_lossy(raw), 8) == Ok(entry.mode) => {
+                    out.extend_from_slice(raw);
+                }
+                _ => out.extend_from_slice(format!("{:o}", entry.mode).as_bytes()),
+            }
+            out.push(b' ');
+            out.extend_from_slice(&entry.name);
+            out.push(0);
+            out.extend_from_slice(entry.id.as_bytes());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mode: &[u8], name: &[u8], id: u8) -> Vec<u8> {
+        let mut out = mode.to_vec();
+        out.push(b' ');
+        out.extend_from_slice(name);
+        out.push(0);
+        out.extend_from_slice(&[id; 20]);
+        out
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let data = [
+            entry(b"100644", b"README", 1),
+            entry(b"100755", b"build.sh", 2),
+            entry(b"040000", b"legacy", 3),
+            entry(b"40000", b"src", 4),
+            entry(b"100664", b"group-writable", 5),
+            entry(b"120000", b"link", 6),
+            entry(b"160000", b"\xffmodule", 7),
+        ]
+        .concat();
+        let tree = Tree::parse(&data, HashKind::Sha1).unwrap();
+        assert_eq!(tree.entries.len(), 7);
+        assert_eq!(tree.entries[2].mode, MODE_TREE);
+        assert!(tree.entries[2].is_tree());
+        assert_eq!(tree.entries[6].kind(), ObjectKind::Commit);
+        assert_eq!(tree.serialize(), data);
+    }
+
+    #[test]
+    fn edited_modes_are_written_canonically() {
+        let mut tree = Tree::parse(&entry(b"040000", b"dir", 1), HashKind::Sha1).unwrap();
+        tree.entries[0].mode = MODE_BLOB;
+        assert_eq!(tree.serialize(), entry(b"100644", b"dir", 1));
+        let built = Tree { entries: vec![TreeEntry::new(MODE_TREE, b"dir".to_vec(), ObjectId::from_bytes(&[1; 20]))] };
+        assert_eq!(built.serialize(), entry(b"40000", b"dir", 1));
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        assert!(Tree::parse(b"100644 name", HashKind::Sha1).is_err());
+        assert!(Tree::parse(b"10x644 name\0", HashKind::Sha1).is_err());
+        let mut truncated = entry(b"100644", b"name", 1);
+        truncated.pop();
+        assert!(Tree::parse(&truncated, HashKind::Sha1).is_err());
+    }
+
+    #[test]
+    fn trees_sort_as_if_they_ended_in_a_slash() {
+        assert_eq!(compare_names(b"foo", true, b"foo.c", false), Ordering::Greater);
+        assert_eq!(compare_names(b"foo", true, b"foo0", false), Ordering::Less);
+        assert_eq!(compare_names(b"foo", false, b"foo.c", false), Ordering::Less);
+    }
+}
@@ -0,0 +1,190 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::object::{self, HashKind, ObjectId, ObjectKind};
+use super::RawObject;
+
+/// Objects stored one per file as `objects/xx/yyyy...`, zlib-compressed with
+/// their header.
+pub struct LooseStore {
+    dir: PathBuf,
+}
+
+impl LooseStore {
+    pub fn new(dir: PathBuf) -> LooseStore {
+        LooseStore { dir }
+    }
+
+    pub fn path_for(&self, id: &ObjectId) -> PathBuf {
+        let hex = id.to_hex();
+        self.dir.join(&hex[..2]).join(&hex[2..])
+    }
+
+    pub fn exists(&self, id: &ObjectId) -> bool {
+        self.path_for(id).is_file()
+    }
+
+    /// Ids of every loose object, found by listing the fan-out directories.
+    pub fn ids(&self) -> Result<Vec<ObjectId>, String> {
+        let mut ids = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ids),
+            Err(e) => return Err(e.to_string()),
+        };
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let prefix = entry.file_name().to_string_lossy().into_owned();
+            if prefix.len() != 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+            for file in fs::read_dir(entry.path()).map_err(|e| e.to_string())? {
+                let file = file.map_err(|e| e.to_string())?;
+                let rest = file.file_name().to_string_lossy().into_owned();
+                if let Ok(id) = ObjectId::from_hex(&format!("{}{}", prefix, rest)) {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Ids of the loose objects whose hex form starts with `prefix`, which
+    /// must be at least two characters so only one fan-out directory is read.
+    pub fn ids_with_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>, String> {
+        let entries = match fs::read_dir(self.dir.join(&prefix[..2])) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut ids = Vec::new();
+        for file in entries {
+            let rest = file.map_err(|e| e.to_string())?.file_name().to_string_lossy().into_owned();
+            if !rest.starts_with(&prefix[2..]) {
+                continue;
+            }
+            if let Ok(id) = ObjectId::from_hex(&format!("{}{}", &prefix[..2], rest)) {
+                ids.push(id);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Deletes the loose copy of `id`, and its fan-out directory if that
+    /// leaves it empty.
+    pub fn remove(&self, id: &ObjectId) -> Result<(), String> {
+        let path = self.path_for(id);
+        fs::remove_file(&path).map_err(|e| format!("Could not remove {}: {}", path.display(), e))?;
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    /// The type and size of `id`, inflating only as much as the header.
+    pub fn read_header(&self, id: &ObjectId) -> Result<Option<(ObjectKind, usize)>, String> {
+        let file = match fs::File::open(self.path_for(id)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        // Headers are `<type> <size>\0`, well under 32 bytes.
+        let mut header = Vec::new();
+        ZlibDecoder::new(file)
+            .take(32)
+            .read_to_end(&mut header)
+            .map_err(|e| format!("Corrupt loose object {}: {}", id, e))?;
+        let (kind, size, _) = object::parse_header(&header)
+            .map_err(|e| format!("Corrupt loose object {}: {}", id, e))?;
+        Ok(Some((kind, size)))
+    }
+
+    pub fn read(&self, id: &ObjectId) -> Result<Option<RawObject>, String> {
+        let compressed = match fs::read(self.path_for(id)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let mut decoder = ZlibDecoder::new(&compressed[..]);
+        let mut data = Vec::new();
+        decoder.read_to_end(&mut data)
+            .map_err(|e| format!("Corrupt loose object {}: {}", id, e))?;
+
+        let (kind, size, start) = object::parse_header(&data)
+            .map_err(|e| format!("Corrupt loose object {}: {}", id, e))?;
+        if data.len() - start != size {
+            return Err(format!(
+                "Corrupt loose object {}: header says {} bytes but it holds {}",
+                id, size, data.len() - start
+            ));
+        }
+        data.drain(..start);
+        Ok(Some(RawObject { kind, data }))
+    }
+
+    /// Writes an already-encoded object (header included) under `id`. The
+    /// file is written to a temporary name first so that readers never see a
+    /// partially written object.
+    pub fn write(&self, id: &ObjectId, encoded: &[u8]) -> Result<(), String> {
+        let path = self.path_for(id);
+        if path.is_file() {
+            return Ok(());
+        }
+        let dir = path.parent().ok_or("Invalid object path")?;
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+        let tmp_path = dir.join(format!("tmp_obj_{}", std::process::id()));
+        let file = fs::File::create(&tmp_path).map_err(|e| e.to_string())?;
+        let mut encoder = ZlibEncoder::new(file, Compression::default());
+        encoder.write_all(encoded).map_err(|e| e.to_string())?;
+        encoder.finish().map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, &path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Stores an object whose `size` bytes of content are streamed from
+    /// `reader`, hashing and compressing in a single pass. The id is only
+    /// known at the end, so the temporary file lives at the top of the
+    /// objects directory until it is renamed into place.
+    pub fn write_stream(
+        &self,
+        hash: HashKind,
+        kind: ObjectKind,
+        size: u64,
+        reader: &mut dyn Read,
+    ) -> Result<ObjectId, String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let tmp_path = self.dir.join(format!("tmp_obj_{}", std::process::id()));
+        let file = fs::File::create(&tmp_path).map_err(|e| e.to_string())?;
+        let mut encoder = ZlibEncoder::new(file, Compression::default());
+
+        let written = hash
+            .hash_stream(kind, size, reader, &mut encoder)
+            .and_then(|id| encoder.finish().map(|_| id).map_err(|e| e.to_string()));
+        let id = match written {
+            Ok(id) => id,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(e);
+            }
+        };
+
+        let path = self.path_for(&id);
+        if path.is_file() {
+            fs::remove_file(&tmp_path).map_err(|e| e.to_string())?;
+            return Ok(id);
+        }
+        let dir = path.parent().ok_or("Invalid object path")?;
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, &path).map_err(|e| e.to_string())?;
+        Ok(id)
+    }
+}
@@ -0,0 +1,247 @@
+pub mod loose;
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::object::{self, Commit, HashKind, Object, ObjectId, ObjectKind, Tag, Tree};
+use crate::pack::Pack;
+use loose::LooseStore;
+
+/// The shortest abbreviation accepted in place of a full id.
+pub const MIN_ABBREV: usize = 4;
+
+/// The length ids are abbreviated to when nothing longer is needed to keep
+/// them unique.
+pub const DEFAULT_ABBREV: usize = 7;
+
+/// A decompressed object with its header stripped.
+pub struct RawObject {
+    pub kind: ObjectKind,
+    pub data: Vec<u8>,
+}
+
+impl RawObject {
+    pub fn parse(&self, hash: HashKind) -> Result<Object, String> {
+        Object::parse(self.kind, &self.data, hash)
+    }
+}
+
+/// Entry point for every object read and write made by the commands.
+/// Lookups check loose objects first and then each pack in `objects/pack`.
+pub struct ObjectDatabase {
+    objects_dir: PathBuf,
+    hash: HashKind,
+    loose: LooseStore,
+    packs: Vec<Pack>,
+}
+
+impl ObjectDatabase {
+    pub fn new(objects_dir: PathBuf, hash: HashKind) -> Result<ObjectDatabase, String> {
+        let packs = Self::open_packs(&objects_dir, hash)?;
+        Ok(ObjectDatabase {
+            loose: LooseStore::new(objects_dir.clone()),
+            objects_dir,
+            hash,
+            packs,
+        })
+    }
+
+    fn open_packs(objects_dir: &Path, hash: HashKind) -> Result<Vec<Pack>, String> {
+        let pack_dir = objects_dir.join("pack");
+        let entries = match fs::read_dir(&pack_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let mut idx_paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().is_some_and(|ext| ext == "idx") && path.with_extension("pack").is_file() {
+                idx_paths.push(path);
+            }
+        }
+        idx_paths.sort();
+        idx_paths.iter().map(|path| Pack::open(path, hash)).collect()
+    }
+
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    /// The hash function this repository names objects with.
+    pub fn hash_kind(&self) -> HashKind {
+        self.hash
+    }
+
+    pub fn packs(&self) -> &[Pack] {
+        &self.packs
+    }
+
+    pub fn loose(&self) -> &LooseStore {
+        &self.loose
+    }
+
+    /// Whether `id` is stored in any pack, regardless of loose copies.
+    pub fn is_packed(&self, id: &ObjectId) -> bool {
+        self.packs.iter().any(|pack| pack.contains(id))
+    }
+
+    /// Computes the id `data` would have when stored as an object of `kind`.
+    pub fn hash(&self, kind: ObjectKind, data: &[u8]) -> ObjectId {
+        self.hash.hash_object(kind, data)
+    }
+
+    pub fn exists(&self, id: &ObjectId) -> bool {
+        self.loose.exists(id) || self.packs.iter().any(|pack| pack.contains(id))
+    }
+
+    /// Every stored object whose hex id starts with `prefix`, sorted and
+    /// without duplicates.
+    pub fn ids_with_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>, String> {
+        let mut ids = self.loose.ids_with_prefix(prefix)?;
+        for pack in &self.packs {
+            ids.extend(pack.index().ids_with_prefix(prefix));
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Resolves a full or abbreviated hex id. A full id is returned as is,
+    /// whether or not the object exists; a shorter one must be at least
+    /// `MIN_ABBREV` characters and match exactly one stored object. `None`
+    /// means `name` is not hex or nothing matches it.
+    pub fn resolve_prefix(&self, name: &str) -> Result<Option<ObjectId>, String> {
+        if name.len() < MIN_ABBREV
+            || name.len() > self.hash.hex_len()
+            || !name.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Ok(None);
+        }
+        let prefix = name.to_ascii_lowercase();
+        if prefix.len() == self.hash.hex_len() {
+            return ObjectId::from_hex(&prefix).map(Some);
+        }
+
+        let mut candidates = self.ids_with_prefix(&prefix)?;
+        match candidates.len() {
+            0 => Ok(None),
+            1 => Ok(candidates.pop()),
+            _ => {
+                let mut message = format!("short object ID {} is ambiguous\nThe candidates are:", name);
+                for id in &candidates {
+                    let kind = self.read(id).map(|object| object.kind.as_str()).unwrap_or("bad object");
+                    message.push_str(&format!("\n  {} {}", self.abbreviate(id, DEFAULT_ABBREV)?, kind));
+                }
+                Err(message)
+            }
+        }
+    }
+
+    /// Like `resolve_prefix`, but a name that matches nothing is an error.
+    pub fn resolve_hex(&self, name: &str) -> Result<ObjectId, String> {
+        self.resolve_prefix(name)?
+            .ok_or_else(|| format!("Not a valid object name {}", name))
+    }
+
+    /// The shortest prefix of `id`, at least `min_len` characters long, that
+    /// no other stored object shares.
+    pub fn abbreviate(&self, id: &ObjectId, min_len: usize) -> Result<String, String> {
+        let hex = id.to_hex();
+        let min_len = min_len.clamp(MIN_ABBREV, hex.len());
+        let mut len = min_len;
+        for other in self.ids_with_prefix(&hex[..min_len])? {
+            if &other == id {
+                continue;
+            }
+            let common = other
+                .to_hex()
+                .bytes()
+                .zip(hex.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            len = len.max(common + 1);
+        }
+        Ok(hex[..len.min(hex.len())].to_string())
+    }
+
+    pub fn read(&self, id: &ObjectId) -> Result<RawObject, String> {
+        if let Some(object) = self.loose.read(id)? {
+            return Ok(object);
+        }
+        for pack in &self.packs {
+            if let Some(object) = pack.read(id)? {
+                return Ok(object);
+            }
+        }
+        Err(format!("Object {} not found", id))
+    }
+
+    /// The type and size of `id` without reading its whole content.
+    pub fn read_header(&self, id: &ObjectId) -> Result<(ObjectKind, usize), String> {
+        if let Some(header) = self.loose.read_header(id)? {
+            return Ok(header);
+        }
+        for pack in &self.packs {
+            if let Some(offset) = pack.index().lookup(id) {
+                return pack.read_header_at(offset);
+            }
+        }
+        Err(format!("Object {} not found", id))
+    }
+
+    /// Every stored object, loose or packed, sorted and without duplicates.
+    pub fn ids(&self) -> Result<Vec<ObjectId>, String> {
+        let mut ids = self.loose.ids()?;
+        for pack in &self.packs {
+            let index = pack.index();
+            ids.extend((0..index.len()).map(|i| index.id_at(i)));
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    pub fn read_tree(&self, id: &ObjectId) -> Result<Tree, String> {
+        let raw = self.read_expecting(id, ObjectKind::Tree)?;
+        Tree::parse(&raw.data, self.hash)
+    }
+
+    pub fn read_commit(&self, id: &ObjectId) -> Result<Commit, String> {
+        let raw = self.read_expecting(id, ObjectKind::Commit)?;
+        Commit::parse(&raw.data)
+    }
+
+    pub fn read_tag(&self, id: &ObjectId) -> Result<Tag, String> {
+        let raw = self.read_expecting(id, ObjectKind::Tag)?;
+        Tag::parse(&raw.data)
+    }
+
+    fn read_expecting(&self, id: &ObjectId, expected: ObjectKind) -> Result<RawObject, String> {
+        let raw = self.read(id)?;
+        if raw.kind != expected {
+            return Err(format!("Object {} is a {}, not a {}", id, raw.kind, expected));
+        }
+        Ok(raw)
+    }
+
+    /// Like `write`, for content too large to hold in memory: `size` bytes
+    /// are streamed from `reader`.
+    pub fn write_stream(&self, kind: ObjectKind, size: u64, reader: &mut dyn Read) -> Result<ObjectId, String> {
+        self.loose.write_stream(self.hash, kind, size, reader)
+    }
+
+    pub fn write_object(&self, object: &Object) -> Result<ObjectId, String> {
+        self.write(object.kind(), &object.serialize())
+    }
+
+    pub fn write(&self, kind: ObjectKind, data: &[u8]) -> Result<ObjectId, String> {
+        let id = self.hash(kind, data);
+        let mut encoded = object::encode_header(kind, data.len());
+        encoded.extend_from_slice(data);
+        self.loose.write(&id, &encoded)?;
+        Ok(id)
+    }
+}
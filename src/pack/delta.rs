@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::object::{ObjectId, ObjectKind};
+use crate::odb::RawObject;
+use super::{inflate, parse_entry_header, EntryKind};
+
+/// Longest delta chain we are willing to follow before assuming the pack
+/// is corrupt (or loops back on itself).
+pub const MAX_CHAIN_DEPTH: usize = 10_000;
+
+/// Default memory budget for cached delta bases.
+pub const DEFAULT_CACHE_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Reads a delta header size: little-endian base-128.
+fn read_size(delta: &[u8], pos: &mut usize) -> Result<usize, String> {
+    let mut size = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*pos).ok_or("Delta header is truncated")?;
+        *pos += 1;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// The size of the object `delta` produces, read from its header without
+/// applying it. Only the first few bytes of the delta are needed.
+pub fn delta_result_size(delta: &[u8]) -> Result<usize, String> {
+    let mut pos = 0;
+    read_size(delta, &mut pos)?;
+    read_size(delta, &mut pos)
+}
+
+/// Rebuilds an object from `base` and a git delta: two sizes followed by
+/// copy instructions (high bit set, copying a range of the base) and insert
+/// instructions (1-127 literal bytes).
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos)?;
+    let result_size = read_size(delta, &mut pos)?;
+    if base_size != base.len() {
+        return Err(format!("Delta expects a {} byte base, got {}", base_size, base.len()));
+    }
+
+    let mut out = Vec::with_capacity(result_size);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+
+        if op & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    let byte = *delta.get(pos).ok_or("Delta copy instruction is truncated")?;
+                    pos += 1;
+                    offset |= (byte as usize) << (8 * i);
+                }
+            }
+            for i in 0..3 {
+                if op & (1 << (4 + i)) != 0 {
+                    let byte = *delta.get(pos).ok_or("Delta copy instruction is truncated")?;
+                    pos += 1;
+                    size |= (byte as usize) << (8 * i);
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let end = offset.checked_add(size).filter(|&end| end <= base.len())
+                .ok_or("Delta copies past the end of its base")?;
+            out.extend_from_slice(&base[offset..end]);
+        } else if op != 0 {
+            let end = pos + op as usize;
+            if end > delta.len() {
+                return Err("Delta insert instruction is truncated".to_string());
+            }
+            out.extend_from_slice(&delta[pos..end]);
+            pos = end;
+        } else {
+            return Err("Delta contains the reserved opcode 0".to_string());
+        }
+    }
+
+    if out.len() != result_size {
+        return Err(format!("Delta produced {} bytes, expected {}", out.len(), result_size));
+    }
+    Ok(out)
+}
+
+/// A size-bounded, least-recently-used cache of resolved objects keyed by
+/// their pack offset, so walking many deltas that share a base does not
+/// re-inflate the whole chain each time.
+pub struct DeltaBaseCache {
+    entries: HashMap<u64, CacheEntry>,
+    limit: usize,
+    used: usize,
+    tick: u64,
+}
+
+struct CacheEntry {
+    kind: ObjectKind,
+    data: Rc<[u8]>,
+    last_used: u64,
+}
+
+impl DeltaBaseCache {
+    pub fn new(limit: usize) -> DeltaBaseCache {
+        DeltaBaseCache { entries: HashMap::new(), limit, used: 0, tick: 0 }
+    }
+
+    pub fn get(&mut self, offset: u64) -> Option<(ObjectKind, Rc<[u8]>)> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(&offset)?;
+        entry.last_used = self.tick;
+        Some((entry.kind, entry.data.clone()))
+    }
+
+    pub fn insert(&mut self, offset: u64, kind: ObjectKind, data: Rc<[u8]>) {
+        if data.len() > self.limit || self.entries.contains_key(&offset) {
+            return;
+        }
+        self.tick += 1;
+        self.used += data.len();
+        self.entries.insert(offset, CacheEntry { kind, data, last_used: self.tick });
+
+        while self.used > self.limit {
+            let oldest = self.entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(&offset, _)| offset);
+            match oldest.and_then(|offset| self.entries.remove(&offset)) {
+                Some(evicted) => self.used -= evicted.data.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Where the base of a REF_DELTA entry lives.
+pub enum RefBase {
+    /// Elsewhere in the same pack.
+    Offset(u64),
+    /// Outside the pack (thin packs), already resolved.
+    Object(RawObject),
+}
+
+/// Resolves the entry at `offset` in `pack` to a full object, following
+/// OFS_DELTA and REF_DELTA bases until a whole object (or a cached one) is
+/// reached and then applying the deltas back up the chain.
+pub fn resolve_entry(
+    pack: &[u8],
+    offset: u64,
+    hash_len: usize,
+    cache: &mut DeltaBaseCache,
+    find_ref_base: &dyn Fn(&ObjectId) -> Result<RefBase, String>,
+) -> Result<RawObject, String> {
+    let mut chain = Vec::new();
+    let mut current = offset;
+    // A thin pack's external base has no offset of its own; `current` is
+    // then still the delta's offset and must not key the base in the cache.
+    let mut external = false;
+
+    let (kind, mut data): (ObjectKind, Rc<[u8]>) = loop {
+        if let Some(cached) = cache.get(current) {
+            break cached;
+        }
+        if chain.len() > MAX_CHAIN_DEPTH {
+            return Err(format!("Delta chain at {} is too deep", offset));
+        }
+
+        let header = parse_entry_header(pack, current, hash_len)?;
+        match header.kind {
+            EntryKind::Base(kind) => {
+                break (kind, inflate(&pack[header.data_offset..], header.size)?.into());
+            }
+            EntryKind::OfsDelta(base_offset) => {
+                chain.push((current, header));
+                current = base_offset;
+            }
+            EntryKind::RefDelta(ref base_id) => {
+                let base = find_ref_base(base_id)?;
+                chain.push((current, header));
+                match base {
+                    RefBase::Offset(base_offset) => current = base_offset,
+                    RefBase::Object(object) => {
+                        external = true;
+                        break (object.kind, object.data.into());
+                    }
+                }
+            }
+        }
+    };
+
+    if chain.is_empty() {
+        return Ok(RawObject { kind, data: data.to_vec() });
+    }
+
+    if !external {
+        cache.insert(current, kind, data.clone());
+    }
+    for (i, (entry_offset, header)) in chain.iter().rev().enumerate() {
+        let delta = inflate(&pack[header.data_offset..], header.size)?;
+        data = apply_delta(&data, &delta)
+            .map_err(|e| format!("Pack entry at {}: {}", entry_offset, e))?
+            .into();
+        if i + 1 < chain.len() {
+            cache.insert(*entry_offset, kind, data.clone());
+        }
+    }
+
+    Ok(RawObject { kind, data: data.to_vec() })
+}
+
+/// Block size used to index a delta base. Matches shorter than this are not
+/// found, which keeps the index small and the copies worthwhile.
+const BLOCK: usize = 16;
+/// Most base offsets remembered per block hash; long runs of identical
+/// blocks would otherwise make lookups quadratic.
+const MAX_BUCKET: usize = 64;
+const MAX_COPY: usize = 0xff_ffff;
+const MAX_INSERT: usize = 0x7f;
+
+/// Positions of every aligned block of a base, for finding copy sources.
+pub struct DeltaIndex<'a> {
+    base: &'a [u8],
+    blocks: HashMap<u64, Vec<usize>>,
+}
+
+fn block_hash(block: &[u8]) -> u64 {
+    // FNV-1a
+    block.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl<'a> DeltaIndex<'a> {
+    pub fn new(base: &'a [u8]) -> DeltaIndex<'a> {
+        let mut blocks: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut offset = 0;
+        while offset + BLOCK <= base.len() {
+            let bucket = blocks.entry(block_hash(&base[offset..offset + BLOCK])).or_default();
+            if bucket.len() < MAX_BUCKET {
+                bucket.push(offset);
+            }
+            offset += BLOCK;
+        }
+        DeltaIndex { base, blocks }
+    }
+
+    pub fn base(&self) -> &'a [u8] {
+        self.base
+    }
+
+    /// The longest match for `target[pos..]` among indexed blocks.
+    fn best_match(&self, target: &[u8], pos: usize) -> Option<(usize, usize)> {
+        let candidates = self.blocks.get(&block_hash(&target[pos..pos + BLOCK]))?;
+        let mut best: Option<(usize, usize)> = None;
+        for &start in candidates {
+            let len = self.base[start..]
+                .iter()
+                .zip(&target[pos..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= BLOCK && best.map_or(true, |(_, best_len)| len > best_len) {
+                best = Some((start, len));
+            }
+        }
+        best
+    }
+}
+
+fn write_size(out: &mut Vec<u8>, mut size: usize) {
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn flush_insert(out: &mut Vec<u8>, pending: &[u8]) {
+    for chunk in pending.chunks(MAX_INSERT) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn write_copy(out: &mut Vec<u8>, offset: usize, size: usize) {
+    let mut op = 0x80u8;
+    let mut args = Vec::with_capacity(7);
+    for i in 0..4 {
+        let byte = (offset >> (8 * i)) as u8;
+        if byte != 0 {
+            op |= 1 << i;
+            args.push(byte);
+        }
+    }
+    for i in 0..3 {
+        let byte = (size >> (8 * i)) as u8;
+        if byte != 0 {
+            op |= 1 << (4 + i);
+            args.push(byte);
+        }
+    }
+    out.push(op);
+    out.extend_from_slice(&args);
+}
+
+/// Encodes `target` as a delta against the indexed base, giving up (and
+/// returning `None`) as soon as the delta grows past `max_size` bytes.
+pub fn create_delta(index: &DeltaIndex, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let base = index.base();
+    if base.len() > u32::MAX as usize {
+        return None;
+    }
+
+    let mut out = Vec::new();
+    write_size(&mut out, base.len());
+    write_size(&mut out, target.len());
+
+    let mut pending_start = 0;
+    let mut pos = 0;
+    while pos + BLOCK <= target.len() {
+        let (mut start, mut len) = match index.best_match(target, pos) {
+            Some(found) => found,
+            None => {
+                pos += 1;
+                continue;
+            }
+        };
+
+        // Grow the match backwards into bytes we were about to insert.
+        while pos > pending_start && start > 0 && base[start - 1] == target[pos - 1] {
+            start -= 1;
+            pos -= 1;
+            len += 1;
+        }
+
+        flush_insert(&mut out, &target[pending_start..pos]);
+        let mut copied = 0;
+        while copied < len {
+            let chunk = (len - copied).min(MAX_COPY);
+            write_copy(&mut out, start + copied, chunk);
+            copied += chunk;
+        }
+        pos += len;
+        pending_start = pos;
+
+        if out.len() > max_size {
+            return None;
+        }
+    }
+    flush_insert(&mut out, &target[pending_start..]);
+
+    (out.len() <= max_size).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::HashKind;
+    use crate::pack::{deflate, encode_entry_header, OBJ_OFS_DELTA, OBJ_REF_DELTA};
+
+    fn text(seed: u32, lines: usize) -> Vec<u8> {
+        (0..lines).flat_map(|i| format!("line {} of blob {}\n", i, seed).into_bytes()).collect()
+    }
+
+    fn delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+        create_delta(&DeltaIndex::new(base), target, usize::MAX).unwrap()
+    }
+
+    fn pack_header(out: &mut Vec<u8>) {
+        out.extend_from_slice(b"PACK");
+        out.extend_from_slice(&2u32.to_be_bytes());
+        out.extend_from_slice(&0u32.to_be_bytes());
+    }
+
+    fn push_ref_delta(out: &mut Vec<u8>, base: &ObjectId, delta: &[u8]) -> u64 {
+        let offset = out.len() as u64;
+        out.extend_from_slice(&encode_entry_header(OBJ_REF_DELTA, delta.len()));
+        out.extend_from_slice(base.as_bytes());
+        out.extend_from_slice(&deflate(delta));
+        offset
+    }
+
+    fn push_ofs_delta(out: &mut Vec<u8>, base_offset: u64, delta: &[u8]) -> u64 {
+        let offset = out.len() as u64;
+        let distance = offset - base_offset;
+        assert!(distance < 0x80, "test entries stay within a one-byte distance");
+        out.extend_from_slice(&encode_entry_header(OBJ_OFS_DELTA, delta.len()));
+        out.push(distance as u8);
+        out.extend_from_slice(&deflate(delta));
+        offset
+    }
+
+    #[test]
+    fn external_base_is_not_cached_under_the_delta_offset() {
+        let base = text(1, 40);
+        let mut middle = base.clone();
+        middle.extend_from_slice(b"appended to the middle\n");
+        let mut top = middle.clone();
+        top.splice(0..0, b"prepended to the top\n".iter().copied());
+
+        let base_id = HashKind::Sha1.hash_object(ObjectKind::Blob, &base);
+        let mut pack = Vec::new();
+        pack_header(&mut pack);
+        let middle_offset = push_ref_delta(&mut pack, &base_id, &delta(&base, &middle));
+        let top_offset = push_ofs_delta(&mut pack, middle_offset, &delta(&middle, &top));
+
+        let find_ref_base = |id: &ObjectId| {
+            assert_eq!(*id, base_id);
+            Ok(RefBase::Object(RawObject { kind: ObjectKind::Blob, data: base.clone() }))
+        };
+        let mut cache = DeltaBaseCache::new(DEFAULT_CACHE_LIMIT);
+        let resolved = resolve_entry(&pack, middle_offset, 20, &mut cache, &find_ref_base).unwrap();
+        assert_eq!(resolved.data, middle);
+        let resolved = resolve_entry(&pack, top_offset, 20, &mut cache, &find_ref_base).unwrap();
+        assert_eq!(resolved.data, top);
+    }
+
+    #[test]
+    fn encoded_deltas_apply_back_to_the_target() {
+        let base = text(2, 200);
+        let mut edited = base.clone();
+        edited.splice(100..120, b"an edit in the middle".iter().copied());
+        edited.extend_from_slice(&text(3, 5));
+        let mut shuffled = base[base.len() / 2..].to_vec();
+        shuffled.extend_from_slice(&base[..base.len() / 2]);
+
+        for target in [base.clone(), edited, shuffled, Vec::new(), b"short".to_vec(), vec![0xff; 300]] {
+            let encoded = delta(&base, &target);
+            assert_eq!(delta_result_size(&encoded).unwrap(), target.len());
+            assert_eq!(apply_delta(&base, &encoded).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn copies_keep_a_similar_target_small() {
+        let base = text(4, 2000);
+        let mut target = base.clone();
+        target.extend_from_slice(b"tail\n");
+        let encoded = delta(&base, &target);
+        assert!(encoded.len() < 64, "delta is {} bytes", encoded.len());
+        assert!(create_delta(&DeltaIndex::new(&base), &text(5, 2000), 64).is_none());
+    }
+
+    #[test]
+    fn rejects_deltas_for_a_different_base() {
+        let base = text(6, 20);
+        let encoded = delta(&base, &text(7, 20));
+        assert!(apply_delta(&base[1..], &encoded).is_err());
+        assert!(apply_delta(&base, &encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn resolves_mixed_ofs_and_ref_chains() {
+        let base = text(8, 30);
+        let mut first = base.clone();
+        first.extend_from_slice(b"first\n");
+        let mut second = first.clone();
+        second.insert(0, b'#');
+        let mut third = second.clone();
+        third.truncate(third.len() / 2);
+        let first_id = HashKind::Sha1.hash_object(ObjectKind::Blob, &first);
+
+        // base <- first (OFS) <- second (REF, by id) <- third (OFS)
+        let mut pack = Vec::new();
+        pack_header(&mut pack);
+        let base_offset = pack.len() as u64;
+        pack.extend_from_slice(&encode_entry_header(crate::pack::OBJ_BLOB, base.len()));
+        pack.extend_from_slice(&deflate(&base));
+        let first_offset = push_ofs_delta(&mut pack, base_offset, &delta(&base, &first));
+        let second_offset = push_ref_delta(&mut pack, &first_id, &delta(&first, &second));
+        let third_offset = push_ofs_delta(&mut pack, second_offset, &delta(&second, &third));
+
+        let find_ref_base = |id: &ObjectId| match *id == first_id {
+            true => Ok(RefBase::Offset(first_offset)),
+            false => Err(format!("missing delta base {}", id)),
+        };
+        // Resolve newest first with a cold cache, then again with the cache
+        // holding the intermediate results.
+        let mut cache = DeltaBaseCache::new(DEFAULT_CACHE_LIMIT);
+        for _ in 0..2 {
+            for (offset, expected) in [(third_offset, &third), (second_offset, &second), (first_offset, &first), (base_offset, &base)] {
+                let resolved = resolve_entry(&pack, offset, 20, &mut cache, &find_ref_base).unwrap();
+                assert_eq!(resolved.kind, ObjectKind::Blob);
+                assert_eq!(&resolved.data, expected);
+            }
+        }
+
+        let unknown = HashKind::Sha1.hash_object(ObjectKind::Blob, b"unknown");
+        let missing_offset = push_ref_delta(&mut pack, &unknown, &delta(&first, &second));
+        match resolve_entry(&pack, missing_offset, 20, &mut cache, &find_ref_base) {
+            Err(error) => assert!(error.contains("missing delta base"), "{}", error),
+            Ok(_) => panic!("resolved a delta whose base is missing"),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+
+use crate::object::{HashKind, ObjectId, ObjectKind};
+use super::delta::{create_delta, DeltaIndex};
+use super::indexer::IndexedPack;
+use super::writer::PackWriter;
+
+/// An object queued for packing, with the hash of the path it was found at
+/// so that versions of the same file end up next to each other.
+pub struct PackObject {
+    pub id: ObjectId,
+    pub kind: ObjectKind,
+    pub name_hash: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DeltaOptions {
+    /// How many preceding objects each object is tried against.
+    pub window: usize,
+    /// Longest delta chain allowed.
+    pub depth: usize,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        DeltaOptions { window: 10, depth: 50 }
+    }
+}
+
+/// Objects smaller than this are never worth deltifying.
+const MIN_DELTA_SIZE: usize = 32;
+
+/// git's path hash: the last sixteen non-space characters of the name,
+/// weighted towards the end so files with the same suffix sort together.
+pub fn name_hash(name: &[u8]) -> u32 {
+    name.iter()
+        .filter(|b| !b.is_ascii_whitespace())
+        .fold(0u32, |hash, &b| (hash >> 2).wrapping_add((b as u32) << 24))
+}
+
+fn kind_order(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Commit => 0,
+        ObjectKind::Tree => 1,
+        ObjectKind::Blob => 2,
+        ObjectKind::Tag => 3,
+    }
+}
+
+/// Picks a delta base for each object. Candidates are sorted by type, name
+/// hash and descending size, and each object is compared with the `window`
+/// objects before it; the smallest delta that beats half the object's size
+/// (shrinking as the base's chain gets deeper) wins.
+pub fn find_deltas(objects: &[PackObject], options: DeltaOptions) -> Vec<Option<(usize, Vec<u8>)>> {
+    let mut result: Vec<Option<(usize, Vec<u8>)>> = (0..objects.len()).map(|_| None).collect();
+    if options.window == 0 || options.depth == 0 {
+        return result;
+    }
+
+    let mut order: Vec<usize> = (0..objects.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&objects[a], &objects[b]);
+        kind_order(a.kind)
+            .cmp(&kind_order(b.kind))
+            .then(a.name_hash.cmp(&b.name_hash))
+            .then(b.data.len().cmp(&a.data.len()))
+    });
+
+    let mut depth = vec![0usize; objects.len()];
+    let mut window: VecDeque<(usize, DeltaIndex)> = VecDeque::with_capacity(options.window);
+
+    for &i in &order {
+        let target = &objects[i];
+        if target.data.len() >= MIN_DELTA_SIZE {
+            let mut best: Option<(usize, Vec<u8>)> = None;
+            for (j, index) in window.iter() {
+                let base = &objects[*j];
+                if base.kind != target.kind || depth[*j] >= options.depth {
+                    continue;
+                }
+                // A base much smaller than the target can't save much.
+                if base.data.len() < target.data.len() / 32 {
+                    continue;
+                }
+                let budget = (target.data.len() / 2).saturating_sub(20)
+                    * (options.depth - depth[*j])
+                    / options.depth;
+                let max_size = match &best {
+                    Some((_, delta)) => budget.min(delta.len().saturating_sub(1)),
+                    None => budget,
+                };
+                if let Some(delta) = create_delta(index, &target.data, max_size) {
+                    best = Some((*j, delta));
+                }
+            }
+            if let Some((base, delta)) = best {
+                depth[i] = depth[base] + 1;
+                result[i] = Some((base, delta));
+            }
+        }
+
+        if window.len() == options.window {
+            window.pop_front();
+        }
+        window.push_back((i, DeltaIndex::new(&target.data)));
+    }
+    result
+}
+
+/// Writes `objects` as a pack, storing each as an OFS_DELTA when a delta
+/// was found for it. Bases are always written before the deltas that use
+/// them.
+pub fn write_pack(
+    objects: &[PackObject],
+    options: DeltaOptions,
+    hash: HashKind,
+) -> (Vec<u8>, IndexedPack, usize) {
+    let deltas = find_deltas(objects, options);
+    let mut offsets: Vec<Option<u64>> = vec![None; objects.len()];
+    let mut writer = PackWriter::new(hash);
+
+    for i in 0..objects.len() {
+        // Walk down to the deepest unwritten base and write back up.
+        let mut chain = vec![i];
+        while let Some((base, _)) = &deltas[*chain.last().unwrap()] {
+            if offsets[*base].is_some() {
+                break;
+            }
+            chain.push(*base);
+        }
+        for &k in chain.iter().rev() {
+            if offsets[k].is_some() {
+                continue;
+            }
+            let object = &objects[k];
+            let offset = match &deltas[k] {
+                Some((base, delta)) => {
+                    let base_offset = offsets[*base].expect("delta base is written first");
+                    writer.add_ofs_delta(object.id.clone(), base_offset, delta)
+                }
+                None => writer.add_object(object.id.clone(), object.kind, &object.data),
+            };
+            offsets[k] = Some(offset);
+        }
+    }
+
+    let delta_count = deltas.iter().filter(|delta| delta.is_some()).count();
+    let (pack, indexed) = writer.finish();
+    (pack, indexed, delta_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::delta::apply_delta;
+    use crate::pack::index::IndexEntry;
+    use crate::pack::indexer;
+
+    fn object(kind: ObjectKind, name: &[u8], data: Vec<u8>) -> PackObject {
+        PackObject { id: HashKind::Sha1.hash_object(kind, &data), kind, name_hash: name_hash(name), data }
+    }
+
+    /// Successive versions of one file, each a line longer than the last.
+    fn versions(count: usize) -> Vec<PackObject> {
+        let mut data: Vec<u8> = (0..200).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+        (0..count)
+            .map(|i| {
+                data.extend_from_slice(format!("added in version {}\n", i).as_bytes());
+                object(ObjectKind::Blob, b"src/file.txt", data.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn similar_objects_are_deltified() {
+        let mut objects = versions(4);
+        objects.push(object(ObjectKind::Blob, b"tiny", b"small".to_vec()));
+        let deltas = find_deltas(&objects, DeltaOptions::default());
+        assert_eq!(deltas.iter().filter(|delta| delta.is_some()).count(), 3);
+        // Tiny objects are never worth it.
+        assert!(deltas[4].is_none());
+        for (i, delta) in deltas.iter().enumerate() {
+            if let Some((base, delta)) = delta {
+                let applied = apply_delta(&objects[*base].data, delta).unwrap();
+                assert_eq!(applied, objects[i].data);
+            }
+        }
+    }
+
+    #[test]
+    fn deltas_stay_within_one_kind() {
+        let blob = versions(1).remove(0);
+        let mut tag_data = blob.data.clone();
+        tag_data.extend_from_slice(b"!\n");
+        let objects = vec![blob, object(ObjectKind::Tag, b"", tag_data)];
+        assert!(find_deltas(&objects, DeltaOptions::default()).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn chains_are_limited_to_the_depth() {
+        let objects = versions(8);
+        let deltas = find_deltas(&objects, DeltaOptions { window: 1, depth: 2 });
+        let depth_of = |mut i: usize| {
+            let mut depth = 0;
+            while let Some((base, _)) = &deltas[i] {
+                i = *base;
+                depth += 1;
+            }
+            depth
+        };
+        assert!((0..objects.len()).all(|i| depth_of(i) <= 2));
+        assert!(deltas.iter().any(Option::is_some));
+
+        let none = find_deltas(&objects, DeltaOptions { window: 0, depth: 50 });
+        assert!(none.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn written_pack_indexes_to_the_same_objects() {
+        let objects = versions(5);
+        let (pack, written, delta_count) = write_pack(&objects, DeltaOptions::default(), HashKind::Sha1);
+        assert_eq!(delta_count, 4);
+
+        let indexed = indexer::index_pack(&pack, HashKind::Sha1, &|_| None).unwrap();
+        assert_eq!(indexed.checksum, written.checksum);
+        let ids = |entries: &[IndexEntry]| {
+            let mut ids: Vec<_> = entries.iter().map(|entry| (entry.id.clone(), entry.offset, entry.crc32)).collect();
+            ids.sort_by_key(|(_, offset, _)| *offset);
+            ids
+        };
+        assert_eq!(ids(&indexed.entries), ids(&written.entries));
+        let mut expected: Vec<_> = objects.iter().map(|object| object.id.clone()).collect();
+        let mut found: Vec<_> = indexed.entries.iter().map(|entry| entry.id.clone()).collect();
+        expected.sort();
+        found.sort();
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,314 @@
+use std::fs;
+use std::path::Path;
+
+use crate::object::{HashKind, ObjectId};
+
+const IDX_MAGIC: &[u8; 4] = b"\xfftOc";
+const FANOUT_LEN: usize = 256 * 4;
+
+/// A version 2 pack index: a fan-out table over the first byte of each id,
+/// followed by the sorted ids, their CRC32s, 32-bit offsets and, for packs
+/// over 2 GiB, a table of 64-bit offsets. The file does not record its
+/// hash function, so the reader must know which one the repository uses.
+pub struct PackIndex {
+    data: Vec<u8>,
+    count: usize,
+    hash: HashKind,
+    hash_len: usize,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path, hash: HashKind) -> Result<PackIndex, String> {
+        let data = fs::read(path)
+            .map_err(|e| format!("Could not read pack index {}: {}", path.display(), e))?;
+        PackIndex::parse(data, hash)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(data: Vec<u8>, hash: HashKind) -> Result<PackIndex, String> {
+        let hash_len = hash.id_len();
+        if data.len() < 8 + FANOUT_LEN || &data[..4] != IDX_MAGIC {
+            return Err("Unsupported pack index: only version 2 indexes can be read".to_string());
+        }
+        let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if version != 2 {
+            return Err(format!("Unsupported pack index version {}", version));
+        }
+
+        let count = u32::from_be_bytes(data[8 + FANOUT_LEN - 4..8 + FANOUT_LEN].try_into().unwrap()) as usize;
+        let index = PackIndex { data, count, hash, hash_len };
+        // Lookups trust the fan-out to bound their binary search, so it has
+        // to be sorted and end at the object count.
+        if (1..256).any(|byte| index.fanout(byte) < index.fanout(byte - 1)) {
+            return Err("Pack index has a non-monotonic fan-out table".to_string());
+        }
+        let minimum = index.offsets_start() + count * 4 + 2 * hash_len;
+        if index.data.len() < minimum {
+            return Err("Pack index is truncated".to_string());
+        }
+        // Whatever follows the 32-bit offsets is 64-bit offsets, of which
+        // there can be no more than there are objects.
+        let large = index.data.len() - minimum;
+        if large % 8 != 0 || large / 8 > count {
+            return Err(format!("Pack index size does not match its object count of {}", count));
+        }
+        // An offset with its top bit set is a slot in that table, which
+        // offset_at reads without checking.
+        for i in 0..count {
+            let offset = index.small_offset_at(i);
+            if offset & 0x8000_0000 != 0 && (offset & 0x7fff_ffff) as usize >= large / 8 {
+                return Err(format!("Pack index has a bad 64-bit offset slot for object {}", i));
+            }
+        }
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn hash_kind(&self) -> HashKind {
+        self.hash
+    }
+
+    pub fn hash_len(&self) -> usize {
+        self.hash_len
+    }
+
+    /// Finds the pack offset of `id`, narrowing the search with the fan-out
+    /// table before binary searching the sorted id list.
+    pub fn lookup(&self, id: &ObjectId) -> Option<u64> {
+        self.position(id).map(|i| self.offset_at(i))
+    }
+
+    pub fn position(&self, id: &ObjectId) -> Option<usize> {
+        let bytes = id.as_bytes();
+        if bytes.len() != self.hash_len {
+            return None;
+        }
+        let first = bytes[0] as usize;
+        let mut lo = if first == 0 { 0 } else { self.fanout(first - 1) };
+        let mut hi = self.fanout(first);
+
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.id_bytes_at(mid).cmp(bytes) {
+                std::cmp::Ordering::Equal => return Some(mid),
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        None
+    }
+
+    /// Every id whose hex form starts with `prefix`, in sorted order. The ids
+    /// sharing a prefix are contiguous, so this is a binary search for the
+    /// first candidate followed by a scan.
+    pub fn ids_with_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        let mut padded = prefix.to_string();
+        if padded.len() % 2 == 1 {
+            padded.push('0');
+        }
+        let Ok(lower) = hex::decode(&padded) else {
+            return Vec::new();
+        };
+
+        let mut lo = 0;
+        let mut hi = self.count;
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.id_bytes_at(mid) < lower.as_slice() {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        (lo..self.count)
+            .map(|i| self.id_at(i))
+            .take_while(|id| id.to_hex().starts_with(prefix))
+            .collect()
+    }
+
+    pub fn id_at(&self, i: usize) -> ObjectId {
+        ObjectId::from_bytes(self.id_bytes_at(i))
+    }
+
+    pub fn crc_at(&self, i: usize) -> u32 {
+        let start = self.crcs_start() + i * 4;
+        u32::from_be_bytes(self.data[start..start + 4].try_into().unwrap())
+    }
+
+    pub fn offset_at(&self, i: usize) -> u64 {
+        let offset = self.small_offset_at(i);
+        if offset & 0x8000_0000 == 0 {
+            return offset as u64;
+        }
+        let large = self.large_offsets_start() + (offset & 0x7fff_ffff) as usize * 8;
+        u64::from_be_bytes(self.data[large..large + 8].try_into().unwrap())
+    }
+
+    /// The checksum of the pack this index describes.
+    pub fn pack_checksum(&self) -> &[u8] {
+        let end = self.data.len() - self.hash_len;
+        &self.data[end - self.hash_len..end]
+    }
+
+    /// Whether the trailing checksum matches the rest of the index.
+    pub fn verify_checksum(&self) -> bool {
+        let (body, trailer) = self.data.split_at(self.data.len() - self.hash_len);
+        self.hash.digest(body) == trailer
+    }
+
+    fn fanout(&self, byte: usize) -> usize {
+        let start = 8 + byte * 4;
+        u32::from_be_bytes(self.data[start..start + 4].try_into().unwrap()) as usize
+    }
+
+    /// The 32-bit offset of the object at `i`, or its slot in the 64-bit
+    /// table when the top bit is set.
+    fn small_offset_at(&self, i: usize) -> u32 {
+        let start = self.offsets_start() + i * 4;
+        u32::from_be_bytes(self.data[start..start + 4].try_into().unwrap())
+    }
+
+    fn id_bytes_at(&self, i: usize) -> &[u8] {
+        let start = 8 + FANOUT_LEN + i * self.hash_len;
+        &self.data[start..start + self.hash_len]
+    }
+
+    fn crcs_start(&self) -> usize {
+        8 + FANOUT_LEN + self.count * self.hash_len
+    }
+
+    fn offsets_start(&self) -> usize {
+        self.crcs_start() + self.count * 4
+    }
+
+    fn large_offsets_start(&self) -> usize {
+        self.offsets_start() + self.count * 4
+    }
+}
+
+/// One object's row in a pack index.
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub id: ObjectId,
+    pub offset: u64,
+    pub crc32: u32,
+}
+
+/// Serialises a version 2 index for `entries`, which need not be sorted.
+/// Offsets that do not fit in 31 bits go to the 64-bit offset table.
+pub fn write_index(entries: &[IndexEntry], pack_checksum: &[u8], hash: HashKind) -> Vec<u8> {
+    let mut sorted: Vec<&IndexEntry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut out = Vec::new();
+    out.extend_from_slice(IDX_MAGIC);
+    out.extend_from_slice(&2u32.to_be_bytes());
+
+    let mut fanout = [0u32; 256];
+    for entry in &sorted {
+        fanout[entry.id.as_bytes()[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout {
+        total += count;
+        out.extend_from_slice(&total.to_be_bytes());
+    }
+
+    for entry in &sorted {
+        out.extend_from_slice(entry.id.as_bytes());
+    }
+    for entry in &sorted {
+        out.extend_from_slice(&entry.crc32.to_be_bytes());
+    }
+    let mut large_offsets = Vec::new();
+    for entry in &sorted {
+        if entry.offset < 0x8000_0000 {
+            out.extend_from_slice(&(entry.offset as u32).to_be_bytes());
+        } else {
+            let slot = (large_offsets.len() / 8) as u32 | 0x8000_0000;
+            out.extend_from_slice(&slot.to_be_bytes());
+            large_offsets.extend_from_slice(&entry.offset.to_be_bytes());
+        }
+    }
+    out.extend_from_slice(&large_offsets);
+    out.extend_from_slice(pack_checksum);
+
+    let checksum = hash.digest(&out);
+    out.extend_from_slice(&checksum);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(count: u8) -> Vec<IndexEntry> {
+        (0..count)
+            .map(|i| IndexEntry {
+                id: ObjectId::from_bytes(&[i.wrapping_mul(37); 20]),
+                offset: 12 + i as u64 * 100,
+                crc32: i as u32,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn written_index_reads_back() {
+        let mut written = entries(5);
+        written.push(IndexEntry { id: ObjectId::from_bytes(&[0xfe; 20]), offset: 0x1_0000_0000, crc32: 9 });
+        let data = write_index(&written, &[7; 20], HashKind::Sha1);
+        let index = PackIndex::parse(data, HashKind::Sha1).unwrap();
+
+        assert_eq!(index.len(), 6);
+        assert!(index.verify_checksum());
+        assert_eq!(index.pack_checksum(), [7; 20]);
+        for entry in &written {
+            let i = index.position(&entry.id).unwrap();
+            assert_eq!(index.offset_at(i), entry.offset);
+            assert_eq!(index.crc_at(i), entry.crc32);
+        }
+        assert_eq!(index.lookup(&ObjectId::from_bytes(&[1; 20])), None);
+        assert_eq!(index.ids_with_prefix("fefe"), vec![ObjectId::from_bytes(&[0xfe; 20])]);
+    }
+
+    #[test]
+    fn rejects_a_decreasing_fanout() {
+        let mut data = write_index(&entries(5), &[7; 20], HashKind::Sha1);
+        // Claim one object below 0x00 more than below 0x01.
+        let first = u32::from_be_bytes(data[8..12].try_into().unwrap());
+        data[8..12].copy_from_slice(&(first + 2).to_be_bytes());
+        let error = PackIndex::parse(data, HashKind::Sha1).err().unwrap();
+        assert!(error.contains("fan-out"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_large_offset_slot_past_the_table() {
+        let mut written = entries(3);
+        written.push(IndexEntry { id: ObjectId::from_bytes(&[0xfe; 20]), offset: 0x1_0000_0000, crc32: 9 });
+        let data = write_index(&written, &[7; 20], HashKind::Sha1);
+        let index = PackIndex::parse(data.clone(), HashKind::Sha1).unwrap();
+        let slot = index.offsets_start() + index.position(&written[3].id).unwrap() * 4;
+
+        // The only 64-bit offset is slot 0; slot 1 would read the trailer.
+        let mut data = data;
+        data[slot..slot + 4].copy_from_slice(&0x8000_0001u32.to_be_bytes());
+        let error = PackIndex::parse(data, HashKind::Sha1).err().unwrap();
+        assert!(error.contains("64-bit offset"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_count_the_file_does_not_hold() {
+        for count in [4u32, 6] {
+            let mut data = write_index(&entries(5), &[7; 20], HashKind::Sha1);
+            data[8 + FANOUT_LEN - 4..8 + FANOUT_LEN].copy_from_slice(&count.to_be_bytes());
+            assert!(PackIndex::parse(data, HashKind::Sha1).is_err(), "count {}", count);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use flate2::bufread::ZlibDecoder;
+
+use crate::object::{HashKind, ObjectId};
+use crate::odb::{ObjectDatabase, RawObject};
+use super::delta::{self, DeltaBaseCache, RefBase};
+use super::index::{self, IndexEntry};
+use super::{deflate, encode_entry_header, inflate, parse_entry_header, type_from_kind, EntryHeader, EntryKind};
+
+/// A pack whose entries have all been located and named.
+pub struct IndexedPack {
+    /// The hash function naming the objects, which also produced `checksum`.
+    pub hash: HashKind,
+    pub checksum: Vec<u8>,
+    pub entries: Vec<IndexEntry>,
+    /// Delta bases that had to come from outside the pack. Non-empty only
+    /// for thin packs, which must be completed before they can be stored.
+    pub external_bases: Vec<ObjectId>,
+}
+
+/// Checks the header and trailing checksum of `pack` and returns its object
+/// count.
+pub fn verify_pack_header(pack: &[u8], hash: HashKind) -> Result<u32, String> {
+    if pack.len() < 12 + hash.id_len() || &pack[..4] != b"PACK" {
+        return Err("Not a pack file".to_string());
+    }
+    let version = u32::from_be_bytes(pack[4..8].try_into().unwrap());
+    if version != 2 && version != 3 {
+        return Err(format!("Unsupported pack version {}", version));
+    }
+
+    let (body, trailer) = pack.split_at(pack.len() - hash.id_len());
+    if hash.digest(body) != trailer {
+        return Err("Pack checksum mismatch: the pack is corrupt or truncated".to_string());
+    }
+    Ok(u32::from_be_bytes(pack[8..12].try_into().unwrap()))
+}
+
+/// Walks every entry in order, returning its header and the offset just past
+/// its compressed data. Entries are only delimited by where their zlib
+/// stream ends, so each one has to be inflated to find the next.
+fn scan_entries(pack: &[u8], count: u32, hash: HashKind) -> Result<Vec<(u64, EntryHeader, u64)>, String> {
+    let body_end = pack.len() - hash.id_len();
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut offset = 12u64;
+
+    for i in 0..count {
+        let header = parse_entry_header(&pack[..body_end], offset, hash.id_len())
+            .map_err(|e| format!("Object {}/{}: {}", i + 1, count, e))?;
+        let mut decoder = ZlibDecoder::new(&pack[header.data_offset..body_end]);
+        let mut inflated = Vec::with_capacity(header.size);
+        decoder.read_to_end(&mut inflated)
+            .map_err(|e| format!("Object {}/{}: failed to inflate: {}", i + 1, count, e))?;
+        if inflated.len() != header.size {
+            return Err(format!("Object {}/{}: size mismatch", i + 1, count));
+        }
+        let end = header.data_offset as u64 + decoder.total_in();
+        entries.push((offset, header, end));
+        offset = end;
+    }
+
+    if offset as usize != body_end {
+        return Err("Pack has trailing garbage after its last object".to_string());
+    }
+    Ok(entries)
+}
+
+/// Computes the id and CRC32 of every object in `pack`. Delta bases given by
+/// id are looked up in the pack first and then through `find_external`, which
+/// lets thin packs be indexed against objects the repository already has.
+pub fn index_pack(
+    pack: &[u8],
+    hash: HashKind,
+    find_external: &dyn Fn(&ObjectId) -> Option<RawObject>,
+) -> Result<IndexedPack, String> {
+    let count = verify_pack_header(pack, hash)?;
+    let scanned = scan_entries(pack, count, hash)?;
+
+    let mut cache = DeltaBaseCache::new(delta::DEFAULT_CACHE_LIMIT);
+    let mut ids: HashMap<u64, ObjectId> = HashMap::new();
+    let mut offsets_by_id: HashMap<ObjectId, u64> = HashMap::new();
+    let mut pending = Vec::new();
+    let external_bases: RefCell<Vec<ObjectId>> = RefCell::new(Vec::new());
+
+    for (offset, header, _) in &scanned {
+        match header.kind {
+            EntryKind::Base(kind) => {
+                let data = inflate(&pack[header.data_offset..], header.size)?;
+                let id = hash.hash_object(kind, &data);
+                offsets_by_id.insert(id.clone(), *offset);
+                ids.insert(*offset, id);
+            }
+            _ => pending.push(*offset),
+        }
+    }
+
+    // REF_DELTA bases may appear after the deltas that use them, so keep
+    // resolving until a pass makes no progress.
+    while !pending.is_empty() {
+        let mut unresolved = Vec::new();
+        let mut resolved = Vec::new();
+        {
+            let missing_base = Cell::new(false);
+            let find_ref_base = |id: &ObjectId| -> Result<RefBase, String> {
+                if let Some(&offset) = offsets_by_id.get(id) {
+                    return Ok(RefBase::Offset(offset));
+                }
+                match find_external(id) {
+                    Some(object) => {
+                        let mut external = external_bases.borrow_mut();
+                        if !external.contains(id) {
+                            external.push(id.clone());
+                        }
+                        Ok(RefBase::Object(object))
+                    }
+                    None => {
+                        missing_base.set(true);
+                        Err(format!("missing delta base {}", id))
+                    }
+                }
+            };
+            for offset in pending {
+                missing_base.set(false);
+                match delta::resolve_entry(pack, offset, hash.id_len(), &mut cache, &find_ref_base) {
+                    Ok(object) => resolved.push((offset, hash.hash_object(object.kind, &object.data))),
+                    Err(e) if missing_base.get() => unresolved.push((offset, e)),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        if resolved.is_empty() {
+            let (offset, error) = &unresolved[0];
+            return Err(format!(
+                "Pack has {} unresolved deltas (first at offset {}: {})",
+                unresolved.len(), offset, error
+            ));
+        }
+        for (offset, id) in resolved {
+            offsets_by_id.insert(id.clone(), offset);
+            ids.insert(offset, id);
+        }
+        pending = unresolved.into_iter().map(|(offset, _)| offset).collect();
+    }
+
+    let entries = scanned
+        .iter()
+        .map(|(offset, _, end)| IndexEntry {
+            id: ids[offset].clone(),
+            offset: *offset,
+            crc32: crc32fast::hash(&pack[*offset as usize..*end as usize]),
+        })
+        .collect();
+
+    Ok(IndexedPack {
+        hash,
+        checksum: pack[pack.len() - hash.id_len()..].to_vec(),
+        entries,
+        external_bases: external_bases.into_inner(),
+    })
+}
+
+/// Turns a thin pack into a self-contained one by appending `bases` as whole
+/// objects, then rewriting the object count and trailing checksum.
+pub fn complete_thin_pack(pack: &[u8], bases: &[RawObject], hash: HashKind) -> Vec<u8> {
+    let count = u32::from_be_bytes(pack[8..12].try_into().unwrap()) + bases.len() as u32;
+    let mut out = pack[..pack.len() - hash.id_len()].to_vec();
+    out[8..12].copy_from_slice(&count.to_be_bytes());
+    for base in bases {
+        out.extend_from_slice(&encode_entry_header(type_from_kind(base.kind), base.data.len()));
+        out.extend_from_slice(&deflate(&base.data));
+    }
+    let checksum = hash.digest(&out);
+    out.extend_from_slice(&checksum);
+    out
+}
+
+/// Indexes `pack`, completing it first if it is thin and `odb` has the
+/// missing bases.
+pub fn index_with_bases(odb: &ObjectDatabase, pack: Vec<u8>) -> Result<(Vec<u8>, IndexedPack), String> {
+    let find_external = |id: &ObjectId| odb.read(id).ok();
+    let indexed = index_pack(&pack, odb.hash_kind(), &find_external)?;
+    if indexed.external_bases.is_empty() {
+        return Ok((pack, indexed));
+    }
+
+    let bases = indexed.external_bases
+        .iter()
+        .map(|id| odb.read(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let completed = complete_thin_pack(&pack, &bases, odb.hash_kind());
+    let indexed = index_pack(&completed, odb.hash_kind(), &|_| None)?;
+    Ok((completed, indexed))
+}
+
+/// Indexes `pack`, completing it if thin, and stores it as
+/// `objects/pack/pack-<checksum>.{pack,idx}`, returning the index path.
+pub fn store_pack(odb: &ObjectDatabase, pack: Vec<u8>) -> Result<PathBuf, String> {
+    let (pack, indexed) = index_with_bases(odb, pack)?;
+    write_pack(odb, &pack, &indexed)
+}
+
+/// Writes an already indexed pack and its index into the pack directory.
+pub fn write_pack(odb: &ObjectDatabase, pack: &[u8], indexed: &IndexedPack) -> Result<PathBuf, String> {
+    let pack_dir = odb.objects_dir().join("pack");
+    fs::create_dir_all(&pack_dir).map_err(|e| e.to_string())?;
+    let base = pack_dir.join(format!("pack-{}", hex::encode(&indexed.checksum)));
+    let pack_path = base.with_extension("pack");
+    let idx_path = base.with_extension("idx");
+
+    write_file(&pack_path, pack)?;
+    write_file(&idx_path, &index::write_index(&indexed.entries, &indexed.checksum, indexed.hash))?;
+    Ok(idx_path)
+}
+
+/// Writes via a temporary file so a crash never leaves a half-written pack
+/// or index where readers would pick it up.
+pub fn write_file(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectKind;
+    use crate::pack::delta::{create_delta, DeltaIndex};
+    use crate::pack::{OBJ_OFS_DELTA, OBJ_REF_DELTA};
+
+    fn blob(seed: u32, extra: &str) -> Vec<u8> {
+        let mut data: Vec<u8> = (0..50).flat_map(|i| format!("line {} of {}\n", i, seed).into_bytes()).collect();
+        data.extend_from_slice(extra.as_bytes());
+        data
+    }
+
+    fn delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+        create_delta(&DeltaIndex::new(base), target, usize::MAX).unwrap()
+    }
+
+    /// A thin pack: one whole blob, a REF_DELTA against `external`, which
+    /// the pack does not hold, and an OFS_DELTA on top of that.
+    fn thin_pack(external: &RawObject) -> (Vec<u8>, [Vec<u8>; 3]) {
+        let hash = HashKind::Sha1;
+        let whole = blob(1, "");
+        let middle = blob(2, "middle\n");
+        let top = blob(2, "middle\ntop\n");
+
+        let mut pack = b"PACK".to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&3u32.to_be_bytes());
+        pack.extend_from_slice(&encode_entry_header(type_from_kind(ObjectKind::Blob), whole.len()));
+        pack.extend_from_slice(&deflate(&whole));
+
+        let middle_offset = pack.len();
+        let middle_delta = delta(&external.data, &middle);
+        pack.extend_from_slice(&encode_entry_header(OBJ_REF_DELTA, middle_delta.len()));
+        pack.extend_from_slice(hash.hash_object(external.kind, &external.data).as_bytes());
+        pack.extend_from_slice(&deflate(&middle_delta));
+
+        let top_delta = delta(&middle, &top);
+        let distance = pack.len() - middle_offset;
+        assert!(distance < 0x80);
+        pack.extend_from_slice(&encode_entry_header(OBJ_OFS_DELTA, top_delta.len()));
+        pack.push(distance as u8);
+        pack.extend_from_slice(&deflate(&top_delta));
+
+        let checksum = hash.digest(&pack);
+        pack.extend_from_slice(&checksum);
+        (pack, [whole, middle, top])
+    }
+
+    #[test]
+    fn indexes_and_completes_a_thin_pack() {
+        let hash = HashKind::Sha1;
+        let external = RawObject { kind: ObjectKind::Blob, data: blob(2, "") };
+        let external_id = hash.hash_object(external.kind, &external.data);
+        let (pack, blobs) = thin_pack(&external);
+        let expected: Vec<ObjectId> = blobs.iter().map(|data| hash.hash_object(ObjectKind::Blob, data)).collect();
+
+        let find_external = |id: &ObjectId| (*id == external_id).then(|| RawObject { kind: external.kind, data: external.data.clone() });
+        let indexed = index_pack(&pack, hash, &find_external).unwrap();
+        let ids: Vec<ObjectId> = indexed.entries.iter().map(|entry| entry.id.clone()).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(indexed.external_bases, vec![external_id.clone()]);
+
+        let completed = complete_thin_pack(&pack, &[external], hash);
+        assert_eq!(verify_pack_header(&completed, hash), Ok(4));
+        let indexed = index_pack(&completed, hash, &|_| None).unwrap();
+        let ids: Vec<ObjectId> = indexed.entries.iter().map(|entry| entry.id.clone()).collect();
+        assert_eq!(ids[..3], expected[..]);
+        assert_eq!(ids[3], external_id);
+        assert!(indexed.external_bases.is_empty());
+        assert_eq!(indexed.checksum, completed[completed.len() - hash.id_len()..]);
+    }
+
+    #[test]
+    fn thin_pack_without_its_base_is_rejected() {
+        let external = RawObject { kind: ObjectKind::Blob, data: blob(2, "") };
+        let (pack, _) = thin_pack(&external);
+        match index_pack(&pack, HashKind::Sha1, &|_| None) {
+            Err(error) => assert!(error.contains("unresolved deltas"), "{}", error),
+            Ok(_) => panic!("indexed a thin pack without its base"),
+        }
+    }
+
+    #[test]
+    fn rejects_a_corrupt_checksum() {
+        let external = RawObject { kind: ObjectKind::Blob, data: blob(2, "") };
+        let (mut pack, _) = thin_pack(&external);
+        let last = pack.len() - 1;
+        pack[last] ^= 1;
+        assert!(verify_pack_header(&pack, HashKind::Sha1).is_err());
+    }
+}
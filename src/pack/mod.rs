@@ -0,0 +1,371 @@
+pub mod delta;
+pub mod deltify;
+pub mod index;
+pub mod indexer;
+pub mod writer;
+
+use std::cell::{OnceCell, RefCell};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::object::{HashKind, ObjectId, ObjectKind};
+use crate::odb::RawObject;
+use delta::{DeltaBaseCache, RefBase};
+use index::PackIndex;
+
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+/// What a pack entry holds: a whole object, or a delta against a base found
+/// either earlier in the same pack or by id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    Base(ObjectKind),
+    OfsDelta(u64),
+    RefDelta(ObjectId),
+}
+
+/// The variable-length header in front of every pack entry.
+#[derive(Clone, Debug)]
+pub struct EntryHeader {
+    pub kind: EntryKind,
+    /// Inflated size of the entry data (the delta itself, for deltas).
+    pub size: usize,
+    /// Offset of the zlib stream that follows the header.
+    pub data_offset: usize,
+}
+
+pub fn kind_from_type(type_bits: u8) -> Option<ObjectKind> {
+    match type_bits {
+        OBJ_COMMIT => Some(ObjectKind::Commit),
+        OBJ_TREE => Some(ObjectKind::Tree),
+        OBJ_BLOB => Some(ObjectKind::Blob),
+        OBJ_TAG => Some(ObjectKind::Tag),
+        _ => None,
+    }
+}
+
+pub fn type_from_kind(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Commit => OBJ_COMMIT,
+        ObjectKind::Tree => OBJ_TREE,
+        ObjectKind::Blob => OBJ_BLOB,
+        ObjectKind::Tag => OBJ_TAG,
+    }
+}
+
+/// Parses the entry header at `offset`: 3 type bits and a little-endian
+/// base-128 size, followed by the delta base reference for delta entries.
+pub fn parse_entry_header(data: &[u8], offset: u64, hash_len: usize) -> Result<EntryHeader, String> {
+    let mut pos = offset as usize;
+    let byte_at = |pos: usize| -> Result<u8, String> {
+        data.get(pos).copied().ok_or_else(|| format!("Pack entry at {} is truncated", offset))
+    };
+
+    let mut byte = byte_at(pos)?;
+    pos += 1;
+    let type_bits = (byte >> 4) & 0x7;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        byte = byte_at(pos)?;
+        pos += 1;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+
+    let kind = match type_bits {
+        OBJ_OFS_DELTA => {
+            // Big-endian base-128 where each continuation adds one, so that
+            // every offset has exactly one encoding.
+            let mut byte = byte_at(pos)?;
+            pos += 1;
+            let mut distance = (byte & 0x7f) as u64;
+            while byte & 0x80 != 0 {
+                byte = byte_at(pos)?;
+                pos += 1;
+                distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+            }
+            if distance == 0 || distance > offset {
+                return Err(format!("Pack entry at {} has an invalid delta base offset", offset));
+            }
+            EntryKind::OfsDelta(offset - distance)
+        }
+        OBJ_REF_DELTA => {
+            if pos + hash_len > data.len() {
+                return Err(format!("Pack entry at {} is truncated", offset));
+            }
+            let base = ObjectId::from_bytes(&data[pos..pos + hash_len]);
+            pos += hash_len;
+            EntryKind::RefDelta(base)
+        }
+        other => EntryKind::Base(
+            kind_from_type(other)
+                .ok_or_else(|| format!("Pack entry at {} has unknown type {}", offset, other))?,
+        ),
+    };
+
+    Ok(EntryHeader { kind, size, data_offset: pos })
+}
+
+/// Encodes an entry header: the type and the low four size bits in the first
+/// byte, then the rest of the size seven bits at a time.
+pub fn encode_entry_header(type_bits: u8, size: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut byte = (type_bits << 4) | (size & 0x0f) as u8;
+    let mut rest = size >> 4;
+    while rest != 0 {
+        out.push(byte | 0x80);
+        byte = (rest & 0x7f) as u8;
+        rest >>= 7;
+    }
+    out.push(byte);
+    out
+}
+
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+/// Inflates at most the first `len` bytes of the zlib stream at the start
+/// of `data`.
+pub fn inflate_prefix(data: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(len);
+    ZlibDecoder::new(data)
+        .take(len as u64)
+        .read_to_end(&mut out)
+        .map_err(|e| format!("Failed to inflate pack entry: {}", e))?;
+    Ok(out)
+}
+
+/// Inflates the zlib stream at the start of `data`, which must expand to
+/// exactly `size` bytes.
+pub fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut decoder = ZlibDecoder::new(data);
+    let mut out = Vec::with_capacity(size);
+    decoder.read_to_end(&mut out)
+        .map_err(|e| format!("Failed to inflate pack entry: {}", e))?;
+    if out.len() != size {
+        return Err(format!("Pack entry inflated to {} bytes, expected {}", out.len(), size));
+    }
+    Ok(out)
+}
+
+/// The bytes of a pack file. On unix the file is mapped rather than read,
+/// so taking a few objects out of a large pack only pages in the parts they
+/// live in, and a pack bigger than memory can still be read.
+#[cfg(unix)]
+struct PackData {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl PackData {
+    fn open(path: &Path) -> std::io::Result<PackData> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = fs::File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        // Mapping nothing is an error, and there is nothing to map anyway.
+        if len == 0 {
+            return Ok(PackData { ptr: std::ptr::null_mut(), len });
+        }
+        // SAFETY: a read-only private mapping of a file we hold open. Packs
+        // are written under a temporary name and renamed into place, never
+        // modified after, so the mapped bytes do not change under us.
+        let ptr = unsafe {
+            libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(PackData { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: `ptr` maps `len` readable bytes until `self` is dropped.
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for PackData {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // SAFETY: `ptr` and `len` are the mapping made in `open`, and
+            // no slice borrowed from it outlives `self`.
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct PackData(Vec<u8>);
+
+#[cfg(not(unix))]
+impl PackData {
+    fn open(path: &Path) -> std::io::Result<PackData> {
+        fs::read(path).map(PackData)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A `.pack` file and its `.idx`. The index is read when the pack is opened;
+/// the pack itself is only mapped on first access.
+pub struct Pack {
+    pack_path: PathBuf,
+    index: PackIndex,
+    data: OnceCell<PackData>,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+impl Pack {
+    pub fn open(idx_path: &Path, hash: HashKind) -> Result<Pack, String> {
+        let index = PackIndex::open(idx_path, hash)?;
+        Ok(Pack {
+            pack_path: idx_path.with_extension("pack"),
+            index,
+            data: OnceCell::new(),
+            cache: RefCell::new(DeltaBaseCache::new(delta::DEFAULT_CACHE_LIMIT)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.pack_path
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.index.position(id).is_some()
+    }
+
+    pub fn data(&self) -> Result<&[u8], String> {
+        if let Some(data) = self.data.get() {
+            return Ok(data.bytes());
+        }
+        let data = PackData::open(&self.pack_path)
+            .map_err(|e| format!("Could not read pack {}: {}", self.pack_path.display(), e))?;
+        let bytes = data.bytes();
+        if bytes.len() < 12 || &bytes[..4] != b"PACK" {
+            return Err(format!("{} is not a pack file", self.pack_path.display()));
+        }
+        let count = u32::from_be_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        if count != self.index.len() {
+            return Err(format!(
+                "{} has {} objects but its index lists {}",
+                self.pack_path.display(), count, self.index.len()
+            ));
+        }
+        Ok(self.data.get_or_init(|| data).bytes())
+    }
+
+    pub fn entry_header(&self, offset: u64) -> Result<EntryHeader, String> {
+        parse_entry_header(self.data()?, offset, self.index.hash_len())
+    }
+
+    /// The type and size of the object at `offset`, found without
+    /// reconstructing it: a delta's size comes from the start of its delta
+    /// data and its type from the whole object at the end of its chain.
+    pub fn read_header_at(&self, offset: u64) -> Result<(ObjectKind, usize), String> {
+        let mut header = self.entry_header(offset)?;
+        let size = match header.kind {
+            EntryKind::Base(kind) => return Ok((kind, header.size)),
+            _ => delta::delta_result_size(&inflate_prefix(&self.data()?[header.data_offset..], 20)?)?,
+        };
+        for _ in 0..delta::MAX_CHAIN_DEPTH {
+            let base_offset = match header.kind {
+                EntryKind::Base(kind) => return Ok((kind, size)),
+                EntryKind::OfsDelta(base_offset) => base_offset,
+                EntryKind::RefDelta(ref base_id) => self.index.lookup(base_id).ok_or_else(|| {
+                    format!("Delta base {} is missing from {}", base_id, self.pack_path.display())
+                })?,
+            };
+            header = self.entry_header(base_offset)?;
+        }
+        Err(format!("Delta chain at {} is too deep", offset))
+    }
+
+    pub fn read(&self, id: &ObjectId) -> Result<Option<RawObject>, String> {
+        match self.index.lookup(id) {
+            Some(offset) => self.read_at(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_at(&self, offset: u64) -> Result<RawObject, String> {
+        let data = self.data()?;
+        let find_ref_base = |id: &ObjectId| {
+            self.index.lookup(id).map(RefBase::Offset).ok_or_else(|| {
+                format!("Delta base {} is missing from {}", id, self.pack_path.display())
+            })
+        };
+        delta::resolve_entry(
+            data,
+            offset,
+            self.index.hash_len(),
+            &mut self.cache.borrow_mut(),
+            &find_ref_base,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use writer::PackWriter;
+
+    #[test]
+    fn reads_objects_from_a_pack_on_disk() {
+        let dir = std::env::temp_dir().join(format!("codecrafters-git-pack-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let hash = HashKind::Sha1;
+
+        let blobs: Vec<Vec<u8>> = (0..3).map(|i| format!("blob number {}\n", i).repeat(20).into_bytes()).collect();
+        let mut writer = PackWriter::new(hash);
+        for blob in &blobs {
+            writer.add_object(hash.hash_object(ObjectKind::Blob, blob), ObjectKind::Blob, blob);
+        }
+        let (pack, indexed) = writer.finish();
+        let idx_path = dir.join("pack-test.idx");
+        fs::write(dir.join("pack-test.pack"), &pack).unwrap();
+        fs::write(&idx_path, index::write_index(&indexed.entries, &indexed.checksum, hash)).unwrap();
+
+        let opened = Pack::open(&idx_path, hash).unwrap();
+        for blob in &blobs {
+            let object = opened.read(&hash.hash_object(ObjectKind::Blob, blob)).unwrap().unwrap();
+            assert_eq!(object.kind, ObjectKind::Blob);
+            assert_eq!(&object.data, blob);
+        }
+        assert!(opened.read(&hash.hash_object(ObjectKind::Blob, b"absent")).unwrap().is_none());
+
+        // An index that disagrees with its pack about the object count.
+        let short = index::write_index(&indexed.entries[..2], &indexed.checksum, hash);
+        fs::write(&idx_path, short).unwrap();
+        let opened = Pack::open(&idx_path, hash).unwrap();
+        assert!(opened.data().is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use crate::object::{HashKind, ObjectId, ObjectKind};
+use super::index::IndexEntry;
+use super::indexer::IndexedPack;
+use super::{deflate, encode_entry_header, type_from_kind, OBJ_OFS_DELTA};
+
+/// Builds a version 2 pack in memory, recording each entry's offset and
+/// CRC32 as it goes so the index can be written without re-reading it.
+pub struct PackWriter {
+    hash: HashKind,
+    out: Vec<u8>,
+    entries: Vec<IndexEntry>,
+}
+
+impl Default for PackWriter {
+    fn default() -> Self {
+        PackWriter::new(HashKind::default())
+    }
+}
+
+impl PackWriter {
+    pub fn new(hash: HashKind) -> PackWriter {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"PACK");
+        out.extend_from_slice(&2u32.to_be_bytes());
+        // The object count is filled in by `finish`.
+        out.extend_from_slice(&0u32.to_be_bytes());
+        PackWriter { hash, out, entries: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends a whole object and returns its offset.
+    pub fn add_object(&mut self, id: ObjectId, kind: ObjectKind, data: &[u8]) -> u64 {
+        let mut entry = encode_entry_header(type_from_kind(kind), data.len());
+        entry.extend_from_slice(&deflate(data));
+        self.push_entry(id, entry)
+    }
+
+    /// Appends `delta` as an OFS_DELTA against the entry at `base_offset`,
+    /// which must already have been written.
+    pub fn add_ofs_delta(&mut self, id: ObjectId, base_offset: u64, delta: &[u8]) -> u64 {
+        let offset = self.out.len() as u64;
+        let mut entry = encode_entry_header(OBJ_OFS_DELTA, delta.len());
+        entry.extend_from_slice(&encode_ofs_distance(offset - base_offset));
+        entry.extend_from_slice(&deflate(delta));
+        self.push_entry(id, entry)
+    }
+
+    fn push_entry(&mut self, id: ObjectId, entry: Vec<u8>) -> u64 {
+        let offset = self.out.len() as u64;
+        self.entries.push(IndexEntry { id, offset, crc32: crc32fast::hash(&entry) });
+        self.out.extend_from_slice(&entry);
+        offset
+    }
+
+    /// Patches in the object count and appends the trailing checksum.
+    pub fn finish(mut self) -> (Vec<u8>, IndexedPack) {
+        let count = self.entries.len() as u32;
+        self.out[8..12].copy_from_slice(&count.to_be_bytes());
+        let checksum = self.hash.digest(&self.out);
+        self.out.extend_from_slice(&checksum);
+        let indexed = IndexedPack {
+            hash: self.hash,
+            checksum,
+            entries: self.entries,
+            external_bases: Vec::new(),
+        };
+        (self.out, indexed)
+    }
+}
+
+/// The inverse of the OFS_DELTA offset decoding in `parse_entry_header`.
+fn encode_ofs_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
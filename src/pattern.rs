@@ -0,0 +1,132 @@
+/// A POSIX basic regular expression, as `log --grep` and `--author` take
+/// them: literals, `.`, bracket expressions, `*`, and the `^` and `$`
+/// anchors. As in a BRE, `+`, `?`, `|` and parentheses are literals.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pieces: Vec<Piece>,
+    anchored_start: bool,
+    anchored_end: bool,
+    ignore_case: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Piece {
+    atom: Atom,
+    repeated: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Atom {
+    Char(char),
+    Any,
+    /// A bracket expression: single characters and ranges, maybe negated.
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Pattern, String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut i = 0;
+        let anchored_start = chars.first() == Some(&'^');
+        if anchored_start {
+            i = 1;
+        }
+        let mut anchored_end = false;
+
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            let atom = match c {
+                '$' if i == chars.len() => {
+                    anchored_end = true;
+                    break;
+                }
+                // A leading star has nothing to repeat and is a literal.
+                '*' if !pieces.is_empty() => {
+                    let last = pieces.last_mut().expect("checked above");
+                    last.repeated = true;
+                    continue;
+                }
+                '.' => Atom::Any,
+                '\\' => {
+                    let escaped = *chars.get(i).ok_or_else(|| format!("trailing backslash in '{}'", pattern))?;
+                    i += 1;
+                    Atom::Char(escaped)
+                }
+                '[' => {
+                    let (atom, end) = Self::class(&chars, i).ok_or_else(|| format!("unmatched [ in '{}'", pattern))?;
+                    i = end;
+                    atom
+                }
+                c => Atom::Char(c),
+            };
+            pieces.push(Piece { atom, repeated: false });
+        }
+        Ok(Pattern { pieces, anchored_start, anchored_end, ignore_case })
+    }
+
+    /// Parses a bracket expression whose contents start at `start`, giving
+    /// the atom and the index just past the closing `]`. A `]` right after
+    /// the opening bracket (or its `^`) is a member, not the end.
+    fn class(chars: &[char], start: usize) -> Option<(Atom, usize)> {
+        let mut i = start;
+        let negated = chars.get(i) == Some(&'^');
+        if negated {
+            i += 1;
+        }
+        let first = i;
+        let mut ranges = Vec::new();
+        loop {
+            let c = *chars.get(i)?;
+            if c == ']' && i > first {
+                return Some((Atom::Class { negated, ranges }, i + 1));
+            }
+            if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&end| end != ']') {
+                ranges.push((c, chars[i + 2]));
+                i += 3;
+            } else {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = match self.ignore_case {
+            true => text.to_lowercase().chars().collect(),
+            false => text.chars().collect(),
+        };
+        match self.anchored_start {
+            true => self.match_here(0, &text, 0),
+            false => (0..=text.len()).any(|start| self.match_here(0, &text, start)),
+        }
+    }
+
+    /// Backtracking match of `pieces[piece..]` against `text[at..]`.
+    fn match_here(&self, piece: usize, text: &[char], at: usize) -> bool {
+        let Some(current) = self.pieces.get(piece) else {
+            return !self.anchored_end || at == text.len();
+        };
+        if current.repeated {
+            let run = text[at..].iter().take_while(|&&c| self.atom_matches(&current.atom, c)).count();
+            return (0..=run).rev().any(|n| self.match_here(piece + 1, text, at + n));
+        }
+        text.get(at).is_some_and(|&c| self.atom_matches(&current.atom, c)) && self.match_here(piece + 1, text, at + 1)
+    }
+
+    fn atom_matches(&self, atom: &Atom, c: char) -> bool {
+        let fold = |c: char| match self.ignore_case {
+            true => c.to_lowercase().next().unwrap_or(c),
+            false => c,
+        };
+        match atom {
+            Atom::Char(expected) => fold(*expected) == c,
+            Atom::Any => c != '\n',
+            Atom::Class { negated, ranges } => {
+                ranges.iter().any(|&(low, high)| (fold(low)..=fold(high)).contains(&c)) != *negated
+            }
+        }
+    }
+}
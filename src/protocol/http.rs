@@ -0,0 +1,115 @@
+use reqwest::blocking::Client;
+use url::Url;
+
+use crate::object::ObjectId;
+use super::pktline::{self, Packet, PktReader};
+use super::{demux_pack_response, Advertisement};
+
+const UPLOAD_PACK: &str = "git-upload-pack";
+const AGENT: &str = concat!("agent=codecrafters-git/", env!("CARGO_PKG_VERSION"));
+
+/// The smart HTTP transport: `GET info/refs?service=git-upload-pack` to list
+/// refs, then `POST git-upload-pack` with the wanted ids to receive a pack.
+pub struct HttpTransport {
+    base: String,
+    client: Client,
+}
+
+impl HttpTransport {
+    pub fn new(url: &Url) -> HttpTransport {
+        HttpTransport {
+            base: url.as_str().trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    pub fn discover_refs(&self) -> Result<Advertisement, String> {
+        let url = format!("{}/info/refs?service={}", self.base, UPLOAD_PACK);
+        let response = self.client
+            .get(&url)
+            .header("Accept", "*/*")
+            .send()
+            .map_err(|e| format!("Failed to fetch refs: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("{} returned {}", url, response.status()));
+        }
+
+        let content_type = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        if content_type != format!("application/x-{}-advertisement", UPLOAD_PACK) {
+            return Err(format!("{} does not speak the smart HTTP protocol", self.base));
+        }
+
+        let body = response.bytes().map_err(|e| format!("Failed to read refs response: {}", e))?;
+        let mut reader = PktReader::new(&body);
+
+        // Smart HTTP prefixes the advertisement with a service announcement.
+        match reader.next_packet()? {
+            Some(Packet::Data(line)) if pktline::trim_newline(line) == format!("# service={}", UPLOAD_PACK).as_bytes() => {}
+            _ => return Err("Missing service announcement in refs response".to_string()),
+        }
+        match reader.next_packet()? {
+            Some(Packet::Flush) => {}
+            _ => return Err("Missing flush after service announcement".to_string()),
+        }
+
+        Advertisement::parse(&mut reader)
+    }
+
+    /// Asks for a pack containing `wants` and everything reachable from
+    /// them. With no haves to negotiate, the request is just the want lines
+    /// followed by `done`.
+    pub fn fetch_pack(&self, advertisement: &Advertisement, wants: &[ObjectId]) -> Result<Vec<u8>, String> {
+        let side_band = if advertisement.has_capability("side-band-64k") {
+            Some("side-band-64k")
+        } else if advertisement.has_capability("side-band") {
+            Some("side-band")
+        } else {
+            None
+        };
+        // The object format is echoed back only when the server announced it.
+        let object_format = advertisement
+            .has_capability("object-format")
+            .then(|| advertisement.object_format())
+            .transpose()?
+            .map(|hash| format!("object-format={}", hash.name()));
+        let mut capabilities: Vec<&str> = Vec::new();
+        if advertisement.has_capability("ofs-delta") {
+            capabilities.push("ofs-delta");
+        }
+        capabilities.extend(side_band);
+        capabilities.extend(object_format.as_deref());
+        capabilities.push(AGENT);
+
+        let mut body = Vec::new();
+        for (i, want) in wants.iter().enumerate() {
+            let line = if i == 0 {
+                format!("want {} {}\n", want, capabilities.join(" "))
+            } else {
+                format!("want {}\n", want)
+            };
+            pktline::write_packet(&mut body, line.as_bytes());
+        }
+        pktline::write_flush(&mut body);
+        pktline::write_packet(&mut body, b"done\n");
+
+        let url = format!("{}/{}", self.base, UPLOAD_PACK);
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", format!("application/x-{}-request", UPLOAD_PACK))
+            .header("Accept", format!("application/x-{}-result", UPLOAD_PACK))
+            .body(body)
+            .send()
+            .map_err(|e| format!("Failed to fetch pack: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("{} returned {}", url, response.status()));
+        }
+
+        let body = response.bytes().map_err(|e| format!("Failed to read pack response: {}", e))?;
+        demux_pack_response(&body, side_band.is_some())
+    }
+}
@@ -0,0 +1,222 @@
+pub mod http;
+pub mod pktline;
+
+use crate::object::{HashKind, ObjectId};
+use pktline::{trim_newline, Packet, PktReader};
+
+#[derive(Clone, Debug)]
+pub struct AdvertisedRef {
+    pub name: String,
+    pub id: ObjectId,
+    /// For annotated tags, the object the tag ultimately points at.
+    pub peeled: Option<ObjectId>,
+}
+
+/// The refs and capabilities an upload-pack server announces.
+#[derive(Clone, Debug, Default)]
+pub struct Advertisement {
+    pub refs: Vec<AdvertisedRef>,
+    pub capabilities: Vec<String>,
+}
+
+impl Advertisement {
+    /// Parses a v0/v1 ref advertisement. The first ref line carries the
+    /// capability list after a NUL; an empty repository advertises a single
+    /// `capabilities^{}` placeholder instead of refs.
+    pub fn parse(reader: &mut PktReader) -> Result<Advertisement, String> {
+        let mut advertisement = Advertisement::default();
+        let mut first = true;
+
+        while let Some(packet) = reader.next_packet()? {
+            let line = match packet {
+                Packet::Flush => break,
+                Packet::Data(line) => trim_newline(line),
+                _ => return Err("Unexpected packet in ref advertisement".to_string()),
+            };
+
+            let line = if first {
+                first = false;
+                match line.iter().position(|&b| b == 0) {
+                    Some(nul) => {
+                        advertisement.capabilities = String::from_utf8_lossy(&line[nul + 1..])
+                            .split(' ')
+                            .filter(|cap| !cap.is_empty())
+                            .map(str::to_string)
+                            .collect();
+                        &line[..nul]
+                    }
+                    None => line,
+                }
+            } else {
+                line
+            };
+
+            let line = String::from_utf8_lossy(line);
+            let (id, name) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("Malformed ref advertisement line: {}", line))?;
+            if name == "capabilities^{}" {
+                continue;
+            }
+            let id = ObjectId::from_hex(id)?;
+
+            if let Some(tag) = name.strip_suffix("^{}") {
+                if let Some(last) = advertisement.refs.iter_mut().rev().find(|r| r.name == tag) {
+                    last.peeled = Some(id);
+                }
+                continue;
+            }
+            advertisement.refs.push(AdvertisedRef { name: name.to_string(), id, peeled: None });
+        }
+
+        Ok(advertisement)
+    }
+
+    pub fn has_capability(&self, name: &str) -> bool {
+        self.capabilities.iter().any(|cap| cap == name || cap.starts_with(&format!("{}=", name)))
+    }
+
+    /// The ref `name` is a symbolic link to, from a `symref=` capability.
+    pub fn symref_target(&self, name: &str) -> Option<&str> {
+        self.capabilities.iter().find_map(|cap| {
+            let (from, to) = cap.strip_prefix("symref=")?.split_once(':')?;
+            (from == name).then_some(to)
+        })
+    }
+
+    /// The server's hash function, from the `object-format=` capability.
+    /// Servers that do not announce one only speak SHA-1.
+    pub fn object_format(&self) -> Result<HashKind, String> {
+        match self.capabilities.iter().find_map(|cap| cap.strip_prefix("object-format=")) {
+            Some(name) => HashKind::from_name(name),
+            None => Ok(HashKind::Sha1),
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&AdvertisedRef> {
+        self.refs.iter().find(|r| r.name == name)
+    }
+}
+
+/// Splits an upload-pack response into the pack it carries. The negotiation
+/// lines (`NAK`/`ACK`) come first; after them the pack is either multiplexed
+/// over side-band channels (1 = pack data, 2 = progress, 3 = fatal error) or,
+/// without side-band, follows as raw bytes.
+pub fn demux_pack_response(body: &[u8], side_band: bool) -> Result<Vec<u8>, String> {
+    let mut reader = PktReader::new(body);
+
+    loop {
+        match reader.next_packet()? {
+            Some(Packet::Data(line)) => {
+                let line = trim_newline(line);
+                if line == b"NAK" || line.starts_with(b"ACK ") {
+                    break;
+                }
+                if let Some(message) = line.strip_prefix(b"ERR ") {
+                    return Err(format!("Remote error: {}", String::from_utf8_lossy(message)));
+                }
+                if line.starts_with(b"shallow ") || line.starts_with(b"unshallow ") {
+                    continue;
+                }
+                return Err(format!("Unexpected upload-pack response: {}", String::from_utf8_lossy(line)));
+            }
+            Some(Packet::Flush) => continue,
+            Some(_) => return Err("Unexpected packet in upload-pack response".to_string()),
+            None => return Err("Upload-pack response ended before the pack".to_string()),
+        }
+    }
+
+    if !side_band {
+        return Ok(reader.remaining().to_vec());
+    }
+
+    let mut pack = Vec::new();
+    while let Some(packet) = reader.next_packet()? {
+        let payload = match packet {
+            Packet::Flush => break,
+            Packet::Data(payload) => payload,
+            _ => return Err("Unexpected packet in side-band stream".to_string()),
+        };
+        match payload.split_first() {
+            Some((1, data)) => pack.extend_from_slice(data),
+            Some((2, progress)) => eprint!("{}", String::from_utf8_lossy(progress)),
+            Some((3, message)) => {
+                return Err(format!("Remote error: {}", String::from_utf8_lossy(trim_newline(message))))
+            }
+            _ => return Err("Invalid side-band channel".to_string()),
+        }
+    }
+    Ok(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pktline::{write_flush, write_packet};
+
+    fn side_band(channel: u8, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![channel];
+        payload.extend_from_slice(data);
+        payload
+    }
+
+    #[test]
+    fn demuxes_side_band_pack_data() {
+        let mut body = Vec::new();
+        write_packet(&mut body, b"NAK\n");
+        write_packet(&mut body, &side_band(2, b"Counting objects: 3\r"));
+        write_packet(&mut body, &side_band(1, b"PACK"));
+        write_packet(&mut body, &side_band(2, b"done.\n"));
+        write_packet(&mut body, &side_band(1, b"rest"));
+        write_flush(&mut body);
+        assert_eq!(demux_pack_response(&body, true).unwrap(), b"PACKrest");
+    }
+
+    #[test]
+    fn skips_acks_and_shallow_lines() {
+        let mut body = Vec::new();
+        write_packet(&mut body, b"shallow 0123456789012345678901234567890123456789\n");
+        write_flush(&mut body);
+        write_packet(&mut body, b"ACK 0123456789012345678901234567890123456789\n");
+        body.extend_from_slice(b"PACKraw");
+        assert_eq!(demux_pack_response(&body, false).unwrap(), b"PACKraw");
+    }
+
+    #[test]
+    fn reports_remote_errors() {
+        let mut body = Vec::new();
+        write_packet(&mut body, b"NAK\n");
+        write_packet(&mut body, &side_band(3, b"repository is broken\n"));
+        assert_eq!(demux_pack_response(&body, true).unwrap_err(), "Remote error: repository is broken");
+
+        let mut body = Vec::new();
+        write_packet(&mut body, b"ERR access denied\n");
+        assert_eq!(demux_pack_response(&body, true).unwrap_err(), "Remote error: access denied");
+
+        let mut body = Vec::new();
+        write_packet(&mut body, b"NAK\n");
+        write_packet(&mut body, &side_band(4, b"?"));
+        assert!(demux_pack_response(&body, true).is_err());
+    }
+
+    #[test]
+    fn parses_advertisement() {
+        let head = "0123456789012345678901234567890123456789";
+        let tag = "1111111111111111111111111111111111111111";
+        let mut body = Vec::new();
+        write_packet(&mut body, format!("{} HEAD\0ofs-delta symref=HEAD:refs/heads/main\n", head).as_bytes());
+        write_packet(&mut body, format!("{} refs/heads/main\n", head).as_bytes());
+        write_packet(&mut body, format!("{} refs/tags/v1\n", tag).as_bytes());
+        write_packet(&mut body, format!("{} refs/tags/v1^{{}}\n", head).as_bytes());
+        write_flush(&mut body);
+
+        let advertisement = Advertisement::parse(&mut PktReader::new(&body)).unwrap();
+        assert_eq!(advertisement.refs.len(), 3);
+        assert!(advertisement.has_capability("ofs-delta"));
+        assert!(!advertisement.has_capability("side-band"));
+        assert_eq!(advertisement.symref_target("HEAD"), Some("refs/heads/main"));
+        let v1 = advertisement.find("refs/tags/v1").unwrap();
+        assert_eq!(v1.id.to_string(), tag);
+        assert_eq!(v1.peeled.as_ref().map(ObjectId::to_string).as_deref(), Some(head));
+    }
+}
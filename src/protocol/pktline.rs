@@ -0,0 +1,114 @@
+/// One pkt-line: four hex digits of length (including themselves) followed
+/// by the payload, or one of the special zero-payload packets.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Flush,
+    Delim,
+    ResponseEnd,
+    Data(&'a [u8]),
+}
+
+/// Longest payload a single pkt-line can carry.
+pub const MAX_PAYLOAD: usize = 65516;
+
+pub fn write_packet(out: &mut Vec<u8>, payload: &[u8]) {
+    out.extend_from_slice(format!("{:04x}", payload.len() + 4).as_bytes());
+    out.extend_from_slice(payload);
+}
+
+pub fn write_flush(out: &mut Vec<u8>) {
+    out.extend_from_slice(b"0000");
+}
+
+/// Reads pkt-lines out of an in-memory response body.
+pub struct PktReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PktReader<'a> {
+    pub fn new(data: &'a [u8]) -> PktReader<'a> {
+        PktReader { data, pos: 0 }
+    }
+
+    /// Bytes not yet consumed as pkt-lines.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    pub fn next_packet(&mut self) -> Result<Option<Packet<'a>>, String> {
+        if self.pos == self.data.len() {
+            return Ok(None);
+        }
+        let len_hex = self.data
+            .get(self.pos..self.pos + 4)
+            .ok_or("Truncated pkt-line length")?;
+        let len_str = std::str::from_utf8(len_hex).map_err(|_| "Invalid pkt-line length")?;
+        let len = usize::from_str_radix(len_str, 16)
+            .map_err(|_| format!("Invalid pkt-line length {:?}", len_str))?;
+        self.pos += 4;
+
+        let packet = match len {
+            0 => Packet::Flush,
+            1 => Packet::Delim,
+            2 => Packet::ResponseEnd,
+            3 => return Err("Invalid pkt-line length 3".to_string()),
+            _ => {
+                let end = self.pos + len - 4;
+                let payload = self.data.get(self.pos..end).ok_or("Truncated pkt-line")?;
+                self.pos = end;
+                Packet::Data(payload)
+            }
+        };
+        Ok(Some(packet))
+    }
+}
+
+/// Strips the trailing newline most text pkt-lines carry.
+pub fn trim_newline(payload: &[u8]) -> &[u8] {
+    payload.strip_suffix(b"\n").unwrap_or(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read_packets() {
+        let mut out = Vec::new();
+        write_packet(&mut out, b"hello\n");
+        write_flush(&mut out);
+        write_packet(&mut out, b"");
+        assert_eq!(out, b"000ahello\n00000004");
+
+        let mut reader = PktReader::new(&out);
+        assert_eq!(reader.next_packet(), Ok(Some(Packet::Data(b"hello\n"))));
+        assert_eq!(reader.next_packet(), Ok(Some(Packet::Flush)));
+        assert_eq!(reader.next_packet(), Ok(Some(Packet::Data(b""))));
+        assert_eq!(reader.next_packet(), Ok(None));
+    }
+
+    #[test]
+    fn special_packets_and_remaining_bytes() {
+        let mut reader = PktReader::new(b"000100020000PACK");
+        assert_eq!(reader.next_packet(), Ok(Some(Packet::Delim)));
+        assert_eq!(reader.next_packet(), Ok(Some(Packet::ResponseEnd)));
+        assert_eq!(reader.next_packet(), Ok(Some(Packet::Flush)));
+        assert_eq!(reader.remaining(), b"PACK");
+    }
+
+    #[test]
+    fn rejects_malformed_lengths() {
+        assert!(PktReader::new(b"0003").next_packet().is_err());
+        assert!(PktReader::new(b"00zz").next_packet().is_err());
+        assert!(PktReader::new(b"00").next_packet().is_err());
+        assert!(PktReader::new(b"0009abc").next_packet().is_err());
+    }
+
+    #[test]
+    fn trims_one_newline() {
+        assert_eq!(trim_newline(b"NAK\n"), b"NAK");
+        assert_eq!(trim_newline(b"NAK"), b"NAK");
+        assert_eq!(trim_newline(b"a\n\n"), b"a\n");
+    }
+}
@@ -0,0 +1,72 @@
+/// Quotes a path the way git prints it when `core.quotePath` is on: paths
+/// with control characters, `"`, `\` or non-ASCII bytes are wrapped in
+/// double quotes with C-style escapes (octal for bytes without a short
+/// form). Other paths are printed as they are.
+pub fn quote_path(path: &[u8]) -> String {
+    let needs_quoting = path
+        .iter()
+        .any(|&b| b < 0x20 || b == 0x7f || b == b'"' || b == b'\\' || b >= 0x80);
+    if !needs_quoting {
+        return String::from_utf8_lossy(path).into_owned();
+    }
+
+    let mut out = String::from("\"");
+    for &b in path {
+        match b {
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b'\t' => out.push_str("\\t"),
+            b'\n' => out.push_str("\\n"),
+            0x0b => out.push_str("\\v"),
+            0x0c => out.push_str("\\f"),
+            b'\r' => out.push_str("\\r"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            _ if !(0x20..0x7f).contains(&b) => out.push_str(&format!("\\{:03o}", b)),
+            _ => out.push(b as char),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Reverses `quote_path`: a path in double quotes has its escapes decoded,
+/// and anything else is taken as it is. Returns `None` for a malformed
+/// quoted path.
+pub fn unquote_path(text: &str) -> Option<Vec<u8>> {
+    let Some(quoted) = text.strip_prefix('"') else {
+        return Some(text.as_bytes().to_vec());
+    };
+    let bytes = quoted.strip_suffix('"')?.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let escape = *bytes.get(i + 1)?;
+        i += 2;
+        out.push(match escape {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'v' => 0x0b,
+            b'f' => 0x0c,
+            b'r' => b'\r',
+            b'"' | b'\\' => escape,
+            b'0'..=b'3' => {
+                let digits = bytes.get(i - 1..i + 2)?;
+                if !digits.iter().all(|d| (b'0'..=b'7').contains(d)) {
+                    return None;
+                }
+                i += 2;
+                digits.iter().fold(0u8, |value, d| (value << 3) | (d - b'0'))
+            }
+            _ => return None,
+        });
+    }
+    Some(out)
+}
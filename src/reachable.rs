@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+
+use crate::object::tree::MODE_GITLINK;
+use crate::object::{Commit, ObjectId, ObjectKind, Tag, Tree};
+use crate::odb::ObjectDatabase;
+
+/// An object found while walking history, with the name it was reached
+/// under (the tree entry name for trees and blobs).
+#[derive(Clone, Debug)]
+pub struct ReachableObject {
+    pub id: ObjectId,
+    pub kind: ObjectKind,
+    pub name: Option<Vec<u8>>,
+}
+
+/// Lists every object reachable from `tips`: commits first, in the order
+/// they are discovered, then tags, then the trees and blobs they point at.
+/// Submodule commits (gitlinks) are not followed.
+pub fn reachable_objects(odb: &ObjectDatabase, tips: &[ObjectId]) -> Result<Vec<ReachableObject>, String> {
+    let mut seen: HashSet<ObjectId> = HashSet::new();
+    let mut commits = Vec::new();
+    let mut tags = Vec::new();
+    let mut blobs = Vec::new();
+    let mut trees_to_walk = Vec::new();
+    let mut stack: Vec<ObjectId> = tips.iter().rev().cloned().collect();
+
+    while let Some(id) = stack.pop() {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        let raw = odb.read(&id)?;
+        match raw.kind {
+            ObjectKind::Commit => {
+                let commit = Commit::parse(&raw.data)?;
+                trees_to_walk.push(commit.tree.clone());
+                stack.extend(commit.parents.iter().rev().cloned());
+                commits.push(ReachableObject { id, kind: ObjectKind::Commit, name: None });
+            }
+            ObjectKind::Tag => {
+                let tag = Tag::parse(&raw.data)?;
+                stack.push(tag.object.clone());
+                tags.push(ReachableObject { id, kind: ObjectKind::Tag, name: None });
+            }
+            ObjectKind::Tree => {
+                seen.remove(&id);
+                trees_to_walk.push(id);
+            }
+            ObjectKind::Blob => {
+                blobs.push(ReachableObject { id, kind: ObjectKind::Blob, name: None });
+            }
+        }
+    }
+
+    let mut objects = commits;
+    objects.extend(tags);
+    for tree in trees_to_walk {
+        walk_tree(odb, &tree, None, &mut seen, &mut objects)?;
+    }
+    objects.extend(blobs);
+    Ok(objects)
+}
+
+fn walk_tree(
+    odb: &ObjectDatabase,
+    id: &ObjectId,
+    name: Option<Vec<u8>>,
+    seen: &mut HashSet<ObjectId>,
+    out: &mut Vec<ReachableObject>,
+) -> Result<(), String> {
+    if !seen.insert(id.clone()) {
+        return Ok(());
+    }
+    let raw = odb.read(id)?;
+    if raw.kind != ObjectKind::Tree {
+        return Err(format!("Object {} is a {}, not a tree", id, raw.kind));
+    }
+    let tree = Tree::parse(&raw.data, odb.hash_kind())?;
+    out.push(ReachableObject { id: id.clone(), kind: ObjectKind::Tree, name });
+
+    for entry in &tree.entries {
+        if entry.mode == MODE_GITLINK {
+            continue;
+        }
+        if entry.is_tree() {
+            walk_tree(odb, &entry.id, Some(entry.name.clone()), seen, out)?;
+        } else if seen.insert(entry.id.clone()) {
+            out.push(ReachableObject {
+                id: entry.id.clone(),
+                kind: ObjectKind::Blob,
+                name: Some(entry.name.clone()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Every commit reachable from `tip`, `tip` included.
+pub fn ancestors(odb: &ObjectDatabase, tip: &ObjectId) -> Result<HashSet<ObjectId>, String> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![tip.clone()];
+    while let Some(id) = stack.pop() {
+        if seen.insert(id.clone()) {
+            stack.extend(odb.read_commit(&id)?.parents);
+        }
+    }
+    Ok(seen)
+}
+
+/// The best common ancestors of `a` and `b`: the commits reachable from
+/// both that are not themselves ancestors of another such commit. Newest
+/// first by committer date.
+pub fn merge_bases(odb: &ObjectDatabase, a: &ObjectId, b: &ObjectId) -> Result<Vec<ObjectId>, String> {
+    let from_a = ancestors(odb, a)?;
+    let common: HashSet<ObjectId> = ancestors(odb, b)?
+        .into_iter()
+        .filter(|id| from_a.contains(id))
+        .collect();
+
+    // Anything reachable from a common ancestor's parents is common too, and
+    // is redundant as a base.
+    let mut redundant = HashSet::new();
+    let mut stack = Vec::new();
+    for id in &common {
+        stack.extend(odb.read_commit(id)?.parents);
+    }
+    while let Some(id) = stack.pop() {
+        if redundant.insert(id.clone()) {
+            stack.extend(odb.read_commit(&id)?.parents);
+        }
+    }
+
+    let mut bases = Vec::new();
+    for id in common {
+        if !redundant.contains(&id) {
+            let time = odb.read_commit(&id)?.committer.time;
+            bases.push((time, id));
+        }
+    }
+    bases.sort_by(|x, y| y.cmp(x));
+    Ok(bases.into_iter().map(|(_, id)| id).collect())
+}
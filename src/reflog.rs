@@ -0,0 +1,229 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, Config};
+use crate::date;
+use crate::ident::{self, Role};
+use crate::lockfile::LockFile;
+use crate::object::{ObjectId, Signature};
+use crate::refs;
+
+/// One line of a reflog: a ref moving from `old` to `new`, who moved it
+/// and when, and why. A null `old` means the ref was created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub old: ObjectId,
+    pub new: ObjectId,
+    pub committer: Signature,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    /// `<old> <new> <name> <<email>> <time> <tz>[\t<message>]`.
+    fn parse(line: &str) -> Result<ReflogEntry, String> {
+        let malformed = || format!("Malformed reflog line: {}", line);
+        let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut parts = head.splitn(3, ' ');
+        let old = ObjectId::from_hex(parts.next().ok_or_else(malformed)?).map_err(|_| malformed())?;
+        let new = ObjectId::from_hex(parts.next().ok_or_else(malformed)?).map_err(|_| malformed())?;
+        let committer = Signature::parse(parts.next().ok_or_else(malformed)?.as_bytes())?;
+        Ok(ReflogEntry { old, new, committer, message: message.to_string() })
+    }
+
+    fn to_line(&self) -> String {
+        match self.message.is_empty() {
+            true => format!("{} {} {}\n", self.old, self.new, self.committer),
+            false => format!("{} {} {}\t{}\n", self.old, self.new, self.committer, self.message),
+        }
+    }
+}
+
+pub fn path(git_dir: &Path, name: &str) -> PathBuf {
+    git_dir.join("logs").join(name)
+}
+
+pub fn exists(git_dir: &Path, name: &str) -> bool {
+    path(git_dir, name).is_file()
+}
+
+/// The reflog of `name`, oldest entry first; empty when there is none.
+pub fn read(git_dir: &Path, name: &str) -> Result<Vec<ReflogEntry>, String> {
+    let text = match fs::read_to_string(path(git_dir, name)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Could not read reflog for {}: {}", name, e)),
+    };
+    text.lines().filter(|line| !line.is_empty()).map(ReflogEntry::parse).collect()
+}
+
+/// Replaces the reflog of `name` with `entries`.
+pub fn rewrite(git_dir: &Path, name: &str, entries: &[ReflogEntry]) -> Result<(), String> {
+    let mut lock = LockFile::acquire(&path(git_dir, name))?;
+    let text: String = entries.iter().map(ReflogEntry::to_line).collect();
+    lock.write_all(text.as_bytes())?;
+    lock.commit()
+}
+
+pub fn delete(git_dir: &Path, name: &str) -> Result<(), String> {
+    match fs::remove_file(path(git_dir, name)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Could not remove reflog for {}: {}", name, e)),
+    }
+}
+
+/// Every ref that has a reflog, `HEAD` first and the rest sorted.
+pub fn list(git_dir: &Path) -> Result<Vec<String>, String> {
+    fn walk(dir: &Path, prefix: &str, out: &mut Vec<String>) -> Result<(), String> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        };
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let name = format!("{}{}", prefix, name);
+            match entry.file_type().map_err(|e| e.to_string())?.is_dir() {
+                true => walk(&entry.path(), &format!("{}/", name), out)?,
+                false if !name.ends_with(".lock") => out.push(name),
+                false => {}
+            }
+        }
+        Ok(())
+    }
+
+    let mut names = Vec::new();
+    walk(&git_dir.join("logs/refs"), "refs/", &mut names)?;
+    names.sort();
+    if exists(git_dir, "HEAD") {
+        names.insert(0, "HEAD".to_string());
+    }
+    Ok(names)
+}
+
+/// The full name of the ref whose reflog a possibly abbreviated `name`
+/// refers to, trying the same places as ref lookup.
+pub fn dwim(git_dir: &Path, name: &str) -> Option<String> {
+    ["{}", "refs/{}", "refs/tags/{}", "refs/heads/{}", "refs/remotes/{}", "refs/remotes/{}/HEAD"]
+        .iter()
+        .map(|rule| rule.replace("{}", name))
+        .find(|full| exists(git_dir, full))
+}
+
+/// Which refs get a reflog started for them, from `core.logAllRefUpdates`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LogAll {
+    Never,
+    /// `HEAD`, branches, remote-tracking branches and notes.
+    Branches,
+    Always,
+}
+
+/// Appends reflog entries for ref updates, all signed by the committer
+/// identity worked out once up front.
+pub struct ReflogWriter<'a> {
+    git_dir: &'a Path,
+    committer: Signature,
+    log_all: LogAll,
+}
+
+impl<'a> ReflogWriter<'a> {
+    pub fn new(git_dir: &'a Path) -> Result<ReflogWriter<'a>, String> {
+        let config = Config::load_all(Some(git_dir))?;
+        let log_all = match config.get("core.logallrefupdates") {
+            Some(value) if value.eq_ignore_ascii_case("always") => LogAll::Always,
+            Some(value) => match config::parse_bool(Some(value)) {
+                Some(false) => LogAll::Never,
+                _ => LogAll::Branches,
+            },
+            // Bare repositories only keep reflogs that already exist.
+            None if config.get_bool("core.bare")? == Some(true) => LogAll::Never,
+            None => LogAll::Branches,
+        };
+        // A ref update should not fail for want of an identity, so fall
+        // back to the login name as git does.
+        let committer = ident::identity(&config, Role::Committer).unwrap_or_else(|_| {
+            let user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+            let time = date::now();
+            Signature::new(user.clone(), format!("{}@localhost", user), time, date::local_offset(time))
+        });
+        Ok(ReflogWriter { git_dir, committer, log_all })
+    }
+
+    /// Whether a change to `name` is logged: always when it already has a
+    /// reflog or `force` is given, otherwise as `core.logAllRefUpdates` says.
+    fn should_log(&self, name: &str, force: bool) -> bool {
+        if force || exists(self.git_dir, name) {
+            return true;
+        }
+        match self.log_all {
+            LogAll::Never => false,
+            LogAll::Always => true,
+            LogAll::Branches => {
+                name == "HEAD" || ["refs/heads/", "refs/remotes/", "refs/notes/"].iter().any(|p| name.starts_with(p))
+            }
+        }
+    }
+
+    /// Records `name` moving from `old` to `new`, `None` standing for the
+    /// null id. Whitespace in `message` is squeezed onto one line.
+    pub fn append(
+        &self,
+        name: &str,
+        old: Option<&ObjectId>,
+        new: Option<&ObjectId>,
+        message: &str,
+        force: bool,
+    ) -> Result<(), String> {
+        if !self.should_log(name, force) {
+            return Ok(());
+        }
+        let Some(null) = old.or(new).map(|id| ObjectId::from_bytes(&vec![0; id.as_bytes().len()])) else {
+            return Ok(());
+        };
+        let entry = ReflogEntry {
+            old: old.cloned().unwrap_or_else(|| null.clone()),
+            new: new.cloned().unwrap_or(null),
+            committer: self.committer.clone(),
+            message: message.split_whitespace().collect::<Vec<_>>().join(" "),
+        };
+
+        let log = path(self.git_dir, name);
+        if let Some(dir) = log.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create reflog directory for {}: {}", name, e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log)
+            .map_err(|e| format!("Could not open reflog for {}: {}", name, e))?;
+        file.write_all(entry.to_line().as_bytes())
+            .map_err(|e| format!("Could not write reflog for {}: {}", name, e))
+    }
+
+    /// Records a change to `name` and, when `HEAD` points at `name`, to
+    /// `HEAD` as well, the way moving the current branch moves `HEAD`. As
+    /// in git, a ref set to the value it already had gets no entry of its
+    /// own, though `HEAD` still does.
+    pub fn append_with_head(
+        &self,
+        name: &str,
+        old: Option<&ObjectId>,
+        new: Option<&ObjectId>,
+        message: &str,
+        force: bool,
+    ) -> Result<(), String> {
+        if old != new {
+            self.append(name, old, new, message, force)?;
+        }
+        if name != "HEAD" && refs::follow_symbolic(self.git_dir, "HEAD")? == name {
+            self.append("HEAD", old, new, message, false)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+use std::fs;
+use std::path::Path;
+
+use crate::lockfile::LockFile;
+use crate::object::{ObjectId, ObjectKind, Tag};
+use crate::odb::ObjectDatabase;
+use crate::reflog::{self, ReflogWriter};
+
+/// What a ref file contains: either an object id or a pointer to another ref.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefValue {
+    Direct(ObjectId),
+    Symbolic(String),
+}
+
+/// Refs are followed through at most this many symbolic links.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// Reads `name` as stored, checking loose refs before `packed-refs`.
+pub fn read_ref(git_dir: &Path, name: &str) -> Result<Option<RefValue>, String> {
+    match fs::read_to_string(git_dir.join(name)) {
+        Ok(contents) => {
+            let contents = contents.trim_end();
+            if let Some(target) = contents.strip_prefix("ref: ") {
+                return Ok(Some(RefValue::Symbolic(target.trim().to_string())));
+            }
+            return ObjectId::from_hex(contents)
+                .map(|id| Some(RefValue::Direct(id)))
+                .map_err(|_| format!("Ref {} is corrupt", name));
+        }
+        // Below a file (refs/heads/a when asking for refs/heads/a/b) there
+        // is no loose ref either.
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory) => {}
+        Err(_) if git_dir.join(name).is_dir() => return Ok(None),
+        Err(e) => return Err(format!("Could not read ref {}: {}", name, e)),
+    }
+
+    Ok(read_packed_refs(git_dir)?
+        .into_iter()
+        .find(|(packed_name, _)| packed_name == name)
+        .map(|(_, id)| RefValue::Direct(id)))
+}
+
+/// Follows symbolic refs from `name` to an object id. Returns `None` for a
+/// ref that does not exist, including an unborn branch HEAD points at.
+pub fn resolve(git_dir: &Path, name: &str) -> Result<Option<ObjectId>, String> {
+    let mut name = name.to_string();
+    for _ in 0..=MAX_SYMREF_DEPTH {
+        match read_ref(git_dir, &name)? {
+            Some(RefValue::Direct(id)) => return Ok(Some(id)),
+            Some(RefValue::Symbolic(target)) => name = target,
+            None => return Ok(None),
+        }
+    }
+    Err(format!("Ref {} has too many levels of symbolic links", name))
+}
+
+/// The ref `name` ultimately points at after following symbolic refs: the
+/// current branch for `HEAD`, or `name` itself for a direct ref.
+pub fn follow_symbolic(git_dir: &Path, name: &str) -> Result<String, String> {
+    let mut name = name.to_string();
+    for _ in 0..=MAX_SYMREF_DEPTH {
+        match read_ref(git_dir, &name)? {
+            Some(RefValue::Symbolic(target)) => name = target,
+            _ => return Ok(name),
+        }
+    }
+    Err(format!("Ref {} has too many levels of symbolic links", name))
+}
+
+/// The places a short ref name is looked for, in order.
+const DWIM_RULES: [&str; 6] = [
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+];
+
+/// Expands a possibly abbreviated ref name the way git does, returning the
+/// full name of the first existing ref together with its value. Names at
+/// the top of the git directory (`HEAD`, `FETCH_HEAD`) are only considered
+/// when written in capitals.
+pub fn dwim_ref(git_dir: &Path, name: &str) -> Result<Option<(String, ObjectId)>, String> {
+    if !is_valid_name(name) {
+        return Ok(None);
+    }
+    for rule in DWIM_RULES {
+        let full = rule.replace("{}", name);
+        let top_level = !full.starts_with("refs/");
+        if top_level && !full.bytes().all(|b| b.is_ascii_uppercase() || b == b'_') {
+            continue;
+        }
+        if let Some(id) = resolve(git_dir, &full)? {
+            return Ok(Some((full, id)));
+        }
+    }
+    Ok(None)
+}
+
+/// The shortest unambiguous form of a full ref name, as `--abbrev-ref`
+/// prints it.
+pub fn shorten_ref(name: &str) -> &str {
+    for prefix in ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"] {
+        if let Some(short) = name.strip_prefix(prefix) {
+            return short;
+        }
+    }
+    name
+}
+
+/// Whether `name` is acceptable as a ref name: no empty or dot-leading
+/// components, no `..` or `@{`, no control characters or any of
+/// ` ~^:?*[\`, and no trailing `.lock` or `.`.
+pub fn is_valid_name(name: &str) -> bool {
+    if name.is_empty() || name == "@" || name.ends_with('.') || name.ends_with('/') {
+        return false;
+    }
+    if name.contains("..") || name.contains("@{") {
+        return false;
+    }
+    if name.bytes().any(|b| b < 0x20 || b == 0x7f || b" ~^:?*[\\".contains(&b)) {
+        return false;
+    }
+    name.split('/')
+        .all(|component| !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/// Every ref under `refs/`, loose refs taking precedence over packed ones,
+/// sorted by name.
+pub fn list_refs(git_dir: &Path) -> Result<Vec<(String, ObjectId)>, String> {
+    let mut refs = read_packed_refs(git_dir)?;
+    let mut loose = Vec::new();
+    collect_loose(git_dir, "refs", &mut loose)?;
+
+    for name in loose {
+        if let Some(id) = resolve(git_dir, &name)? {
+            refs.retain(|(packed_name, _)| *packed_name != name);
+            refs.push((name, id));
+        }
+    }
+    refs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(refs)
+}
+
+fn collect_loose(git_dir: &Path, prefix: &str, out: &mut Vec<String>) -> Result<(), String> {
+    let entries = match fs::read_dir(git_dir.join(prefix)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => format!("{}/{}", prefix, name),
+            Err(_) => continue,
+        };
+        if entry.file_type().map_err(|e| e.to_string())?.is_dir() {
+            collect_loose(git_dir, &name, out)?;
+        } else if !name.ends_with(".lock") {
+            out.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// One `packed-refs` entry, with the object an annotated tag peels to
+/// when the file records it on a following `^<id>` line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub id: ObjectId,
+    pub peeled: Option<ObjectId>,
+}
+
+/// Parses `packed-refs`: `<id> <name>` lines after an optional `#` header,
+/// each perhaps followed by the `^<id>` its tag peels to.
+pub fn read_packed(git_dir: &Path) -> Result<Vec<PackedRef>, String> {
+    let contents = match fs::read_to_string(git_dir.join("packed-refs")) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Could not read packed-refs: {}", e)),
+    };
+
+    let mut refs: Vec<PackedRef> = Vec::new();
+    for line in contents.lines() {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(peeled) = line.strip_prefix('^') {
+            let last = refs
+                .last_mut()
+                .filter(|last| last.peeled.is_none())
+                .ok_or_else(|| format!("unexpected line in packed-refs: {}", line))?;
+            last.peeled = Some(ObjectId::from_hex(peeled)?);
+            continue;
+        }
+        let (id, name) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("Malformed packed-refs line: {}", line))?;
+        refs.push(PackedRef { name: name.to_string(), id: ObjectId::from_hex(id)?, peeled: None });
+    }
+    Ok(refs)
+}
+
+/// The `<name, id>` pairs of `packed-refs`, without peeled values.
+pub fn read_packed_refs(git_dir: &Path) -> Result<Vec<(String, ObjectId)>, String> {
+    Ok(read_packed(git_dir)?.into_iter().map(|packed| (packed.name, packed.id)).collect())
+}
+
+/// Replaces `packed-refs` under `lock` with `refs`, sorted by name, in the
+/// format git writes: a header saying peeled values are complete, then a
+/// `^<id>` line after every annotated tag.
+fn write_packed(mut lock: LockFile, refs: &mut [PackedRef]) -> Result<(), String> {
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut text = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for packed in refs.iter() {
+        text.push_str(&format!("{} {}\n", packed.id, packed.name));
+        if let Some(peeled) = &packed.peeled {
+            text.push_str(&format!("^{}\n", peeled));
+        }
+    }
+    lock.write_all(text.as_bytes())?;
+    lock.commit()
+}
+
+/// What an annotated tag ultimately points at, following tags of tags;
+/// `None` when `id` is not a tag.
+pub fn peel_tag(odb: &ObjectDatabase, id: &ObjectId) -> Result<Option<ObjectId>, String> {
+    let mut current = id.clone();
+    loop {
+        let object = odb.read(&current)?;
+        if object.kind != ObjectKind::Tag {
+            return Ok((current != *id).then_some(current));
+        }
+        current = Tag::parse(&object.data)?.object;
+    }
+}
+
+/// Moves refs into `packed-refs` and deletes their loose files: every
+/// direct ref under `refs/` with `all`, otherwise tags and refs that were
+/// already packed. Symbolic refs stay loose.
+pub fn pack_refs(git_dir: &Path, odb: &ObjectDatabase, all: bool, prune: bool) -> Result<(), String> {
+    let lock = LockFile::acquire(&git_dir.join("packed-refs"))?;
+    let mut packed = read_packed(git_dir)?;
+    let mut loose = Vec::new();
+    collect_loose(git_dir, "refs", &mut loose)?;
+
+    let mut moved = Vec::new();
+    for name in loose {
+        let already_packed = packed.iter().any(|p| p.name == name);
+        if !(all || already_packed || name.starts_with("refs/tags/")) {
+            continue;
+        }
+        let Some(RefValue::Direct(id)) = read_ref(git_dir, &name)? else {
+            continue;
+        };
+        let peeled = peel_tag(odb, &id)?;
+        packed.retain(|p| p.name != name);
+        packed.push(PackedRef { name: name.clone(), id, peeled });
+        moved.push(name);
+    }
+    write_packed(lock, &mut packed)?;
+
+    if prune {
+        for name in moved {
+            // A lock keeps a concurrent update from being lost between
+            // packing the value and removing the file.
+            let ref_lock = LockFile::acquire(&git_dir.join(&name))?;
+            let still_packed = matches!(read_ref(git_dir, &name)?, Some(RefValue::Direct(id))
+                if packed.iter().any(|p| p.name == name && p.id == id));
+            if still_packed {
+                remove_loose(git_dir, &name)?;
+            }
+            drop(ref_lock);
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the loose file for `name` and then the directories that leaves
+/// empty, keeping the `refs/<kind>` directories themselves.
+fn remove_loose(git_dir: &Path, name: &str) -> Result<(), String> {
+    match fs::remove_file(git_dir.join(name)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Could not remove ref {}: {}", name, e)),
+    }
+    let mut dir = Path::new(name).parent();
+    while let Some(current) = dir.filter(|dir| dir.components().count() > 2) {
+        if fs::remove_dir(git_dir.join(current)).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+    Ok(())
+}
+
+/// Points the symbolic ref `name` at `target`. With a `message`, the move
+/// is recorded in the reflog of `name`.
+pub fn write_symbolic(git_dir: &Path, name: &str, target: &str, message: Option<&str>) -> Result<(), String> {
+    let mut lock = LockFile::acquire(&git_dir.join(name)).map_err(|e| format!("cannot lock ref '{}': {}", name, e))?;
+    let old = resolve(git_dir, name)?;
+    lock.write_all(format!("ref: {}\n", target).as_bytes())?;
+    lock.commit()?;
+
+    if let (Some(message), Some(new)) = (message, resolve(git_dir, target)?) {
+        ReflogWriter::new(git_dir)?.append(name, old.as_ref(), Some(&new), message, false)?;
+    }
+    Ok(())
+}
+
+/// Removes the symbolic ref `name` itself, leaving what it points at.
+pub fn delete_symbolic(git_dir: &Path, name: &str) -> Result<(), String> {
+    let lock = LockFile::acquire(&git_dir.join(name)).map_err(|e| format!("cannot lock ref '{}': {}", name, e))?;
+    remove_loose(git_dir, name)?;
+    drop(lock);
+    Ok(())
+}
+
+/// The value a ref must have for an update to go ahead.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// No check.
+    Any,
+    /// The ref must not exist yet.
+    Missing,
+    Value(ObjectId),
+}
+
+/// What an update does to its ref.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Set(ObjectId),
+    Delete,
+    /// Only checks the expected value.
+    Verify,
+}
+
+#[derive(Clone, Debug)]
+pub struct RefUpdate {
+    pub name: String,
+    pub change: Change,
+    pub expected: Expected,
+    /// Whether a symbolic ref is followed and its target updated, rather
+    /// than being overwritten itself.
+    pub deref: bool,
+}
+
+/// A set of ref updates applied all together or not at all. Every ref is
+/// locked and checked against its expected value before any is written,
+/// and every change is then recorded in the reflogs.
+pub struct Transaction<'a> {
+    git_dir: &'a Path,
+    updates: Vec<RefUpdate>,
+    message: String,
+    create_reflogs: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(git_dir: &'a Path) -> Transaction<'a> {
+        Transaction { git_dir, updates: Vec::new(), message: String::new(), create_reflogs: false }
+    }
+
+    /// The reason given in the reflog entries.
+    pub fn set_message(&mut self, message: &str) {
+        self.message = message.to_string();
+    }
+
+    /// Starts reflogs for the updated refs even where git would not by
+    /// default, such as for tags.
+    pub fn create_reflogs(&mut self) {
+        self.create_reflogs = true;
+    }
+
+    pub fn add(&mut self, update: RefUpdate) {
+        self.updates.push(update);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    pub fn commit(self) -> Result<(), String> {
+        let git_dir = self.git_dir;
+        let mut locked = Vec::new();
+        for update in self.updates {
+            let target = match update.deref {
+                true => follow_symbolic(git_dir, &update.name)?,
+                false => update.name.clone(),
+            };
+            if !is_valid_name(&target) {
+                return Err(format!("refusing to update ref with bad name '{}'", target));
+            }
+            if locked.iter().any(|(_, name, ..): &(RefUpdate, String, LockFile, Option<ObjectId>)| *name == target) {
+                return Err(format!("multiple updates for ref '{}' not allowed", target));
+            }
+            if matches!(update.change, Change::Set(_)) {
+                check_name_conflicts(git_dir, &target)
+                    .map_err(|e| format!("cannot lock ref '{}': {}", update.name, e))?;
+            }
+            let lock = LockFile::acquire(&git_dir.join(&target))
+                .map_err(|e| format!("cannot lock ref '{}': {}", update.name, e))?;
+
+            let current = resolve(git_dir, &target)?;
+            match (&update.expected, &current) {
+                (Expected::Any, _) | (Expected::Missing, None) => {}
+                (Expected::Value(expected), Some(current)) if expected == current => {}
+                (Expected::Missing, Some(_)) => {
+                    return Err(format!("cannot lock ref '{}': reference already exists", update.name));
+                }
+                (Expected::Value(_), None) => {
+                    return Err(format!("cannot lock ref '{}': unable to resolve reference '{}'", update.name, target));
+                }
+                (Expected::Value(expected), Some(current)) => {
+                    return Err(format!(
+                        "cannot lock ref '{}': is at {} but expected {}",
+                        update.name, current, expected
+                    ));
+                }
+            }
+            locked.push((update, target, lock, current));
+        }
+
+        let deleted: Vec<&str> = locked
+            .iter()
+            .filter(|(update, ..)| update.change == Change::Delete)
+            .map(|(_, target, ..)| target.as_str())
+            .collect();
+        if !deleted.is_empty() {
+            let packed_lock = LockFile::acquire(&git_dir.join("packed-refs"))?;
+            let mut packed = read_packed(git_dir)?;
+            let before = packed.len();
+            packed.retain(|p| !deleted.contains(&p.name.as_str()));
+            if packed.len() != before {
+                write_packed(packed_lock, &mut packed)?;
+            }
+        }
+
+        let mut changed = Vec::new();
+        for (update, target, mut lock, current) in locked {
+            match &update.change {
+                Change::Set(id) => {
+                    lock.write_all(format!("{}\n", id).as_bytes())?;
+                    lock.commit()?;
+                }
+                Change::Delete => {
+                    remove_loose(git_dir, &target)?;
+                    reflog::delete(git_dir, &target)?;
+                    drop(lock);
+                }
+                Change::Verify => drop(lock),
+            }
+            if let Change::Set(id) = update.change {
+                changed.push((target, current, id));
+            }
+        }
+
+        if !changed.is_empty() {
+            let writer = ReflogWriter::new(git_dir)?;
+            for (target, old, new) in changed {
+                writer.append_with_head(&target, old.as_ref(), Some(&new), &self.message, self.create_reflogs)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A ref cannot be created where a ref of the same name is a directory,
+/// or below an existing ref.
+fn check_name_conflicts(git_dir: &Path, name: &str) -> Result<(), String> {
+    let mut prefix = String::new();
+    let components: Vec<&str> = name.split('/').collect();
+    for component in &components[..components.len() - 1] {
+        prefix.push_str(component);
+        if prefix != "refs" && read_ref(git_dir, &prefix)?.is_some() {
+            return Err(format!("'{}' exists; cannot create '{}'", prefix, name));
+        }
+        prefix.push('/');
+    }
+    let below = format!("{}/", name);
+    let mut loose = Vec::new();
+    if git_dir.join(name).is_dir() {
+        collect_loose(git_dir, name, &mut loose)?;
+    }
+    let packed = read_packed(git_dir)?;
+    match loose.first().cloned().or_else(|| packed.into_iter().map(|p| p.name).find(|n| n.starts_with(&below))) {
+        Some(existing) => Err(format!("'{}' exists; cannot create '{}'", existing, name)),
+        None => Ok(()),
+    }
+}
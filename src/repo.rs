@@ -0,0 +1,133 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::object::HashKind;
+use crate::odb::ObjectDatabase;
+use crate::refs;
+
+/// Locates the `.git` directory the commands operate on.
+pub struct Repository {
+    git_dir: PathBuf,
+    work_dir: PathBuf,
+    hash: HashKind,
+}
+
+impl Repository {
+    /// Finds the repository containing the current directory, honouring
+    /// `GIT_DIR` when it is set.
+    pub fn discover() -> Result<Repository, String> {
+        let cwd = env::current_dir().map_err(|e| e.to_string())?;
+        if let Ok(git_dir) = env::var("GIT_DIR") {
+            return Repository::open(cwd.join(git_dir), cwd);
+        }
+
+        let mut dir = cwd.as_path();
+        loop {
+            let candidate = dir.join(".git");
+            if candidate.join("objects").is_dir() {
+                return Repository::at(dir);
+            }
+            dir = dir.parent()
+                .ok_or("Not a git repository (or any of the parent directories): .git")?;
+        }
+    }
+
+    /// Creates the `.git` skeleton under `work_dir`, leaving an existing
+    /// `HEAD` alone so re-initialising is harmless. Re-initialising with a
+    /// different object format is refused, since the existing objects could
+    /// not be read under the new one.
+    pub fn init(work_dir: &Path, initial_branch: &str, hash: HashKind) -> Result<Repository, String> {
+        let git_dir = work_dir.join(".git");
+        for dir in ["objects", "objects/info", "objects/pack", "refs", "refs/heads", "refs/tags"] {
+            fs::create_dir_all(git_dir.join(dir)).map_err(|e| e.to_string())?;
+        }
+        let config = git_dir.join("config");
+        if !config.exists() {
+            let mut text = format!(
+                "[core]\n\trepositoryformatversion = {}\n\tfilemode = true\n\tbare = false\n",
+                if hash == HashKind::Sha1 { 0 } else { 1 }
+            );
+            if hash != HashKind::Sha1 {
+                text.push_str(&format!("[extensions]\n\tobjectformat = {}\n", hash.name()));
+            }
+            fs::write(&config, text).map_err(|e| e.to_string())?;
+        }
+        if !git_dir.join("HEAD").exists() {
+            let branch = format!("refs/heads/{}", initial_branch);
+            if !refs::is_valid_name(&branch) {
+                return Err(format!("invalid initial branch name: '{}'", initial_branch));
+            }
+            refs::write_symbolic(&git_dir, "HEAD", &branch, None)?;
+        }
+
+        let repo = Repository::at(work_dir)?;
+        if repo.hash != hash {
+            return Err(format!(
+                "Attempt to reinitialize repository with different hash ({} is {})",
+                repo.git_dir.display(),
+                repo.hash.name()
+            ));
+        }
+        Ok(repo)
+    }
+
+    pub fn at(work_dir: &Path) -> Result<Repository, String> {
+        Repository::open(work_dir.join(".git"), work_dir.to_path_buf())
+    }
+
+    fn open(git_dir: PathBuf, work_dir: PathBuf) -> Result<Repository, String> {
+        let config = Config::load(&git_dir.join("config"))?;
+        let hash = Self::object_format(&config)?;
+        Ok(Repository { git_dir, work_dir, hash })
+    }
+
+    /// Reads `extensions.objectFormat`, which git only honours once
+    /// `core.repositoryFormatVersion` is 1.
+    fn object_format(config: &Config) -> Result<HashKind, String> {
+        let version = match config.get("core.repositoryformatversion") {
+            Some(value) => value
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid core.repositoryformatversion '{}'", value))?,
+            None => 0,
+        };
+        if version > 1 {
+            return Err(format!("Expected git repo version <= 1, found {}", version));
+        }
+        match config.get("extensions.objectformat") {
+            Some(name) if version == 1 => HashKind::from_name(name),
+            _ => Ok(HashKind::Sha1),
+        }
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    pub fn work_dir(&self) -> &Path {
+        &self.work_dir
+    }
+
+    pub fn hash_kind(&self) -> HashKind {
+        self.hash
+    }
+
+    /// The staging index, `GIT_INDEX_FILE` if that is set.
+    pub fn index_path(&self) -> PathBuf {
+        match env::var_os("GIT_INDEX_FILE") {
+            Some(path) => PathBuf::from(path),
+            None => self.git_dir.join("index"),
+        }
+    }
+
+    /// The config as commands see it: the system and global files, then
+    /// the repository's own, with includes followed.
+    pub fn config(&self) -> Result<Config, String> {
+        Config::load_all(Some(&self.git_dir))
+    }
+
+    pub fn odb(&self) -> Result<ObjectDatabase, String> {
+        ObjectDatabase::new(self.git_dir.join("objects"), self.hash)
+    }
+}
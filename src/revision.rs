@@ -0,0 +1,492 @@
+use crate::date;
+use crate::index::Index;
+use crate::object::{Commit, ObjectId, ObjectKind, Tag};
+use crate::odb::ObjectDatabase;
+use crate::reachable;
+use crate::reflog;
+use crate::refs::{self, RefValue};
+use crate::repo::Repository;
+use crate::worktree;
+
+/// An object named by a revision expression.
+#[derive(Clone, Debug)]
+pub struct Resolved {
+    pub id: ObjectId,
+    /// The full name of the ref the expression named, when it was a plain
+    /// ref name or `@{upstream}` with no operators applied to it.
+    pub ref_name: Option<String>,
+}
+
+/// A revision argument: a single object, an exclusion or a range.
+#[derive(Clone, Debug)]
+pub enum RevisionArg {
+    Include(Resolved),
+    /// `^A`: leave out everything reachable from A.
+    Exclude(Resolved),
+    /// `A..B`: reachable from B but not from A.
+    Range { from: Resolved, to: Resolved },
+    /// `A...B`: reachable from either side but not from both, which is
+    /// everything above their merge bases.
+    Symmetric { left: Resolved, right: Resolved, bases: Vec<ObjectId> },
+}
+
+/// Resolves revision expressions in one repository:
+///
+/// - `<ref>`, expanded the way git does (`main`, `tags/v1`, `origin`),
+///   `HEAD` or `@`, and full or abbreviated hex ids
+/// - `[<branch>]@{upstream}` (or `@{u}`), the branch's remote-tracking ref
+/// - `<rev>~<n>`, the n-th first-parent ancestor
+/// - `<rev>^<n>`, the n-th parent, where `^0` is the commit itself
+/// - `<rev>^{<type>}` to peel to a type, and `<rev>^{}` to peel tags
+/// - `<rev>:<path>`, the tree or blob at `path` in a tree-ish
+/// - `:<path>` and `:<stage>:<path>`, the blob staged for `path`
+///
+/// Paths are taken from the top of the tree, unless they start with `./`
+/// or `../`, which makes them relative to the current directory.
+pub struct Revisions<'a> {
+    repo: &'a Repository,
+    odb: &'a ObjectDatabase,
+}
+
+impl<'a> Revisions<'a> {
+    pub fn new(repo: &'a Repository, odb: &'a ObjectDatabase) -> Revisions<'a> {
+        Revisions { repo, odb }
+    }
+
+    /// Resolves `spec` to a single object.
+    pub fn resolve(&self, spec: &str) -> Result<ObjectId, String> {
+        self.resolve_full(spec).map(|resolved| resolved.id)
+    }
+
+    /// Resolves `spec` and peels it to `kind`, so that a commit or tag can
+    /// stand in for its tree.
+    pub fn resolve_as(&self, spec: &str, kind: ObjectKind) -> Result<ObjectId, String> {
+        self.peel(&self.resolve(spec)?, kind, spec)
+    }
+
+    /// Resolves `spec`, remembering the ref it named if it was one.
+    pub fn resolve_full(&self, spec: &str) -> Result<Resolved, String> {
+        if let Some(colon) = find_outside_braces(spec, |c| c == ':') {
+            let (rev, path) = (&spec[..colon], &spec[colon + 1..]);
+            if rev.is_empty() {
+                let id = self.lookup_index(path)?;
+                return Ok(Resolved { id, ref_name: None });
+            }
+            let tree = self.resolve_as(rev, ObjectKind::Tree)?;
+            let path = self.tree_path(path)?;
+            let id = self.lookup_path(&tree, &path)?
+                .ok_or_else(|| format!("path '{}' does not exist in '{}'", path, rev))?;
+            return Ok(Resolved { id, ref_name: None });
+        }
+
+        let base_end = find_outside_braces(spec, |c| c == '~' || c == '^').unwrap_or(spec.len());
+        let mut resolved = self.resolve_base(&spec[..base_end], spec)?;
+        let mut rest = &spec[base_end..];
+
+        while let Some(op) = rest.chars().next() {
+            if op != '~' && op != '^' {
+                return Err(unknown_revision(spec));
+            }
+            resolved.ref_name = None;
+            rest = &rest[op.len_utf8()..];
+
+            if op == '^' && rest.starts_with('{') {
+                let close = rest.find('}').ok_or_else(|| unknown_revision(spec))?;
+                let target = &rest[1..close];
+                rest = &rest[close + 1..];
+                let consumed = &spec[..spec.len() - rest.len()];
+                resolved.id = match target {
+                    "" => self.peel_tags(&resolved.id)?,
+                    "object" => resolved.id,
+                    _ => {
+                        let kind = ObjectKind::parse(target.as_bytes()).map_err(|_| unknown_revision(spec))?;
+                        self.peel(&resolved.id, kind, consumed)?
+                    }
+                };
+                continue;
+            }
+
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let n: usize = match digits {
+                0 => 1,
+                _ => rest[..digits].parse().map_err(|_| unknown_revision(spec))?,
+            };
+            rest = &rest[digits..];
+            let consumed = &spec[..spec.len() - rest.len()];
+
+            let mut commit = self.peel(&resolved.id, ObjectKind::Commit, consumed)?;
+            if op == '~' {
+                for _ in 0..n {
+                    commit = self.parents(&commit)?
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| unknown_revision(spec))?;
+                }
+            } else if n > 0 {
+                commit = self.parents(&commit)?
+                    .into_iter()
+                    .nth(n - 1)
+                    .ok_or_else(|| unknown_revision(spec))?;
+            }
+            resolved.id = commit;
+        }
+        Ok(resolved)
+    }
+
+    /// Parses one command-line revision argument, which may be a range.
+    /// An argument containing `..` that does not resolve as a range is
+    /// tried as a single revision, since paths may contain dots.
+    pub fn parse_arg(&self, arg: &str) -> Result<RevisionArg, String> {
+        if let Some(excluded) = arg.strip_prefix('^').filter(|rest| !rest.is_empty()) {
+            return Ok(RevisionArg::Exclude(self.resolve_full(excluded)?));
+        }
+
+        for (separator, symmetric) in [("...", true), ("..", false)] {
+            let Some(pos) = arg.find(separator) else {
+                continue;
+            };
+            let side = |spec: &'_ str| self.resolve_full(if spec.is_empty() { "HEAD" } else { spec });
+            let (left, right) = match (side(&arg[..pos]), side(&arg[pos + separator.len()..])) {
+                (Ok(left), Ok(right)) => (left, right),
+                (Err(e), _) | (_, Err(e)) => {
+                    return self.resolve_full(arg).map(RevisionArg::Include).map_err(|_| e);
+                }
+            };
+            if !symmetric {
+                return Ok(RevisionArg::Range { from: left, to: right });
+            }
+            let bases = reachable::merge_bases(
+                self.odb,
+                &self.peel(&left.id, ObjectKind::Commit, &arg[..pos])?,
+                &self.peel(&right.id, ObjectKind::Commit, &arg[pos + separator.len()..])?,
+            )?;
+            return Ok(RevisionArg::Symmetric { left, right, bases });
+        }
+
+        self.resolve_full(arg).map(RevisionArg::Include)
+    }
+
+    /// Follows tags, and from a commit to its tree, until an object of
+    /// `kind` is reached or the chain ends. `name` is the expression used
+    /// in errors.
+    pub fn peel(&self, id: &ObjectId, kind: ObjectKind, name: &str) -> Result<ObjectId, String> {
+        let mut id = id.clone();
+        loop {
+            let object = self.odb.read(&id)?;
+            if object.kind == kind {
+                return Ok(id);
+            }
+            id = match object.kind {
+                ObjectKind::Tag => Tag::parse(&object.data)?.object,
+                ObjectKind::Commit => Commit::parse(&object.data)?.tree,
+                other => {
+                    return Err(format!(
+                        "{}: expected {} type, but the object dereferences to {} type",
+                        name, kind, other
+                    ));
+                }
+            };
+        }
+    }
+
+    fn peel_tags(&self, id: &ObjectId) -> Result<ObjectId, String> {
+        let mut id = id.clone();
+        loop {
+            let object = self.odb.read(&id)?;
+            if object.kind != ObjectKind::Tag {
+                return Ok(id);
+            }
+            id = Tag::parse(&object.data)?.object;
+        }
+    }
+
+    fn parents(&self, commit: &ObjectId) -> Result<Vec<ObjectId>, String> {
+        Ok(self.odb.read_commit(commit)?.parents)
+    }
+
+    /// A name with no operators: a ref, a hex id or an `@{...}` suffix.
+    fn resolve_base(&self, base: &str, spec: &str) -> Result<Resolved, String> {
+        if let Some(at) = base.find("@{") {
+            let selector = base[at + 2..].strip_suffix('}').ok_or_else(|| unknown_revision(spec))?;
+            return match selector.to_ascii_lowercase().as_str() {
+                "upstream" | "u" => self.upstream(&base[..at]),
+                _ => self.reflog_entry(&base[..at], selector, spec),
+            };
+        }
+
+        let name = if base == "@" { "HEAD" } else { base };
+        if name.len() == self.odb.hash_kind().hex_len() {
+            if let Ok(id) = ObjectId::from_hex(name) {
+                return Ok(Resolved { id, ref_name: None });
+            }
+        }
+        if let Some((full, id)) = refs::dwim_ref(self.repo.git_dir(), name)? {
+            return Ok(Resolved { id, ref_name: Some(full) });
+        }
+        match self.odb.resolve_prefix(name)? {
+            Some(id) => Ok(Resolved { id, ref_name: None }),
+            None => Err(unknown_revision(spec)),
+        }
+    }
+
+    /// Looks `name@{selector}` up in the reflog of `name`, or of the current
+    /// branch when `name` is empty: `@{n}` is the value n moves ago and
+    /// `@{<date>}` the value the ref had at that date.
+    fn reflog_entry(&self, name: &str, selector: &str, spec: &str) -> Result<Resolved, String> {
+        let git_dir = self.repo.git_dir();
+        let (full, shown) = match name {
+            "" => {
+                let full = refs::follow_symbolic(git_dir, "HEAD")?;
+                let shown = refs::shorten_ref(&full).to_string();
+                (full, shown)
+            }
+            _ => (reflog::dwim(git_dir, name).ok_or_else(|| unknown_revision(spec))?, name.to_string()),
+        };
+        let entries = reflog::read(git_dir, &full)?;
+        let oldest = entries.first().ok_or_else(|| unknown_revision(spec))?;
+
+        let id = if selector.bytes().all(|b| b.is_ascii_digit()) {
+            let n: usize = selector.parse().map_err(|_| unknown_revision(spec))?;
+            let entry = entries.iter().rev().nth(n).ok_or_else(|| {
+                format!("log for '{}' only has {} entries", shown, entries.len())
+            })?;
+            entry.new.clone()
+        } else {
+            let time = date::approxidate(selector).map_err(|_| unknown_revision(spec))?;
+            match entries.iter().rev().find(|entry| entry.committer.time <= time) {
+                Some(entry) => entry.new.clone(),
+                // Before the log starts, the best guess is where it started from.
+                None if oldest.old.as_bytes().iter().any(|&b| b != 0) => oldest.old.clone(),
+                None => oldest.new.clone(),
+            }
+        };
+        Ok(Resolved { id, ref_name: None })
+    }
+
+    /// The remote-tracking ref that `branch` (or the current branch, when
+    /// empty) merges from, per its `branch.<name>.remote` and `.merge`
+    /// settings and the remote's fetch refspecs.
+    fn upstream(&self, branch: &str) -> Result<Resolved, String> {
+        let git_dir = self.repo.git_dir();
+        let branch = if branch.is_empty() || branch == "HEAD" {
+            match refs::read_ref(git_dir, "HEAD")? {
+                Some(RefValue::Symbolic(target)) => target
+                    .strip_prefix("refs/heads/")
+                    .ok_or("HEAD does not point to a branch")?
+                    .to_string(),
+                _ => return Err("HEAD does not point to a branch".to_string()),
+            }
+        } else {
+            match refs::dwim_ref(git_dir, branch)? {
+                Some((full, _)) if full.starts_with("refs/heads/") => full["refs/heads/".len()..].to_string(),
+                _ => return Err(format!("no such branch: '{}'", branch)),
+            }
+        };
+
+        let config = self.repo.config()?;
+        let remote = config.get(&format!("branch.{}.remote", branch));
+        let merge = config.get(&format!("branch.{}.merge", branch));
+        let (Some(remote), Some(merge)) = (remote, merge) else {
+            return Err(format!("no upstream configured for branch '{}'", branch));
+        };
+
+        let tracking = if remote == "." {
+            Some(merge.to_string())
+        } else {
+            config
+                .get_all(&format!("remote.{}.fetch", remote))
+                .into_iter()
+                .find_map(|refspec| map_refspec(refspec, merge))
+        };
+        let tracking = tracking.ok_or_else(|| {
+            format!("upstream branch '{}' not stored as a remote-tracking branch", merge)
+        })?;
+        let id = refs::resolve(git_dir, &tracking)?.ok_or_else(|| {
+            format!("upstream branch '{}' of '{}' does not exist", tracking, branch)
+        })?;
+        Ok(Resolved { id, ref_name: Some(tracking) })
+    }
+
+    /// Looks up `:<path>` or `:<stage>:<path>` in the index.
+    fn lookup_index(&self, path: &str) -> Result<ObjectId, String> {
+        let (stage, path) = match path.as_bytes() {
+            [digit @ b'0'..=b'3', b':', ..] => (digit - b'0', &path[2..]),
+            _ => (0, path),
+        };
+        let path = &self.tree_path(path)?;
+        let index = Index::load(&self.repo.index_path(), self.repo.hash_kind())?;
+        if let Ok(i) = index.position(path.as_bytes(), stage) {
+            return Ok(index.entries()[i].id.clone());
+        }
+        if index.contains(path.as_bytes()) {
+            return Err(format!("path '{}' is in the index, but not at stage {}", path, stage));
+        }
+        if self.repo.work_dir().join(path).exists() {
+            return Err(format!("path '{}' exists on disk, but not in the index", path));
+        }
+        Err(format!("path '{}' does not exist (neither on disk nor in the index)", path))
+    }
+
+    /// The path from the top of the tree that `path` names: itself, or for
+    /// `./` and `../` paths, the path from the current directory.
+    fn tree_path(&self, path: &str) -> Result<String, String> {
+        if !path.starts_with("./") && !path.starts_with("../") {
+            return Ok(path.to_string());
+        }
+        let prefix = worktree::cwd_prefix(self.repo)?;
+        let mut components: Vec<&str> = Vec::new();
+        let joined = format!("{}{}", String::from_utf8_lossy(&prefix), path);
+        for component in joined.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop().ok_or_else(|| format!("'{}' is outside repository", path))?;
+                }
+                _ => components.push(component),
+            }
+        }
+        Ok(components.join("/"))
+    }
+
+    /// Walks `path` down from `tree`. An empty path names the tree itself.
+    fn lookup_path(&self, tree: &ObjectId, path: &str) -> Result<Option<ObjectId>, String> {
+        let mut current = tree.clone();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            let tree = match self.odb.read_tree(&current) {
+                Ok(tree) => tree,
+                Err(_) => return Ok(None),
+            };
+            match tree.entries.into_iter().find(|entry| entry.name == component.as_bytes()) {
+                Some(entry) => current = entry.id,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(current))
+    }
+}
+
+fn unknown_revision(spec: &str) -> String {
+    format!("ambiguous argument '{}': unknown revision or path not in the working tree", spec)
+}
+
+/// The byte offset of the first character matching `pred` that is not
+/// inside `{...}`.
+fn find_outside_braces(spec: &str, pred: impl Fn(char) -> bool) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ if depth == 0 && pred(c) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Maps `name` through a fetch refspec such as
+/// `+refs/heads/*:refs/remotes/origin/*`, if its source side matches.
+fn map_refspec(refspec: &str, name: &str) -> Option<String> {
+    let (src, dst) = refspec.trim_start_matches('+').split_once(':')?;
+    match (src.split_once('*'), dst.split_once('*')) {
+        (Some((src_prefix, src_suffix)), Some((dst_prefix, dst_suffix))) => {
+            let middle = name.strip_prefix(src_prefix)?.strip_suffix(src_suffix)?;
+            Some(format!("{}{}{}", dst_prefix, middle, dst_suffix))
+        }
+        (None, None) if src == name => Some(dst.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempRepo;
+
+    /// A merge `m` of `b` and `side`, both children of root `a`, with a tag
+    /// on `m`.
+    struct History {
+        t: TempRepo,
+        a: ObjectId,
+        b: ObjectId,
+        side: ObjectId,
+        m: ObjectId,
+        tag: ObjectId,
+    }
+
+    fn history() -> History {
+        let t = TempRepo::new();
+        let tree = t.tree(&[("README", b"hello\n"), ("src/lib.rs", b"fn main() {}\n")]);
+        let a = t.commit(&tree, &[], 100, "a");
+        let b = t.commit(&tree, &[&a], 200, "b");
+        let side = t.commit(&tree, &[&a], 300, "side");
+        let m = t.commit(&tree, &[&b, &side], 400, "m");
+        t.set_ref("refs/heads/main", &m);
+        let tag_data = format!("object {}\ntype commit\ntag v1\ntagger T <t@example.com> 1 +0000\n\nv1\n", m);
+        let tag = t.odb.write(ObjectKind::Tag, tag_data.as_bytes()).unwrap();
+        t.set_ref("refs/tags/v1", &tag);
+        History { t, a, b, side, m, tag }
+    }
+
+    fn resolve(h: &History, spec: &str) -> Result<ObjectId, String> {
+        Revisions::new(&h.t.repo, &h.t.odb).resolve(spec)
+    }
+
+    #[test]
+    fn tilde_follows_first_parents() {
+        let h = history();
+        assert_eq!(resolve(&h, "HEAD").unwrap(), h.m);
+        assert_eq!(resolve(&h, "main~").unwrap(), h.b);
+        assert_eq!(resolve(&h, "main~1").unwrap(), h.b);
+        assert_eq!(resolve(&h, "main~2").unwrap(), h.a);
+        assert_eq!(resolve(&h, "HEAD~0").unwrap(), h.m);
+        assert!(resolve(&h, "HEAD~3").is_err());
+    }
+
+    #[test]
+    fn caret_picks_a_parent() {
+        let h = history();
+        assert_eq!(resolve(&h, "HEAD^").unwrap(), h.b);
+        assert_eq!(resolve(&h, "HEAD^2").unwrap(), h.side);
+        assert_eq!(resolve(&h, "HEAD^2^").unwrap(), h.a);
+        assert_eq!(resolve(&h, "HEAD^0").unwrap(), h.m);
+        assert_eq!(resolve(&h, "v1^0").unwrap(), h.m);
+        assert_eq!(resolve(&h, "HEAD^2~").unwrap(), h.a);
+        assert!(resolve(&h, "HEAD^3").is_err());
+    }
+
+    #[test]
+    fn braces_peel_to_a_type() {
+        let h = history();
+        let tree = h.t.odb.read_commit(&h.m).unwrap().tree;
+        assert_eq!(resolve(&h, "v1").unwrap(), h.tag);
+        assert_eq!(resolve(&h, "v1^{}").unwrap(), h.m);
+        assert_eq!(resolve(&h, "v1^{commit}").unwrap(), h.m);
+        assert_eq!(resolve(&h, "v1^{tree}").unwrap(), tree);
+        assert_eq!(resolve(&h, "v1^{object}").unwrap(), h.tag);
+        assert_eq!(resolve(&h, "HEAD^{tree}").unwrap(), tree);
+        assert!(resolve(&h, "HEAD^{blob}").is_err());
+        assert!(resolve(&h, "HEAD^{nonsense}").is_err());
+        assert!(resolve(&h, "HEAD^{tree").is_err());
+    }
+
+    #[test]
+    fn colon_looks_up_paths() {
+        let h = history();
+        assert_eq!(resolve(&h, "HEAD:README").unwrap(), h.t.blob(b"hello\n"));
+        assert_eq!(resolve(&h, "v1:src/lib.rs").unwrap(), h.t.blob(b"fn main() {}\n"));
+        assert_eq!(resolve(&h, "HEAD~2:src").unwrap(), resolve(&h, "HEAD:src").unwrap());
+        assert_eq!(resolve(&h, "HEAD:").unwrap(), resolve(&h, "HEAD^{tree}").unwrap());
+        let error = resolve(&h, "HEAD:missing").unwrap_err();
+        assert_eq!(error, "path 'missing' does not exist in 'HEAD'");
+    }
+
+    #[test]
+    fn rejects_bad_suffixes() {
+        let h = history();
+        for spec in ["HEAD~0x", "HEAD^x", "HEAD~1é", "HEADé", "main~1~z", "HEAD@", "HEAD~-1"] {
+            assert!(resolve(&h, spec).is_err(), "{} resolved", spec);
+        }
+        assert_eq!(resolve(&h, "HEAD~0x").unwrap_err(), unknown_revision("HEAD~0x"));
+    }
+}
@@ -0,0 +1,391 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::object::tree::MODE_TREE;
+use crate::object::{Commit, ObjectId, ObjectKind};
+use crate::odb::ObjectDatabase;
+use crate::reachable;
+use crate::worktree::pathspec_matches;
+
+/// A commit waiting in the walk's queue. The newest by committer date
+/// comes out first, and commits with the same date in the order they were
+/// queued.
+struct Queued {
+    time: i64,
+    order: Reverse<u64>,
+    id: ObjectId,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Queued) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Queued) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Queued) -> Ordering {
+        (self.time, self.order).cmp(&(other.time, other.order))
+    }
+}
+
+/// A commit the walk reached, with the parents it went on to.
+struct Walked {
+    id: ObjectId,
+    commit: Commit,
+    parents: Vec<ObjectId>,
+    shown: bool,
+}
+
+/// Walks history from a set of tips, newest commit first by committer
+/// date, leaving out everything reachable from hidden commits.
+///
+/// With paths set, history is simplified the way `git log -- <path>` does
+/// it: a commit that changes none of the paths relative to one of its
+/// parents is skipped, and only that parent is followed.
+pub struct RevWalk<'a> {
+    odb: &'a ObjectDatabase,
+    queue: BinaryHeap<Queued>,
+    queued: u64,
+    seen: HashSet<ObjectId>,
+    hidden: HashSet<ObjectId>,
+    first_parent: bool,
+    topo_order: bool,
+    since: Option<i64>,
+    until: Option<i64>,
+    paths: Vec<Vec<u8>>,
+    /// The whole walk in topological order, worked out on the first call
+    /// to `next` when `topo_order` is set.
+    sorted: Option<VecDeque<Walked>>,
+}
+
+impl<'a> RevWalk<'a> {
+    pub fn new(odb: &'a ObjectDatabase) -> RevWalk<'a> {
+        RevWalk {
+            odb,
+            queue: BinaryHeap::new(),
+            queued: 0,
+            seen: HashSet::new(),
+            hidden: HashSet::new(),
+            first_parent: false,
+            topo_order: false,
+            since: None,
+            until: None,
+            paths: Vec::new(),
+            sorted: None,
+        }
+    }
+
+    /// Starts the walk from the commit `id`.
+    pub fn push(&mut self, id: &ObjectId) -> Result<(), String> {
+        self.enqueue(id)
+    }
+
+    /// Leaves out `id` and everything reachable from it.
+    pub fn hide(&mut self, id: &ObjectId) -> Result<(), String> {
+        if !self.hidden.contains(id) {
+            self.hidden.extend(reachable::ancestors(self.odb, id)?);
+        }
+        Ok(())
+    }
+
+    /// Follows only the first parent of merges.
+    pub fn first_parent(&mut self) {
+        self.first_parent = true;
+    }
+
+    /// Shows no parent before all of its children, keeping lines of history
+    /// together rather than interleaving them by date.
+    pub fn topo_order(&mut self) {
+        self.topo_order = true;
+    }
+
+    /// Stops at commits older than `time`.
+    pub fn set_since(&mut self, time: i64) {
+        self.since = Some(time);
+    }
+
+    /// Skips commits newer than `time`, still walking through them.
+    pub fn set_until(&mut self, time: i64) {
+        self.until = Some(time);
+    }
+
+    /// Limits the walk to commits that change something at or below one of
+    /// `paths`, given relative to the top of the work tree.
+    pub fn set_paths(&mut self, paths: Vec<Vec<u8>>) {
+        self.paths = paths;
+    }
+
+    /// The next commit to show.
+    fn next_shown(&mut self) -> Result<Option<(ObjectId, Commit)>, String> {
+        if self.topo_order && self.sorted.is_none() {
+            let mut walked = Vec::new();
+            while let Some(commit) = self.step()? {
+                walked.push(commit);
+            }
+            self.sorted = Some(Self::sort_topologically(walked));
+        }
+        loop {
+            let walked = match &mut self.sorted {
+                Some(sorted) => sorted.pop_front(),
+                None => self.step()?,
+            };
+            match walked {
+                Some(walked) if walked.shown => return Ok(Some((walked.id, walked.commit))),
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn enqueue(&mut self, id: &ObjectId) -> Result<(), String> {
+        if !self.seen.insert(id.clone()) {
+            return Ok(());
+        }
+        let time = self.odb.read_commit(id)?.committer.time;
+        self.queued += 1;
+        self.queue.push(Queued { time, order: Reverse(self.queued), id: id.clone() });
+        Ok(())
+    }
+
+    /// Takes the newest queued commit and queues the parents it leads to.
+    /// Commits that are reached but not shown come back with `shown` unset.
+    fn step(&mut self) -> Result<Option<Walked>, String> {
+        while let Some(Queued { id, .. }) = self.queue.pop() {
+            if self.hidden.contains(&id) {
+                continue;
+            }
+            let commit = self.odb.read_commit(&id)?;
+            // Everything past a commit older than --since is older still,
+            // near enough, so the walk goes no further down this line.
+            if self.since.is_some_and(|since| commit.committer.time < since) {
+                continue;
+            }
+            let (parents, changed) = self.simplify(&commit)?;
+            for parent in &parents {
+                self.enqueue(parent)?;
+            }
+            let shown = changed && !self.until.is_some_and(|until| commit.committer.time > until);
+            return Ok(Some(Walked { id, commit, parents, shown }));
+        }
+        Ok(None)
+    }
+
+    /// The parents to go on to from `commit`, and whether it changes the
+    /// limiting paths. A commit that leaves them as one of its parents had
+    /// them continues through that parent alone.
+    fn simplify(&self, commit: &Commit) -> Result<(Vec<ObjectId>, bool), String> {
+        let parents = match self.first_parent {
+            true => commit.parents.iter().take(1).cloned().collect(),
+            false => commit.parents.clone(),
+        };
+        if self.paths.is_empty() {
+            return Ok((parents, true));
+        }
+        if parents.is_empty() {
+            return Ok((parents, self.trees_differ(None, Some(&commit.tree), b"")?));
+        }
+        for parent in &parents {
+            let parent_tree = self.odb.read_commit(parent)?.tree;
+            if !self.trees_differ(Some(&parent_tree), Some(&commit.tree), b"")? {
+                return Ok((vec![parent.clone()], false));
+            }
+        }
+        Ok((parents, true))
+    }
+
+    /// Whether the trees `a` and `b`, found at `prefix` (empty or ending in
+    /// `/`), differ anywhere under the limiting paths. Subtrees no path
+    /// reaches into are not read.
+    fn trees_differ(&self, a: Option<&ObjectId>, b: Option<&ObjectId>, prefix: &[u8]) -> Result<bool, String> {
+        if a == b {
+            return Ok(false);
+        }
+        // The empty tree need not be stored, since its id is well known.
+        let empty = self.odb.hash(ObjectKind::Tree, b"");
+        let read = |id: Option<&ObjectId>| -> Result<Vec<_>, String> {
+            Ok(match id {
+                Some(id) if *id != empty => self.odb.read_tree(id)?.entries,
+                _ => Vec::new(),
+            })
+        };
+        let mut entries: HashMap<Vec<u8>, [Option<(u32, ObjectId)>; 2]> = HashMap::new();
+        for (side, tree) in [read(a)?, read(b)?].into_iter().enumerate() {
+            for entry in tree {
+                entries.entry(entry.name).or_default()[side] = Some((entry.mode, entry.id));
+            }
+        }
+
+        for (name, [old, new]) in entries {
+            if old == new {
+                continue;
+            }
+            let mut path = prefix.to_vec();
+            path.extend_from_slice(&name);
+            if self.paths.iter().any(|spec| pathspec_matches(spec, &path)) {
+                return Ok(true);
+            }
+            // A path named below this one means looking inside whichever
+            // sides are trees.
+            path.push(b'/');
+            if !self.paths.iter().any(|spec| spec.starts_with(&path)) {
+                continue;
+            }
+            let subtree = |side: &Option<(u32, ObjectId)>| {
+                side.as_ref().filter(|(mode, _)| *mode == MODE_TREE).map(|(_, id)| id.clone())
+            };
+            if self.trees_differ(subtree(&old).as_ref(), subtree(&new).as_ref(), &path)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Orders the walked commits so that each comes before its parents,
+    /// starting from the newest tip and following each line of history to
+    /// its end before the next, last parent first.
+    fn sort_topologically(walked: Vec<Walked>) -> VecDeque<Walked> {
+        let index: HashMap<ObjectId, usize> = walked.iter().enumerate().map(|(i, w)| (w.id.clone(), i)).collect();
+        let mut children = vec![0usize; walked.len()];
+        for commit in &walked {
+            for parent in &commit.parents {
+                if let Some(&i) = index.get(parent) {
+                    children[i] += 1;
+                }
+            }
+        }
+
+        let mut stack: Vec<usize> = (0..walked.len()).filter(|&i| children[i] == 0).rev().collect();
+        let mut order = Vec::with_capacity(walked.len());
+        while let Some(i) = stack.pop() {
+            order.push(i);
+            for parent in &walked[i].parents {
+                if let Some(&p) = index.get(parent) {
+                    children[p] -= 1;
+                    if children[p] == 0 {
+                        stack.push(p);
+                    }
+                }
+            }
+        }
+
+        let mut slots: Vec<Option<Walked>> = walked.into_iter().map(Some).collect();
+        order.into_iter().filter_map(|i| slots[i].take()).collect()
+    }
+}
+
+impl Iterator for RevWalk<'_> {
+    type Item = Result<(ObjectId, Commit), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_shown().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempRepo;
+
+    /// `e` on top of a merge `m` of `b` and the side line `c`-`d`, all
+    /// starting from `a`. Committer dates interleave the two lines.
+    struct History {
+        t: TempRepo,
+        ids: Vec<(ObjectId, &'static str)>,
+    }
+
+    impl History {
+        fn new() -> History {
+            let t = TempRepo::new();
+            let mut ids: Vec<(ObjectId, &'static str)> = Vec::new();
+            let mut commit = |name: &'static str, time: i64, parents: &[&str], readme: &[u8], lib: &[u8]| {
+                let tree = t.tree(&[("README", readme), ("src/lib.rs", lib)]);
+                let parents: Vec<ObjectId> =
+                    parents.iter().map(|p| ids.iter().find(|(_, n)| n == p).unwrap().0.clone()).collect();
+                let id = t.commit(&tree, &parents.iter().collect::<Vec<_>>(), time, name);
+                ids.push((id, name));
+            };
+            commit("a", 100, &[], b"1", b"1");
+            commit("b", 200, &["a"], b"2", b"1");
+            commit("c", 150, &["a"], b"1", b"2");
+            commit("d", 250, &["c"], b"1", b"3");
+            commit("m", 300, &["b", "d"], b"2", b"3");
+            commit("e", 400, &["m"], b"3", b"3");
+            History { t, ids }
+        }
+
+        fn id(&self, name: &str) -> ObjectId {
+            self.ids.iter().find(|(_, n)| *n == name).unwrap().0.clone()
+        }
+
+        fn walk(&self, setup: impl FnOnce(&mut RevWalk)) -> Vec<String> {
+            let mut walk = RevWalk::new(&self.t.odb);
+            walk.push(&self.id("e")).unwrap();
+            setup(&mut walk);
+            walk.map(|item| String::from_utf8(item.unwrap().1.message).unwrap()).collect()
+        }
+    }
+
+    #[test]
+    fn walks_newest_first() {
+        let h = History::new();
+        assert_eq!(h.walk(|_| {}), ["e", "m", "d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn topo_order_keeps_lines_together() {
+        let h = History::new();
+        assert_eq!(h.walk(|walk| walk.topo_order()), ["e", "m", "d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn first_parent_skips_merged_lines() {
+        let h = History::new();
+        assert_eq!(h.walk(|walk| walk.first_parent()), ["e", "m", "b", "a"]);
+        assert_eq!(
+            h.walk(|walk| {
+                walk.first_parent();
+                walk.topo_order();
+            }),
+            ["e", "m", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn hidden_commits_and_their_ancestors_are_left_out() {
+        let h = History::new();
+        assert_eq!(h.walk(|walk| walk.hide(&h.id("b")).unwrap()), ["e", "m", "d", "c"]);
+    }
+
+    #[test]
+    fn dates_bound_the_walk() {
+        let h = History::new();
+        assert_eq!(h.walk(|walk| walk.set_since(200)), ["e", "m", "d", "b"]);
+        assert_eq!(h.walk(|walk| walk.set_until(250)), ["d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn paths_simplify_history() {
+        let h = History::new();
+        // The merge takes src from `d`, so only the side line touched it.
+        assert_eq!(h.walk(|walk| walk.set_paths(vec![b"src".to_vec()])), ["d", "c", "a"]);
+        assert_eq!(h.walk(|walk| walk.set_paths(vec![b"src/lib.rs".to_vec()])), ["d", "c", "a"]);
+        // README in the merge matches `b`, so the side line is never walked.
+        assert_eq!(h.walk(|walk| walk.set_paths(vec![b"README".to_vec()])), ["e", "b", "a"]);
+        assert_eq!(
+            h.walk(|walk| {
+                walk.first_parent();
+                walk.set_paths(vec![b"src".to_vec()]);
+            }),
+            ["m", "a"]
+        );
+        assert_eq!(h.walk(|walk| walk.set_paths(vec![b"missing".to_vec()])), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,78 @@
+//! A scratch repository for unit tests, removed again when dropped.
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::object::tree::{MODE_BLOB, MODE_TREE};
+use crate::object::{Commit, HashKind, Object, ObjectId, ObjectKind, Signature, Tree, TreeEntry};
+use crate::odb::ObjectDatabase;
+use crate::repo::Repository;
+
+/// A file to put in a tree: its path and contents.
+type File<'a> = (&'a str, &'a [u8]);
+
+pub struct TempRepo {
+    pub repo: Repository,
+    pub odb: ObjectDatabase,
+}
+
+impl TempRepo {
+    pub fn new() -> TempRepo {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "codecrafters-git-unit-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let repo = Repository::init(&dir, "main", HashKind::Sha1).unwrap();
+        let odb = repo.odb().unwrap();
+        TempRepo { repo, odb }
+    }
+
+    pub fn blob(&self, data: &[u8]) -> ObjectId {
+        self.odb.write(ObjectKind::Blob, data).unwrap()
+    }
+
+    /// Writes a tree from `path` and contents pairs, where paths may name
+    /// files in subdirectories.
+    pub fn tree(&self, files: &[File]) -> ObjectId {
+        let mut entries: Vec<TreeEntry> = Vec::new();
+        let mut dirs: Vec<(&str, Vec<File>)> = Vec::new();
+        for &(path, data) in files {
+            match path.split_once('/') {
+                Some((dir, rest)) => match dirs.iter_mut().find(|(name, _)| *name == dir) {
+                    Some((_, files)) => files.push((rest, data)),
+                    None => dirs.push((dir, vec![(rest, data)])),
+                },
+                None => entries.push(TreeEntry::new(MODE_BLOB, path.as_bytes().to_vec(), self.blob(data))),
+            }
+        }
+        for (dir, files) in dirs {
+            entries.push(TreeEntry::new(MODE_TREE, dir.as_bytes().to_vec(), self.tree(&files)));
+        }
+        entries.sort_by(|a, b| crate::object::tree::compare_names(&a.name, a.is_tree(), &b.name, b.is_tree()));
+        self.odb.write_object(&Object::Tree(Tree { entries })).unwrap()
+    }
+
+    /// Commits `tree` at `time`, signed by a fixed identity.
+    pub fn commit(&self, tree: &ObjectId, parents: &[&ObjectId], time: i64, message: &str) -> ObjectId {
+        let signature = Signature::new("A U Thor".to_string(), "author@example.com".to_string(), time, 0);
+        let parents = parents.iter().map(|&id| id.clone()).collect();
+        let commit = Commit::new(tree.clone(), parents, signature.clone(), signature, message.as_bytes().to_vec());
+        self.odb.write_object(&Object::Commit(commit)).unwrap()
+    }
+
+    pub fn set_ref(&self, name: &str, id: &ObjectId) {
+        let path = self.repo.git_dir().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("{}\n", id)).unwrap();
+    }
+}
+
+impl Drop for TempRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.repo.work_dir());
+    }
+}
@@ -0,0 +1,212 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::index::{Index, IndexEntry};
+use crate::object::tree::{MODE_BLOB, MODE_EXECUTABLE, MODE_SYMLINK};
+use crate::object::{ObjectId, ObjectKind};
+use crate::odb::ObjectDatabase;
+use crate::repo::Repository;
+
+/// The tree entry mode git records for a file with this metadata, which
+/// must come from `symlink_metadata` so that links are seen as links.
+pub fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        MODE_SYMLINK
+    } else if is_executable(metadata) {
+        MODE_EXECUTABLE
+    } else {
+        MODE_BLOB
+    }
+}
+
+/// Hashes the file at `path` as a blob, storing it when `write` is set.
+/// A symlink's blob is its target; file contents are streamed rather than
+/// read into memory.
+pub fn blob_id(odb: &ObjectDatabase, path: &Path, metadata: &fs::Metadata, write: bool) -> Result<ObjectId, String> {
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path)
+            .map_err(|e| format!("Could not read link {}: {}", path.display(), e))?;
+        let target = name_bytes(target.as_os_str())?;
+        return Ok(match write {
+            true => odb.write(ObjectKind::Blob, &target)?,
+            false => odb.hash(ObjectKind::Blob, &target),
+        });
+    }
+    let mut file = fs::File::open(path)
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    match write {
+        true => odb.write_stream(ObjectKind::Blob, metadata.len(), &mut file),
+        false => odb.hash_kind().hash_stream(ObjectKind::Blob, metadata.len(), &mut file, &mut io::sink()),
+    }
+}
+
+/// Whether the work tree file at `path` differs from what `entry` stages,
+/// reading it only when its stat data cannot settle the question.
+pub fn is_modified(
+    odb: &ObjectDatabase,
+    index: &Index,
+    entry: &IndexEntry,
+    path: &Path,
+    metadata: &fs::Metadata,
+) -> Result<bool, String> {
+    if entry.stat_matches(metadata) && !index.is_racy(entry) {
+        return Ok(false);
+    }
+    Ok(file_mode(metadata) != entry.mode || blob_id(odb, path, metadata, false)? != entry.id)
+}
+
+/// Every file and symlink below `dir` (a repository-relative path, empty
+/// for the top), as repository-relative paths. `.git` directories are
+/// skipped, and so are nested repositories.
+pub fn list_files(work_dir: &Path, dir: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut files = Vec::new();
+    collect_files(work_dir, dir, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn collect_files(work_dir: &Path, dir: &[u8], out: &mut Vec<Vec<u8>>) -> Result<(), String> {
+    let full = work_dir.join(bytes_path(dir)?);
+    for entry in fs::read_dir(&full).map_err(|e| format!("Could not read {}: {}", full.display(), e))? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let mut path = dir.to_vec();
+        if !path.is_empty() {
+            path.push(b'/');
+        }
+        path.extend_from_slice(&name_bytes(&entry.file_name())?);
+
+        let file_type = entry.file_type().map_err(|e| e.to_string())?;
+        if file_type.is_dir() {
+            if !entry.path().join(".git").exists() {
+                collect_files(work_dir, &path, out)?;
+            }
+        } else if file_type.is_file() || file_type.is_symlink() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` is named by `spec`: the path itself or anything below it.
+/// The empty spec, the top of the work tree, names everything.
+pub fn pathspec_matches(spec: &[u8], path: &[u8]) -> bool {
+    spec.is_empty()
+        || (path.starts_with(spec) && (path.len() == spec.len() || path[spec.len()] == b'/'))
+}
+
+/// The repository-relative form of a path given on the command line, which
+/// is relative to the current directory.
+pub fn repo_path(repo: &Repository, arg: &str) -> Result<Vec<u8>, String> {
+    let cwd = env::current_dir().map_err(|e| e.to_string())?;
+    path_below(repo, &cwd, arg)
+}
+
+/// The repository-relative form of a path given relative to the top of the
+/// work tree, as commands taking `--full-tree` read their arguments.
+pub fn top_path(repo: &Repository, arg: &str) -> Result<Vec<u8>, String> {
+    path_below(repo, repo.work_dir(), arg)
+}
+
+fn path_below(repo: &Repository, base: &Path, arg: &str) -> Result<Vec<u8>, String> {
+    let full = normalize(&base.join(arg));
+    let relative = full
+        .strip_prefix(normalize(repo.work_dir()))
+        .map_err(|_| format!("{}: '{}' is outside repository at '{}'", arg, arg, repo.work_dir().display()))?;
+    name_bytes(relative.as_os_str())
+}
+
+/// The current directory relative to the top of the work tree, with a
+/// trailing slash unless it is the top itself.
+pub fn cwd_prefix(repo: &Repository) -> Result<Vec<u8>, String> {
+    let mut prefix = repo_path(repo, ".")?;
+    if !prefix.is_empty() {
+        prefix.push(b'/');
+    }
+    Ok(prefix)
+}
+
+/// Writes repository-relative `path` relative to `prefix` (as returned by
+/// `cwd_prefix`), climbing out with `../` where needed. A directory the
+/// prefix lies in comes out as `./` or a run of `../`.
+pub fn relative_to(path: &[u8], prefix: &[u8]) -> Vec<u8> {
+    let mut common = 0;
+    for (i, (a, b)) in path.iter().zip(prefix).enumerate() {
+        if a != b {
+            break;
+        }
+        if *a == b'/' {
+            common = i + 1;
+        }
+    }
+    if prefix.len() > path.len() && prefix.starts_with(path) && prefix[path.len()] == b'/' {
+        common = path.len() + 1;
+    }
+    let mut out = Vec::new();
+    for _ in prefix[common..].iter().filter(|&&b| b == b'/') {
+        out.extend_from_slice(b"../");
+    }
+    out.extend_from_slice(path.get(common..).unwrap_or_default());
+    if out.is_empty() {
+        out.extend_from_slice(b"./");
+    }
+    out
+}
+
+/// Resolves `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+#[cfg(unix)]
+pub fn name_bytes(name: &OsStr) -> Result<Vec<u8>, String> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(name.as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+pub fn name_bytes(name: &OsStr) -> Result<Vec<u8>, String> {
+    name.to_str()
+        .map(|name| name.replace('\\', "/").into_bytes())
+        .ok_or_else(|| format!("Invalid file name {}", name.to_string_lossy()))
+}
+
+#[cfg(unix)]
+pub fn bytes_path(path: &[u8]) -> Result<PathBuf, String> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(OsStr::from_bytes(path)))
+}
+
+#[cfg(not(unix))]
+pub fn bytes_path(path: &[u8]) -> Result<PathBuf, String> {
+    std::str::from_utf8(path)
+        .map(PathBuf::from)
+        .map_err(|_| format!("Invalid path {}", String::from_utf8_lossy(path)))
+}
+
+/// git records a file as executable if its owner may execute it.
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
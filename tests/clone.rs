@@ -0,0 +1,189 @@
+//! Clones a fixture repository from a smart-HTTP server running on a
+//! loopback socket in this process. The server answers the two requests a
+//! v0 clone makes: the ref advertisement and a single upload-pack POST.
+
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+
+use codecrafters_git::object::ObjectId;
+use codecrafters_git::pack::deltify::{self, DeltaOptions, PackObject};
+use codecrafters_git::protocol::pktline::{self, Packet, PktReader};
+use codecrafters_git::reachable;
+use codecrafters_git::refs;
+use codecrafters_git::repo::Repository;
+
+use common::{git, TempDir};
+
+/// Two commits on `main`, a second branch and a lightweight tag, with a
+/// file that changes between the commits so the pack holds a delta.
+fn make_fixture(dir: &Path) {
+    git(dir, &["init"]);
+    let body: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+    fs::write(dir.join("README"), &body).unwrap();
+    fs::create_dir(dir.join("src")).unwrap();
+    fs::write(dir.join("src/lib.rs"), "pub fn answer() -> u32 { 42 }\n").unwrap();
+    git(dir, &["add", "README", "src/lib.rs"]);
+    git(dir, &["commit", "-q", "-m", "first"]);
+    git(dir, &["update-ref", "refs/heads/topic", "HEAD"]);
+    git(dir, &["update-ref", "refs/tags/v1", "HEAD"]);
+
+    fs::write(dir.join("README"), format!("{}one more line\n", body)).unwrap();
+    git(dir, &["add", "README"]);
+    git(dir, &["commit", "-q", "-m", "second"]);
+}
+
+/// The advertisement `GET info/refs?service=git-upload-pack` returns.
+fn advertisement(repo: &Repository) -> Vec<u8> {
+    let mut body = Vec::new();
+    pktline::write_packet(&mut body, b"# service=git-upload-pack\n");
+    pktline::write_flush(&mut body);
+
+    let head = refs::resolve(repo.git_dir(), "HEAD").unwrap().unwrap();
+    let head_target = refs::follow_symbolic(repo.git_dir(), "HEAD").unwrap();
+    let capabilities = format!("side-band-64k ofs-delta symref=HEAD:{}", head_target);
+    pktline::write_packet(&mut body, format!("{} HEAD\0{}\n", head, capabilities).as_bytes());
+    for (name, id) in refs::list_refs(repo.git_dir()).unwrap() {
+        pktline::write_packet(&mut body, format!("{} {}\n", id, name).as_bytes());
+    }
+    pktline::write_flush(&mut body);
+    body
+}
+
+/// Answers an upload-pack request with everything reachable from its wants,
+/// sent over side-band channel 1 after a progress message on channel 2.
+fn upload_pack(repo: &Repository, request: &[u8]) -> Vec<u8> {
+    let mut wants = Vec::new();
+    let mut reader = PktReader::new(request);
+    while let Some(packet) = reader.next_packet().unwrap() {
+        if let Packet::Data(line) = packet {
+            let line = String::from_utf8_lossy(pktline::trim_newline(line)).into_owned();
+            if let Some(rest) = line.strip_prefix("want ") {
+                wants.push(ObjectId::from_hex(&rest[..40]).unwrap());
+            }
+        }
+    }
+
+    let odb = repo.odb().unwrap();
+    let objects: Vec<PackObject> = reachable::reachable_objects(&odb, &wants)
+        .unwrap()
+        .into_iter()
+        .map(|object| {
+            let raw = odb.read(&object.id).unwrap();
+            PackObject {
+                id: object.id,
+                kind: raw.kind,
+                name_hash: object.name.as_deref().map_or(0, deltify::name_hash),
+                data: raw.data,
+            }
+        })
+        .collect();
+    let (pack, _, _) = deltify::write_pack(&objects, DeltaOptions::default(), odb.hash_kind());
+
+    let mut body = Vec::new();
+    pktline::write_packet(&mut body, b"NAK\n");
+    pktline::write_packet(&mut body, b"\x02Counting objects: done.\n");
+    // Small chunks, so the pack has to be put back together from several
+    // side-band packets.
+    for chunk in pack.chunks(100) {
+        let mut payload = vec![1u8];
+        payload.extend_from_slice(chunk);
+        pktline::write_packet(&mut body, &payload);
+    }
+    pktline::write_flush(&mut body);
+    body
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len()
+    );
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(body).unwrap();
+}
+
+fn serve(mut stream: TcpStream, repo: &Repository) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/fixture.git/info/refs?service=git-upload-pack")) => respond(
+            &mut stream,
+            "200 OK",
+            "application/x-git-upload-pack-advertisement",
+            &advertisement(repo),
+        ),
+        (Some("POST"), Some("/fixture.git/git-upload-pack")) => respond(
+            &mut stream,
+            "200 OK",
+            "application/x-git-upload-pack-result",
+            &upload_pack(repo, &body),
+        ),
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+    }
+}
+
+#[test]
+fn clone_over_smart_http() {
+    let tmp = TempDir::new("clone");
+    let source = tmp.0.join("source");
+    fs::create_dir(&source).unwrap();
+    make_fixture(&source);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server_dir = source.clone();
+    thread::spawn(move || {
+        let repo = Repository::at(&server_dir).unwrap();
+        for stream in listener.incoming() {
+            serve(stream.unwrap(), &repo);
+        }
+    });
+
+    let url = format!("http://127.0.0.1:{}/fixture.git", port);
+    git(&tmp.0, &["clone", &url]);
+    let clone = tmp.0.join("fixture");
+
+    let source_ref = |name: &str| git(&source, &["rev-parse", name]);
+    let clone_ref = |name: &str| git(&clone, &["rev-parse", name]);
+    assert_eq!(clone_ref("HEAD"), source_ref("HEAD"));
+    assert_eq!(git(&clone, &["symbolic-ref", "HEAD"]), "refs/heads/main\n");
+    assert_eq!(clone_ref("refs/remotes/origin/main"), source_ref("main"));
+    assert_eq!(clone_ref("refs/remotes/origin/topic"), source_ref("topic"));
+    assert_eq!(clone_ref("refs/tags/v1"), source_ref("v1"));
+    assert_eq!(git(&clone, &["symbolic-ref", "refs/remotes/origin/HEAD"]), "refs/remotes/origin/main\n");
+
+    // Every object came across intact, and the work tree matches HEAD.
+    git(&clone, &["fsck"]);
+    assert_eq!(git(&clone, &["cat-file", "-p", "HEAD~1:README"]), git(&source, &["cat-file", "-p", "HEAD~1:README"]));
+    for spec in ["HEAD", "HEAD^{tree}", "HEAD:README", "HEAD~1:README"] {
+        for option in ["-t", "-s"] {
+            assert_eq!(git(&clone, &["cat-file", option, spec]), git(&source, &["cat-file", option, spec]));
+        }
+    }
+    assert_eq!(fs::read(clone.join("README")).unwrap(), fs::read(source.join("README")).unwrap());
+    assert_eq!(fs::read(clone.join("src/lib.rs")).unwrap(), fs::read(source.join("src/lib.rs")).unwrap());
+    assert_eq!(git(&clone, &["ls-files"]), "README\nsrc/lib.rs\n");
+}
@@ -0,0 +1,72 @@
+//! What fsck counts as reachable besides the refs: reflog entries, staged
+//! blobs and the trees cached in the index.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use codecrafters_git::object::{HashKind, ObjectKind};
+use codecrafters_git::repo::Repository;
+
+use common::{git, TempDir};
+
+fn dangling(dir: &Path) -> Vec<String> {
+    git(dir, &["fsck"]).lines().filter(|line| line.starts_with("dangling ")).map(str::to_string).collect()
+}
+
+#[test]
+fn amended_commit_is_kept_alive_by_the_reflog() {
+    let tmp = TempDir::new("fsck-reflog");
+    git(&tmp.0, &["init"]);
+    fs::write(tmp.0.join("README"), "hello\n").unwrap();
+    git(&tmp.0, &["add", "README"]);
+    git(&tmp.0, &["commit", "-q", "-m", "first"]);
+    let first = git(&tmp.0, &["rev-parse", "HEAD"]);
+    git(&tmp.0, &["commit", "-q", "--amend", "-m", "amended"]);
+    assert_ne!(git(&tmp.0, &["rev-parse", "HEAD"]), first);
+
+    assert_eq!(dangling(&tmp.0), Vec::<String>::new());
+
+    // Without the reflogs nothing points at the replaced commit.
+    fs::remove_dir_all(tmp.0.join(".git/logs")).unwrap();
+    assert_eq!(dangling(&tmp.0), vec![format!("dangling commit {}", first.trim())]);
+}
+
+#[test]
+fn staged_blob_is_not_dangling() {
+    let tmp = TempDir::new("fsck-index");
+    git(&tmp.0, &["init"]);
+    fs::write(tmp.0.join("README"), "hello\n").unwrap();
+    git(&tmp.0, &["add", "README"]);
+    let blob = git(&tmp.0, &["hash-object", "README"]);
+
+    assert_eq!(dangling(&tmp.0), Vec::<String>::new());
+
+    fs::remove_file(tmp.0.join(".git/index")).unwrap();
+    assert_eq!(dangling(&tmp.0), vec![format!("dangling blob {}", blob.trim())]);
+}
+
+#[test]
+fn cached_tree_is_not_dangling() {
+    let tmp = TempDir::new("fsck-cache-tree");
+    git(&tmp.0, &["init"]);
+    let repo = Repository::at(&tmp.0).unwrap();
+    let tree = repo.odb().unwrap().write(ObjectKind::Tree, b"").unwrap();
+
+    // An index with no entries and a `TREE` extension caching the empty
+    // tree for the top directory.
+    let mut extension = b"\x000 0\n".to_vec();
+    extension.extend_from_slice(tree.as_bytes());
+    let mut index = b"DIRC\0\0\0\x02\0\0\0\0TREE".to_vec();
+    index.extend_from_slice(&(extension.len() as u32).to_be_bytes());
+    index.extend_from_slice(&extension);
+    let checksum = HashKind::Sha1.digest(&index);
+    index.extend_from_slice(&checksum);
+    fs::write(tmp.0.join(".git/index"), &index).unwrap();
+
+    assert_eq!(dangling(&tmp.0), Vec::<String>::new());
+
+    fs::remove_file(tmp.0.join(".git/index")).unwrap();
+    assert_eq!(dangling(&tmp.0), vec![format!("dangling tree {}", tree)]);
+}
@@ -0,0 +1,42 @@
+//! ls-tree run from a subdirectory, where paths are read and printed
+//! relative to it unless --full-name or --full-tree says otherwise.
+
+mod common;
+
+use std::fs;
+
+use common::{git, TempDir};
+
+#[test]
+fn paths_are_relative_to_the_current_directory() {
+    let tmp = TempDir::new("ls-tree");
+    git(&tmp.0, &["init"]);
+    fs::create_dir_all(tmp.0.join("sub/deep")).unwrap();
+    fs::write(tmp.0.join("a"), "a\n").unwrap();
+    fs::write(tmp.0.join("sub/b"), "b\n").unwrap();
+    fs::write(tmp.0.join("sub/deep/c"), "c\n").unwrap();
+    git(&tmp.0, &["add", "a", "sub/b", "sub/deep/c"]);
+    git(&tmp.0, &["commit", "-q", "-m", "first"]);
+    let sub = tmp.0.join("sub");
+    let names = |args: &[&str]| {
+        let mut full = vec!["ls-tree", "--name-only", "HEAD"];
+        full.extend_from_slice(args);
+        git(&sub, &full)
+    };
+
+    assert_eq!(names(&[]), "b\ndeep\n");
+    assert_eq!(names(&["-r"]), "b\ndeep/c\n");
+    assert_eq!(names(&[".."]), "../a\n./\n");
+    assert_eq!(names(&["-d", ".."]), "./\n");
+    assert_eq!(names(&["-t", "deep/c"]), "./\ndeep\ndeep/c\n");
+    assert_eq!(git(&sub.join("deep"), &["ls-tree", "--name-only", "HEAD", "../.."]), "../../a\n../\n");
+
+    assert_eq!(names(&["--full-name"]), "sub/b\nsub/deep\n");
+    assert_eq!(names(&["--full-name", "../a"]), "a\n");
+    assert_eq!(names(&["--full-tree"]), "a\nsub\n");
+    assert_eq!(names(&["--full-tree", "sub/b"]), "sub/b\n");
+
+    let line = git(&sub, &["ls-tree", "HEAD", "../a"]);
+    assert!(line.ends_with(" blob 78981922613b2afb6025042ff6bd878ac1994e85\t../a\n"), "{}", line);
+    assert_eq!(git(&sub, &["ls-tree", "-z", "--name-only", "HEAD", "../a"]), "../a\0");
+}